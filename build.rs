@@ -363,6 +363,15 @@ where
 }
 
 fn main() {
+    // exposed to the server so that it can report what tremor version it understands
+    if let Some(tremor_script_version) = get_cargo_lock_version_for_crate(TREMOR_SCRIPT_CRATE_NAME)
+    {
+        println!(
+            "cargo:rustc-env=TREMOR_SCRIPT_VERSION={}",
+            tremor_script_version
+        );
+    }
+
     match env::var("OUT_DIR") {
         Ok(out_dir) => {
             for language_name in LANGUAGES {
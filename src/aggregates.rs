@@ -0,0 +1,103 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// how the aggregate functions of trickle behave across windows, which their docs don't say.
+// with several windows in a select (`from in[one_second, one_minute]`), each window emits
+// into the next one (tilt frames), merging its state into it.
+
+// (function, what it emits and how its state merges into the next window)
+const AGGREGATES: &[(&str, &str)] = &[
+    (
+        "aggr::stats::count",
+        "Emits the number of events in the window. Counts add up when merged into the next \
+         window, so higher-level windows count all the events of the frames they cover.",
+    ),
+    (
+        "aggr::stats::sum",
+        "Emits the sum of the values in the window. Sums add up when merged into the next \
+         window, exactly.",
+    ),
+    (
+        "aggr::stats::min",
+        "Emits the smallest value in the window. Merges exactly: the next window keeps the \
+         smallest of the frames.",
+    ),
+    (
+        "aggr::stats::max",
+        "Emits the largest value in the window. Merges exactly: the next window keeps the \
+         largest of the frames.",
+    ),
+    (
+        "aggr::stats::mean",
+        "Emits the mean of the values in the window. Keeps the sum and count rather than the \
+         mean, so merging into the next window is exact (not a mean of means).",
+    ),
+    (
+        "aggr::stats::var",
+        "Emits the variance of the values in the window. Keeps the running moments, which \
+         combine when merged into the next window (exact up to float rounding).",
+    ),
+    (
+        "aggr::stats::stdev",
+        "Emits the standard deviation of the values in the window. Keeps the running moments, \
+         which combine when merged into the next window (exact up to float rounding).",
+    ),
+    (
+        "aggr::stats::hdr",
+        "Emits percentiles (and min, max, mean, count) from an HDR histogram of the values in \
+         the window. Histograms merge without loss, so percentiles of higher-level windows are \
+         as precise as the ones of the first, at the cost of keeping the histogram per window \
+         and group.",
+    ),
+    (
+        "aggr::stats::dds",
+        "Emits percentiles (and min, max, mean, count) from a DDSketch of the values in the \
+         window. Sketches merge keeping their relative error bound, with less memory than \
+         `hdr` for values spread over many orders of magnitude.",
+    ),
+    (
+        "aggr::win::first",
+        "Emits the first value of the window. When merged into the next window, the first \
+         value of the earliest frame wins.",
+    ),
+    (
+        "aggr::win::last",
+        "Emits the last value of the window. When merged into the next window, the last value \
+         of the latest frame wins.",
+    ),
+    (
+        "aggr::win::collect_flattened",
+        "Emits all the values of the window, as an array. Merging concatenates the frames, so \
+         memory grows with the number of events the (highest-level) window covers.",
+    ),
+    (
+        "aggr::win::collect_nested",
+        "Emits all the values of the window, as an array. Merging keeps one array per frame, \
+         nesting them one level deeper for each window the events went through.",
+    ),
+];
+
+// markdown note on the behavior across windows of the aggregate function, eg:
+// `aggr::stats::hdr`
+pub fn semantics(function: &str) -> Option<String> {
+    AGGREGATES
+        .iter()
+        .find(|(name, _)| *name == function)
+        .map(|(_, note)| {
+            format!(
+                "**In windows:** {} Without a window, it emits for every event.",
+                note
+            )
+        })
+}
@@ -12,211 +12,2705 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{language, lsp_utils};
+use crate::config::{CompletionDetail, Config};
+use crate::parse_stats::{ParseStats, ParseStatsParams};
+use crate::progress::Progress;
+use crate::project::{self, Project};
+#[cfg(feature = "runtime")]
+use crate::runtime;
+use crate::{
+    aggregates, bench, binary, bindings, blocks, cache, cases, check, commands, completion, consts,
+    embedded, extract, function_docs, imports, interpolation, language, lints, literals, lsp_utils,
+    metadata, metrics, modules, params, parse_stats, patch, paths, pipeline, regions, repl,
+    scratch, signatures, symbols, test_files, watchdog, workspace,
+};
 use halfbrown::HashMap;
-use serde_json::Value;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
 use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
-use tower_lsp::jsonrpc::Result;
+use tower_lsp::jsonrpc::{Error, Result};
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer};
 
+// version of the tremor-script crate the server is built with
+pub const TREMOR_SCRIPT_VERSION: &str = match option_env!("TREMOR_SCRIPT_VERSION") {
+    Some(version) => version,
+    None => "unknown",
+};
+
 // stores the latest state of the document as it changes (on edits)
 // TODO can add more fields here based on ast parsing
 #[derive(Debug, Default)]
 struct DocumentState {
     text: String,
+    // as sent by the client, increasing with each change
+    version: Option<i64>,
+    // text of the latest version that parsed, for features to fall back on while the
+    // document is mid-edit (and does not even tokenize)
+    parsed_text: Option<String>,
+    // of the text, to tell no-op changes apart
+    content_hash: u64,
+    // content hash and settings generation that the published diagnostics were computed for
+    published: Option<(u64, u64)>,
 }
 
 // mapping of file uri to its server document state
 type State = HashMap<Url, DocumentState>;
 
+// a file of the workspace, as it is checked across the workspace
+struct WorkspaceFile {
+    uri: Url,
+    text: String,
+    version: Option<i64>,
+}
+
+// worker threads for computing the diagnostics of many documents at once (eg: the workspace)
+const DIAGNOSTICS_WORKERS: usize = 4;
+
+// files searched for workspace symbols between checks for the request being cancelled
+const SYMBOL_SEARCH_CHUNK: usize = 100;
+
 pub struct Backend {
     client: Client,
     language: Box<dyn language::Language>,
+    // for completing within the scripts embedded in queries
+    embedded_language: Option<Box<dyn language::Language>>,
     state: Mutex<State>,
+    workspace: Mutex<workspace::Workspace>,
+    // client accepts server-initiated progress reporting
+    supports_progress: AtomicBool,
+    // client renders markdown in hovers and in completion item docs
+    supports_markdown_hover: AtomicBool,
+    supports_markdown_documentation: AtomicBool,
+    // client expands snippets (eg: argument placeholders) in completion items
+    supports_snippets: AtomicBool,
+    // client takes document symbols as a tree, rather than a flat list
+    supports_hierarchical_symbols: AtomicBool,
+    // client applies workspace edits that create files (eg: for moving into a new module)
+    supports_create_files: AtomicBool,
+    config: RwLock<Config>,
+    // bumped on settings changes, since these can change the diagnostics
+    config_generation: AtomicU64,
+    // bumped when indexing starts, so that an indexing still going on (eg. of the previous
+    // workspace folders) stops
+    index_generation: AtomicU64,
+    // one per workspace root
+    projects: RwLock<Vec<Project>>,
+    repl: Mutex<repl::Session>,
+    metrics: metrics::Metrics,
+    // set once shutdown was requested, which the exit code depends on as well
+    shut_down: Arc<AtomicBool>,
+    // shared while diagnostics are computed and published, for shutdown to wait for them
+    diagnostics_gate: tokio::sync::RwLock<()>,
 }
 
 impl Backend {
-    pub fn new(client: Client, language: Box<dyn language::Language>) -> Self {
+    pub fn new(
+        client: Client,
+        language: Box<dyn language::Language>,
+        shut_down: Arc<AtomicBool>,
+    ) -> Self {
         Self {
             client,
             language,
+            embedded_language: language::lookup(language::EMBEDDED_LANGUAGE_NAME),
             state: Mutex::new(State::new()),
+            workspace: Mutex::new(workspace::Workspace::default()),
+            supports_progress: AtomicBool::new(false),
+            supports_markdown_hover: AtomicBool::new(false),
+            supports_markdown_documentation: AtomicBool::new(false),
+            supports_snippets: AtomicBool::new(false),
+            supports_hierarchical_symbols: AtomicBool::new(false),
+            supports_create_files: AtomicBool::new(false),
+            config: RwLock::new(Config::default()),
+            config_generation: AtomicU64::new(0),
+            index_generation: AtomicU64::new(0),
+            projects: RwLock::new(vec![]),
+            repl: Mutex::new(repl::Session::default()),
+            metrics: metrics::Metrics::new(),
+            shut_down,
+            diagnostics_gate: tokio::sync::RwLock::new(()),
         }
     }
 
-    async fn update(&self, uri: Url, text: &str) {
-        // TODO implement update as well. also remove unwraps
-        self.state.lock().await.insert(
-            uri,
-            DocumentState {
-                text: text.to_string(),
+    async fn get_server_status(&self) -> Value {
+        let open_documents = self.state.lock().await.len();
+        let workspace = self.workspace.lock().await;
+
+        json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "tremorScriptVersion": TREMOR_SCRIPT_VERSION,
+            "language": self.language.name(),
+            "tremorPath": std::env::var("TREMOR_PATH").unwrap_or_default(),
+            "workspaceRoots": workspace.roots,
+            "indexedFiles": workspace.files.len(),
+            "indexedSymbols": workspace.files.values().map(|f| f.symbols.len()).sum::<usize>(),
+            "cache": {
+                "files": workspace.cache.file_count(),
+                "hits": workspace.cache_hits,
             },
+            "openDocuments": open_documents,
+            "projects": self.projects().iter().map(Project::to_json).collect::<Vec<_>>(),
+        })
+    }
+
+    // request and diagnostics durations, with the index cache use, as json or in the
+    // prometheus text format
+    async fn get_metrics(&self, prometheus: bool) -> Value {
+        let (hits, misses) = {
+            let workspace = self.workspace.lock().await;
+            (workspace.cache_hits as u64, workspace.cache_misses as u64)
+        };
+        if prometheus {
+            return json!(self.metrics.to_prometheus(&[
+                (
+                    "index_cache_hits_total",
+                    "Files indexed from the cache",
+                    hits
+                ),
+                (
+                    "index_cache_misses_total",
+                    "Files indexed from their content",
+                    misses
+                ),
+            ]));
+        }
+        let mut metrics = self.metrics.to_json();
+        metrics["indexCache"] = json!({
+            "hits": hits,
+            "misses": misses,
+            "hitRate": if hits + misses > 0 { hits as f64 / (hits + misses) as f64 } else { 0.0 },
+        });
+        metrics
+    }
+
+    async fn index_workspace(&self) {
+        let generation = self.index_generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let roots = {
+            let mut workspace = self.workspace.lock().await;
+            workspace.load_cache();
+            workspace.roots.clone()
+        };
+        let files: Vec<_> = roots
+            .iter()
+            .flat_map(|root| self.find_files(root))
+            .collect();
+
+        let progress = if self.supports_progress.load(Ordering::Relaxed) {
+            Progress::begin(&self.client, "Indexing tremor workspace", files.len()).await
+        } else {
+            None
+        };
+
+        for (i, path) in files.iter().enumerate() {
+            // lets other work (eg. the newer indexing) go on between files
+            tokio::task::yield_now().await;
+            if self.is_shut_down() || self.index_generation.load(Ordering::Relaxed) != generation {
+                if let Some(progress) = progress {
+                    progress.end("Indexing stopped").await;
+                }
+                return;
+            }
+            if !self.is_large_file(path) {
+                self.workspace.lock().await.index_file(path);
+            }
+            if let Some(progress) = &progress {
+                progress.report(i + 1).await;
+            }
+        }
+
+        if let Some(progress) = progress {
+            progress
+                .end(&format!("Indexed {} files", files.len()))
+                .await;
+        }
+
+        self.save_index_cache(&files).await;
+    }
+
+    async fn save_index_cache(&self, files: &[PathBuf]) {
+        let result = self
+            .workspace
+            .lock()
+            .await
+            .save_cache(files)
+            .map_err(|e| e.to_string());
+        if let Err(e) = result {
+            self.client
+                .log_message(
+                    MessageType::Warning,
+                    format!("Failed to save workspace index cache: {}", e),
+                )
+                .await;
+        }
+    }
+
+    fn is_shut_down(&self) -> bool {
+        self.shut_down.load(Ordering::SeqCst)
+    }
+
+    // requests other than exit are invalid after shutdown
+    fn check_running(&self) -> Result<()> {
+        if self.is_shut_down() {
+            Err(Error::invalid_request())
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn update(&self, uri: Url, text: &str, version: Option<i64>) {
+        let content_hash = cache::content_hash(text);
+        // TODO implement update as well. also remove unwraps
+        let unchanged = {
+            let mut state = self.state.lock().await;
+            let previous = state.remove(&uri);
+            let unchanged = previous
+                .as_ref()
+                .map_or(false, |doc| doc.content_hash == content_hash);
+            let (parsed_text, published) =
+                previous.map_or((None, None), |doc| (doc.parsed_text, doc.published));
+            state.insert(
+                uri.clone(),
+                DocumentState {
+                    text: text.to_string(),
+                    version,
+                    parsed_text,
+                    content_hash,
+                    published,
+                },
+            );
+            unchanged
+        };
+
+        // keeps workspace features in line with the unsaved edits
+        if !unchanged && test_files::kind(&uri).is_none() {
+            let mut workspace = self.workspace.lock().await;
+            if self.config_for(&uri).is_large(text.len() as u64) {
+                workspace.files.remove(&uri);
+            } else {
+                workspace.files.insert(uri, workspace::index_text(text));
+            }
+        }
+    }
+
+    // true if the published diagnostics of the document are for its current text and the
+    // current settings, so that there is nothing to recompute
+    async fn is_published(&self, uri: &Url) -> bool {
+        let generation = self.config_generation.load(Ordering::Relaxed);
+        match self.state.lock().await.get(uri) {
+            Some(doc) => doc.published == Some((doc.content_hash, generation)),
+            None => false,
+        }
+    }
+
+    async fn set_published(&self, uri: &Url, content_hash: u64, generation: u64) {
+        if let Some(doc) = self.state.lock().await.get_mut(uri) {
+            doc.published = Some((content_hash, generation));
+        }
+    }
+
+    // keeps the text as the last one that parsed, if the diagnostics for it have no parse
+    // errors (the only diagnostics without a lint code)
+    async fn update_parsed_text(&self, uri: &Url, text: &str, diagnostics: &[Diagnostic]) {
+        let has_parse_errors = diagnostics
+            .iter()
+            .any(|d| d.code.is_none() && d.severity == Some(DiagnosticSeverity::Error));
+        if has_parse_errors {
+            return;
+        }
+        if let Some(doc) = self.state.lock().await.get_mut(uri) {
+            if doc.text == text {
+                doc.parsed_text = Some(text.to_string());
+            }
+        }
+    }
+
+    // snapshot of the current settings
+    fn config(&self) -> Config {
+        match self.config.read() {
+            Ok(config) => config.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    fn set_config(&self, config: Config) {
+        match self.config.write() {
+            Ok(mut guard) => *guard = config,
+            Err(poisoned) => *poisoned.into_inner() = config,
+        }
+        self.config_generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // (re)loads the function docs from the docsPath setting, returning the number of functions
+    // documented there
+    fn load_docs(&self) -> std::result::Result<usize, String> {
+        let loaded = function_docs::load(self.config().docs_path.as_deref());
+        // the diagnostics depend on the docs (eg: argument counts), as on the settings
+        self.config_generation.fetch_add(1, Ordering::Relaxed);
+        loaded
+    }
+
+    // loads the function docs if the docsPath setting changed
+    async fn apply_docs_path(&self) {
+        if self.config().docs_path == function_docs::path() {
+            return;
+        }
+        if let Err(e) = self.load_docs() {
+            self.client
+                .log_message(
+                    MessageType::Warning,
+                    format!("Failed to load the function docs: {}", e),
+                )
+                .await;
+        }
+    }
+
+    // snapshot of the projects in the workspace
+    fn projects(&self) -> Vec<Project> {
+        match self.projects.read() {
+            Ok(projects) => projects.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    // settings for the document, taking the ones for its workspace root into account
+    fn config_for(&self, uri: &Url) -> Config {
+        let config = self.config();
+        let path = match paths::to_path(uri) {
+            Some(path) => path,
+            None => return config,
+        };
+        let projects = match self.projects.read() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        match projects
+            .iter()
+            .filter(|project| path.starts_with(&project.root))
+            .max_by_key(|project| project.root.components().count())
+        {
+            Some(project) => config.merged(&project.config),
+            None => config,
+        }
+    }
+
+    // whether the file is too large for full analysis, with the settings for it
+    fn is_large_file(&self, path: &Path) -> bool {
+        match (fs::metadata(path), paths::to_uri(path)) {
+            (Ok(metadata), Some(uri)) => self.config_for(&uri).is_large(metadata.len()),
+            _ => false,
+        }
+    }
+
+    // file extension of the language of the document: the one it's mapped to by the
+    // fileLanguages setting, or else the one of its extension. None for other files.
+    async fn language_of(&self, uri: &Url) -> Option<&'static str> {
+        if scratch::is_scratch(uri) {
+            return scratch::language(uri, None);
+        }
+        let path = paths::to_path(uri)?;
+        let roots = self.workspace.lock().await.roots.clone();
+        workspace::file_language(&path, &roots, &self.config_for(uri))
+    }
+
+    // the indexed files of the server's language, with their text (as it is in the editor
+    // for open documents) and version. unreadable files are left out.
+    async fn workspace_files(&self) -> Vec<WorkspaceFile> {
+        let extension = language::file_extension(self.language.name()).unwrap_or_default();
+        let uris = self
+            .workspace
+            .lock()
+            .await
+            .files_of_language(extension, |uri| self.config_for(uri));
+        let mut files = Vec::new();
+        for uri in uris {
+            // open documents may have unsaved edits
+            let (text, version) = match self.state.lock().await.get(&uri) {
+                Some(doc) => (Some(doc.text.clone()), doc.version),
+                None => (None, None),
+            };
+            let text = text
+                .or_else(|| paths::to_path(&uri).and_then(|path| fs::read_to_string(path).ok()));
+            if let Some(text) = text {
+                files.push(WorkspaceFile { uri, text, version });
+            }
+            // lets a cancellation of the request take effect
+            tokio::task::yield_now().await;
+        }
+        files
+    }
+
+    // whether the document is for a server of the other tremor language, so that this one
+    // leaves it alone
+    async fn is_other_language(&self, uri: &Url) -> bool {
+        match self.language_of(uri).await {
+            Some(extension) => language::file_extension(self.language.name()) != Some(extension),
+            None => false,
+        }
+    }
+
+    // tremor files under the workspace root, by their extension or the fileLanguages setting
+    fn find_files(&self, root: &Path) -> Vec<PathBuf> {
+        let config = paths::to_uri(root)
+            .map(|uri| self.config_for(&uri))
+            .unwrap_or_default();
+        workspace::find_files_with(root, &self.exclusions(root), |path| {
+            workspace::is_tremor_file(path)
+                || config
+                    .language_for(path.strip_prefix(root).unwrap_or(path))
+                    .is_some()
+        })
+    }
+
+    // paths not to index under the workspace root, with the settings for it
+    fn exclusions(&self, root: &Path) -> Vec<String> {
+        let exclude = paths::to_uri(root)
+            .map(|uri| self.config_for(&uri).index_exclude)
+            .unwrap_or_default();
+        workspace::exclusions(root, &exclude)
+    }
+
+    // (re)reads the project manifests in the workspace roots, updating the module search paths
+    async fn load_projects(&self) {
+        let roots = self.workspace.lock().await.roots.clone();
+        let config = self.config();
+
+        let mut projects = Vec::new();
+        for root in roots {
+            match Project::load(&root) {
+                Ok(project) => projects.push(project),
+                Err(e) => {
+                    self.client
+                        .log_message(MessageType::Warning, format!("Bad project manifest {}", e))
+                        .await;
+                    projects.push(Project {
+                        root,
+                        ..Project::default()
+                    });
+                }
+            }
+        }
+
+        modules::set_root_paths(
+            projects
+                .iter()
+                .map(|project| {
+                    let mut paths = config.merged(&project.config).path;
+                    // modules are moved into `lib`, so it's a module directory by default
+                    let lib = project.root.join("lib");
+                    if !paths.contains(&lib) {
+                        paths.push(lib);
+                    }
+                    (project.root.clone(), paths)
+                })
+                .collect(),
+        );
+        match self.projects.write() {
+            Ok(mut guard) => *guard = projects,
+            Err(poisoned) => *poisoned.into_inner() = projects,
+        }
+        self.config_generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // runs the work, reporting a panic in it (eg: from a parser bug) to the client and
+    // returning None instead, so that one bad document does not take down the whole server
+    async fn catch_panic<T>(&self, activity: &str, work: impl FnOnce() -> T) -> Option<T> {
+        match panic::catch_unwind(AssertUnwindSafe(work)) {
+            Ok(result) => Some(result),
+            Err(payload) => {
+                let reason = payload
+                    .downcast_ref::<&str>()
+                    .map(|s| (*s).to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown cause".to_string());
+                let message = format!("Internal error while {}: {}", activity, reason);
+                self.client
+                    .log_message(MessageType::Error, message.clone())
+                    .await;
+                self.client.show_message(MessageType::Error, message).await;
+                None
+            }
+        }
+    }
+
+    // with the parseStats setting on, tells the client how long the diagnostics just published
+    // took to compute, and how long parsing alone takes
+    async fn send_parse_stats(
+        &self,
+        uri: &Url,
+        text: &str,
+        version: Option<i64>,
+        diagnostics_took: Duration,
+    ) {
+        if !self.config_for(uri).parse_stats() {
+            return;
+        }
+        let published_at = parse_stats::now();
+        let stats = self
+            .catch_panic("computing parse stats", || {
+                let start = Instant::now();
+                let ast = self.language.ast(uri, text);
+                let parse_ms = ast.as_ref().map(|_| parse_stats::millis(start.elapsed()));
+                ParseStatsParams {
+                    uri: uri.clone(),
+                    version,
+                    diagnostics_ms: parse_stats::millis(diagnostics_took),
+                    parse_ms,
+                    ast_nodes: ast
+                        .and_then(Result::ok)
+                        .map(|ast| parse_stats::node_count(&ast)),
+                    tokens: self.language.tokenize(uri, text).map(|tokens| tokens.len()),
+                    published_at,
+                }
+            })
+            .await;
+        if let Some(stats) = stats {
+            self.client
+                .send_custom_notification::<ParseStats>(stats)
+                .await;
+        }
+    }
+
+    // re-runs diagnostics for all the open documents (eg: after settings change), in parallel
+    async fn publish_all_diagnostics(&self) {
+        let _gate = self.diagnostics_gate.read().await;
+        if self.is_shut_down() {
+            return;
+        }
+        let documents: Vec<(Url, String, Option<i64>)> = self
+            .state
+            .lock()
+            .await
+            .iter()
+            .map(|(uri, doc)| (uri.clone(), doc.text.clone(), doc.version))
+            .collect();
+        let jobs = documents
+            .iter()
+            .map(|(uri, text, _)| check::Job {
+                uri: uri.clone(),
+                text: text.clone(),
+                config: self.config_for(uri),
+            })
+            .collect();
+
+        let generation = self.config_generation.load(Ordering::Relaxed);
+        let mut results = check::spawn_diagnostics(self.language.name(), jobs, DIAGNOSTICS_WORKERS);
+        while let Some((i, diagnostics)) = results.recv().await {
+            // newer settings come with a run of their own, so the rest of this one is dropped
+            // (stopping the workers)
+            if self.is_shut_down() || self.config_generation.load(Ordering::Relaxed) != generation {
+                return;
+            }
+            let (uri, text, version) = &documents[i];
+            // edited since, in which case the edit publishes them
+            if !self.is_latest(uri, *version).await {
+                continue;
+            }
+            let diagnostics = self.diagnostics_or_report(uri, diagnostics).await;
+            self.set_published(uri, cache::content_hash(text), generation)
+                .await;
+            self.client
+                .publish_diagnostics(uri.clone(), diagnostics, *version)
+                .await;
+        }
+    }
+
+    // diagnostics computed on a worker thread, reporting the ones that failed
+    async fn diagnostics_or_report(
+        &self,
+        uri: &Url,
+        diagnostics: Option<Vec<Diagnostic>>,
+    ) -> Vec<Diagnostic> {
+        match diagnostics {
+            Some(diagnostics) => diagnostics,
+            None => {
+                let message = format!("Internal error while computing diagnostics of {}", uri);
+                self.client
+                    .log_message(MessageType::Error, message.clone())
+                    .await;
+                self.client.show_message(MessageType::Error, message).await;
+                vec![]
+            }
+        }
+    }
+
+    // counts of the definitions and diagnostics of all the indexed files, without publishing
+    // the diagnostics
+    async fn workspace_stats(&self) -> Value {
+        let _gate = self.diagnostics_gate.read().await;
+        let mut files = Vec::new();
+        let mut jobs = Vec::new();
+        for WorkspaceFile { uri, text, .. } in self.workspace_files().await {
+            let config = self.config_for(&uri);
+            if config.is_large(text.len() as u64) {
+                files.push(json!({ "uri": uri, "parse": "skipped" }));
+                continue;
+            }
+
+            let tokens = self.language.tokenize(&uri, &text).unwrap_or_default();
+            let defines = tokens
+                .iter()
+                .filter(|t| t.value == language::Token::Define)
+                .count();
+            let symbols = symbols::collect(&text, &tokens);
+            let count = |kind: SymbolKind| symbols.iter().filter(|s| s.kind == kind).count();
+            files.push(json!({
+                "uri": uri,
+                "defines": defines,
+                "scripts": count(SymbolKind::Class),
+                "windows": count(SymbolKind::Struct),
+                "operators": count(SymbolKind::Operator),
+                "streams": count(SymbolKind::Event),
+                "functions": count(SymbolKind::Function),
+            }));
+            jobs.push((files.len() - 1, check::Job { uri, config, text }));
+            tokio::task::yield_now().await;
+        }
+
+        let (indices, jobs): (Vec<usize>, Vec<check::Job>) = jobs.into_iter().unzip();
+        let mut results = check::spawn_diagnostics(self.language.name(), jobs, DIAGNOSTICS_WORKERS);
+        while let Some((i, diagnostics)) = results.recv().await {
+            let file = &mut files[indices[i]];
+            let diagnostics = match diagnostics {
+                Some(diagnostics) => diagnostics,
+                None => {
+                    file["parse"] = json!("failed");
+                    continue;
+                }
+            };
+            let count = |severity: DiagnosticSeverity| {
+                diagnostics
+                    .iter()
+                    .filter(|d| d.severity == Some(severity))
+                    .count()
+            };
+            let mut lints: BTreeMap<String, usize> = BTreeMap::new();
+            for diagnostic in &diagnostics {
+                if let Some(NumberOrString::String(code)) = &diagnostic.code {
+                    *lints.entry(code.clone()).or_default() += 1;
+                }
+            }
+            // the parser's errors are the ones without a lint code
+            let parsed = !diagnostics
+                .iter()
+                .any(|d| d.code.is_none() && d.severity == Some(DiagnosticSeverity::Error));
+            file["parse"] = json!(if parsed { "ok" } else { "failed" });
+            file["errors"] = json!(count(DiagnosticSeverity::Error));
+            file["warnings"] = json!(count(DiagnosticSeverity::Warning));
+            file["lints"] = json!(lints);
+        }
+
+        let mut totals: BTreeMap<String, Value> = BTreeMap::new();
+        totals.insert("files".to_string(), json!(files.len()));
+        for file in &files {
+            if let Some(status) = file["parse"].as_str() {
+                let key = format!("parse_{}", status);
+                let n = totals.get(&key).and_then(Value::as_u64).unwrap_or(0);
+                totals.insert(key, json!(n + 1));
+            }
+            for key in &[
+                "defines",
+                "scripts",
+                "windows",
+                "operators",
+                "streams",
+                "functions",
+                "errors",
+                "warnings",
+            ] {
+                let n = totals.get(*key).and_then(Value::as_u64).unwrap_or(0);
+                totals.insert(key.to_string(), json!(n + file[*key].as_u64().unwrap_or(0)));
+            }
+        }
+        json!({ "files": files, "totals": totals })
+    }
+
+    // publishes the diagnostics of all the indexed files, returning counts of what was found
+    async fn validate_workspace(&self) -> Value {
+        let _gate = self.diagnostics_gate.read().await;
+        let mut versions = Vec::new();
+        let mut jobs = Vec::new();
+        for WorkspaceFile { uri, text, version } in self.workspace_files().await {
+            versions.push(version);
+            jobs.push(check::Job {
+                config: self.config_for(&uri),
+                uri,
+                text,
+            });
+        }
+        let checked: Vec<Url> = jobs.iter().map(|job| job.uri.clone()).collect();
+
+        let progress = if self.supports_progress.load(Ordering::Relaxed) {
+            Progress::begin(&self.client, "Validating tremor workspace", checked.len()).await
+        } else {
+            None
+        };
+
+        let (mut errors, mut warnings) = (0, 0);
+        let mut failed = Vec::new();
+        let mut done = 0;
+        let mut results = check::spawn_diagnostics(self.language.name(), jobs, DIAGNOSTICS_WORKERS);
+        while let Some((i, diagnostics)) = results.recv().await {
+            let uri = &checked[i];
+            let diagnostics = self.diagnostics_or_report(uri, diagnostics).await;
+            let count = |severity: DiagnosticSeverity| {
+                diagnostics
+                    .iter()
+                    .filter(|d| d.severity == Some(severity))
+                    .count()
+            };
+            let file_errors = count(DiagnosticSeverity::Error);
+            if file_errors > 0 {
+                failed.push(uri.clone());
+            }
+            errors += file_errors;
+            warnings += count(DiagnosticSeverity::Warning);
+            self.client
+                .publish_diagnostics(uri.clone(), diagnostics, versions[i])
+                .await;
+
+            done += 1;
+            if let Some(progress) = &progress {
+                progress.report(done).await;
+            }
+        }
+        // in the order of the files, rather than the one they were done in
+        failed.sort();
+
+        if let Some(progress) = progress {
+            progress
+                .end(&format!(
+                    "{} errors, {} warnings in {} files",
+                    errors,
+                    warnings,
+                    checked.len()
+                ))
+                .await;
+        }
+
+        json!({
+            "files": checked.len(),
+            "errors": errors,
+            "warnings": warnings,
+            "failed": failed,
+        })
+    }
+
+    // snapshot of the document text, so that the state is not kept locked while we work on it
+    async fn get_text(&self, uri: &Url) -> Option<String> {
+        self.state.lock().await.get(uri).map(|doc| doc.text.clone())
+    }
+
+    // the document text if it tokenizes, or else the last version of it that parsed
+    async fn get_tokenizable_text(&self, uri: &Url) -> Option<String> {
+        let (text, parsed_text) = match self.state.lock().await.get(uri) {
+            Some(doc) => (doc.text.clone(), doc.parsed_text.clone()),
+            None => return None,
+        };
+        if self.language.tokenize(uri, &text).is_some() {
+            return Some(text);
+        }
+        parsed_text
+    }
+
+    // false if the document has seen newer edits since the given version, in which case
+    // results computed for that version are stale and should be dropped
+    async fn is_latest(&self, uri: &Url, version: Option<i64>) -> bool {
+        match self.state.lock().await.get(uri) {
+            Some(doc) => doc.version == version,
+            None => false,
+        }
+    }
+
+    // edits to the use statements across the workspace, for the (old, new) module file or
+    // directory paths. imports keep the name they had, so the rest of the documents
+    // stay as is.
+    async fn get_module_rename_edit(&self, renames: &[(PathBuf, PathBuf)]) -> WorkspaceEdit {
+        let renamed = |module_file: &PathBuf| {
+            renames.iter().find_map(|(old, new)| {
+                if module_file == old {
+                    Some(new.clone())
+                } else {
+                    module_file
+                        .strip_prefix(old)
+                        .ok()
+                        .map(|relative| new.join(relative))
+                }
+            })
+        };
+
+        let uris: Vec<Url> = self.workspace.lock().await.files.keys().cloned().collect();
+        let mut changes = std::collections::HashMap::new();
+        for uri in uris {
+            // open documents may have unsaved edits
+            let text = match self
+                .get_text(&uri)
+                .await
+                .or_else(|| paths::to_path(&uri).and_then(|path| fs::read_to_string(path).ok()))
+            {
+                Some(text) => text,
+                None => continue,
+            };
+            let tokens = match self.language.tokenize(&uri, &text) {
+                Some(tokens) => tokens,
+                None => continue,
+            };
+
+            let edits: Vec<TextEdit> = imports::parse(&tokens)
+                .into_iter()
+                .filter_map(|import| {
+                    let new_file = renamed(&modules::resolve(&uri, &import.module_path)?)?;
+                    let module_path = modules::module_path(&uri, &new_file)?;
+                    if module_path == import.module_path {
+                        return None;
+                    }
+                    let alias = import.alias.clone().or_else(|| {
+                        Some(import.name().to_string())
+                            .filter(|name| module_path.last() != Some(name))
+                    });
+                    let statement = imports::Import {
+                        module_path,
+                        alias,
+                        range: import.range,
+                    }
+                    .to_statement();
+                    Some(TextEdit::new(import.range, statement))
+                })
+                .collect();
+            if !edits.is_empty() {
+                changes.insert(uri, edits);
+            }
+        }
+
+        WorkspaceEdit::new(changes)
+    }
+
+    // runs the events (json, one per entry) through the document, logging each output
+    async fn run_pipeline(
+        &self,
+        uri: &Url,
+        events: &[String],
+    ) -> std::result::Result<Value, String> {
+        let text = match self.get_text(uri).await {
+            Some(text) => text,
+            None => paths::to_path(uri)
+                .and_then(|path| fs::read_to_string(path).ok())
+                .ok_or("Failed to read the document")?,
+        };
+
+        let outputs = self
+            .catch_panic("running the pipeline", || {
+                self.language.run_pipeline(uri, &text, events)
+            })
+            .await
+            .ok_or("Internal error")?
+            .ok_or("Running is not supported for this language (trickle queries need the runtime feature)")??;
+
+        let (mut emitted, mut dropped, mut failed) = (0, 0, 0);
+        for (i, output) in outputs.iter().enumerate() {
+            let message = match output {
+                Ok(language::RunOutput {
+                    value: Some(value),
+                    port,
+                    ..
+                }) => {
+                    emitted += 1;
+                    format!("{}: {}", port.as_deref().unwrap_or("out"), value)
+                }
+                Ok(_) => {
+                    dropped += 1;
+                    "dropped".to_string()
+                }
+                Err(e) => {
+                    failed += 1;
+                    format!("error: {}", e)
+                }
+            };
+            self.client
+                .log_message(MessageType::Log, format!("[event {}] {}", i + 1, message))
+                .await;
+        }
+
+        Ok(json!({
+            "events": outputs.len(),
+            "emitted": emitted,
+            "dropped": dropped,
+            "failed": failed,
+        }))
+    }
+
+    // creates the deployment file for the pipeline next to it, returning its uri and content
+    async fn scaffold_deployment(
+        &self,
+        uri: &Url,
+        mut inputs: Vec<String>,
+        mut outputs: Vec<String>,
+    ) -> std::result::Result<Value, String> {
+        // pipelines that have none yet still get the usual ports
+        if inputs.is_empty() {
+            inputs.push("in".to_string());
+        }
+        if outputs.is_empty() {
+            outputs.push("out".to_string());
+        }
+        // pipelines are named after their file
+        let path = paths::to_path(uri);
+        let name = path
+            .as_deref()
+            .unwrap_or_else(|| Path::new(uri.path()))
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .filter(|stem| !stem.is_empty())
+            .unwrap_or("main");
+        let content = bindings::scaffold(name, &inputs, &outputs);
+
+        let file = match path.and_then(|path| Some(path.parent()?.join(format!("{}.yaml", name)))) {
+            Some(file) => file,
+            None => return Ok(json!({ "uri": null, "content": content })),
+        };
+        // never overwrites a deployment
+        if file.exists() {
+            return Err(format!("{} already exists", file.display()));
+        }
+        let file_uri = paths::to_uri(&file).ok_or("Bad deployment file path")?;
+        let edit = WorkspaceEdit {
+            changes: None,
+            document_changes: Some(DocumentChanges::Operations(vec![
+                DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+                    uri: file_uri.clone(),
+                    options: None,
+                })),
+                DocumentChangeOperation::Edit(TextDocumentEdit {
+                    text_document: VersionedTextDocumentIdentifier {
+                        uri: file_uri.clone(),
+                        version: None,
+                    },
+                    edits: vec![TextEdit::new(Range::default(), content.clone())],
+                }),
+            ])),
+        };
+        match self.client.apply_edit(edit).await {
+            Ok(response) if response.applied => Ok(json!({ "uri": file_uri, "content": content })),
+            Ok(_) => Err("The deployment file was not created".to_string()),
+            Err(e) => Err(format!("Failed to create the deployment file: {}", e)),
+        }
+    }
+
+    // diagnostics for the document in the shape of a (pull model) document diagnostic
+    // report, which is just a marker if they did not change since the previous result
+    async fn get_diagnostic_report(
+        &self,
+        uri: &Url,
+        text: &str,
+        previous_result_id: Option<&str>,
+    ) -> Value {
+        let result_id = format!(
+            "{:x}-{}",
+            cache::content_hash(text),
+            self.config_generation.load(Ordering::Relaxed)
         );
+        if previous_result_id == Some(result_id.as_str()) {
+            return json!({ "kind": "unchanged", "resultId": result_id });
+        }
+
+        let diagnostics = self
+            .catch_panic("computing diagnostics", || self.get_diagnostics(uri, text))
+            .await
+            .unwrap_or_default();
+        json!({ "kind": "full", "resultId": result_id, "items": diagnostics })
+    }
+
+    // LSP helper functions
+
+    fn get_diagnostics(&self, uri: &Url, text: &str) -> Vec<Diagnostic> {
+        file_dbg("get_diagnostics", text);
+        let start = Instant::now();
+        let diagnostics =
+            check::diagnostics(self.language.as_ref(), uri, text, &self.config_for(uri));
+        self.metrics.diagnostics_took(start.elapsed());
+        diagnostics
     }
 
-    // LSP helper functions
+    fn get_completions(
+        &self,
+        uri: &Url,
+        text: &str,
+        parsed_text: Option<&str>,
+        position: Position,
+    ) -> Vec<CompletionItem> {
+        let pre_position = Position {
+            line: position.line,
+            character: position.character.saturating_sub(1),
+        };
+
+        if let Some(kind) = test_files::kind(uri) {
+            return test_files::completions(kind, text, position);
+        }
+
+        if let Some(tokens) = self.language.tokenize(uri, text) {
+            let token = lsp_utils::get_token(text, &tokens, pre_position);
+            let context = completion::Context::new(text, &tokens, position);
+            // tremor-script ones within the scripts embedded in queries
+            let language: &dyn language::Language = match &self.embedded_language {
+                Some(embedded) if embedded::contains(text, &tokens, position) => embedded.as_ref(),
+                _ => self.language.as_ref(),
+            };
+
+            if let Some((slot, prefix)) = metadata::slot(text, &tokens, position) {
+                return completion::rank(completion::metadata_candidates(&slot), &prefix);
+            }
+
+            if let Some(slot) = context.select_slot {
+                let prefix = token.as_ref().map_or("", |t| t.value.as_str());
+                return completion::rank(
+                    completion::select_candidates(text, &tokens, slot),
+                    prefix,
+                );
+            }
+
+            if let Some(slot) = patch::slot(text, &tokens, position) {
+                let prefix = token.as_ref().map_or("", |t| t.value.as_str());
+                return completion::rank(completion::patch_candidates(&tokens, &slot), prefix);
+            }
+
+            if let Some(definition) = params::slot(text, &tokens, position) {
+                let prefix = token.as_ref().map_or("", |t| t.value.as_str());
+                return completion::rank(completion::param_candidates(&definition), prefix);
+            }
+
+            let mut keywords = completion::keyword_candidates(language, text, &tokens, position);
+            if let Some(slot) = cases::slot(text, &tokens, position) {
+                let values = self.get_case_values(uri, text, &tokens, &slot);
+                keywords.extend(completion::case_candidates(&slot, &values));
+            }
+
+            if let Some(token) = token {
+                file_dbg("get_completions_token", &token.value);
+                let module_parts: Vec<&str> = token.value.rsplitn(2, "::").collect();
+
+                if let Some(written_name) = module_parts.get(1) {
+                    file_dbg("get_completions_module_name", written_name);
+                    // builtin modules can be used under an alias
+                    let builtin_module = imports::unalias(
+                        &imports::parse(&tokens),
+                        written_name,
+                        &language.modules(uri),
+                    );
+                    let module_name = &builtin_module.as_str();
+                    // whatever has been typed after the `::` so far
+                    let prefix = module_parts
+                        .first()
+                        .filter(|s| s.chars().all(|c| c.is_alphanumeric() || c == '_'))
+                        .unwrap_or(&"");
+
+                    let mut candidates: Vec<completion::Candidate> = language
+                        .functions(uri, module_name)
+                        .iter()
+                        .map(|function_name| {
+                            let mut detail = None;
+                            let mut documentation = None;
+                            let mut insert_text = None;
+                            let mut score = 0;
+                            if let Some(function_doc) = language
+                                .function_doc(uri, &format!("{}::{}", module_name, function_name))
+                            {
+                                file_dbg("get_completions_function_doc", &function_doc.description);
+                                detail = Some(function_doc.signature.to_string());
+                                documentation = Some(Documentation::MarkupContent(MarkupContent {
+                                    kind: MarkupKind::Markdown,
+                                    value: function_doc.description.clone(),
+                                }));
+                                let args_snippet = function_doc
+                                    .signature
+                                    .args
+                                    .iter()
+                                    .enumerate()
+                                    // produces snippet text like ${1:arg} (where arg is the placeholder text)
+                                    // https://microsoft.github.io/language-server-protocol/specifications/specification-3-14/#snippet-syntax
+                                    .map(|(i, arg)| format!("${{{}:{}}}", i + 1, arg))
+                                    .collect::<Vec<String>>()
+                                    .join(", ");
+                                insert_text = Some(format!("{}({})", function_name, args_snippet));
+                                if context.after_string && function_doc.signature.result == "string"
+                                {
+                                    score += 20;
+                                }
+                            };
+                            if context.in_select && language.is_aggregate_module(module_name) {
+                                score += 20;
+                            }
+                            if context.after_string && *module_name == "string" {
+                                score += 20;
+                            }
+                            completion::Candidate {
+                                item: CompletionItem {
+                                    label: function_name.to_string(),
+                                    kind: Some(CompletionItemKind::Function),
+                                    detail,
+                                    documentation,
+                                    insert_text,
+                                    insert_text_format: Some(InsertTextFormat::Snippet),
+                                    ..CompletionItem::default()
+                                },
+                                score,
+                            }
+                        })
+                        .collect();
+
+                    // functions of a module file the document uses
+                    let path: Vec<String> = written_name.split("::").map(String::from).collect();
+                    if let Some((module_uri, module_text)) =
+                        self.get_imported_module(uri, &tokens, &path)
+                    {
+                        if let Some(module_tokens) =
+                            self.language.tokenize(&module_uri, &module_text)
+                        {
+                            candidates.extend(completion::function_candidates(
+                                &module_text,
+                                &module_tokens,
+                            ));
+                        }
+                    }
+
+                    return completion::rank(candidates, prefix);
+                }
+
+                // no module yet, so it can be the start of one (or a function of the document),
+                // with the modules the document uses under the name it uses them with
+                let document_imports = imports::parse(&tokens);
+                let mut candidates = completion::import_candidates(&document_imports);
+                candidates.extend(
+                    completion::module_candidates(language, uri)
+                        .into_iter()
+                        .filter(|c| !document_imports.iter().any(|i| i.name() == c.item.label)),
+                );
+                candidates.extend(completion::imported_function_candidates(
+                    language,
+                    uri,
+                    &document_imports,
+                ));
+                candidates.extend(completion::function_candidates(text, &tokens));
+                candidates.extend(keywords);
+                return completion::rank(candidates, &token.value);
+            }
+
+            return completion::rank(keywords, "");
+        }
+
+        match parsed_text {
+            Some(parsed_text) => self.get_fallback_completions(uri, text, parsed_text, position),
+            None => vec![],
+        }
+    }
+
+    // values of the expression matched at the slot, from an enum-like const of the document or
+    // the event schema of the project
+    fn get_case_values(
+        &self,
+        uri: &Url,
+        text: &str,
+        tokens: &[language::TokenSpan],
+        slot: &cases::Slot,
+    ) -> Vec<String> {
+        let values = cases::const_values(text, tokens, slot.field());
+        if !values.is_empty() {
+            return values;
+        }
+        let (event_path, path) = match (slot.event_path(), paths::to_path(uri)) {
+            (Some(event_path), Some(path)) => (event_path, path),
+            _ => return vec![],
+        };
+        self.projects()
+            .iter()
+            .filter(|project| path.starts_with(&project.root))
+            .max_by_key(|project| project.root.components().count())
+            .and_then(|project| {
+                let (_, schema) = project.schemas.iter().find(|(name, _)| name == "event")?;
+                let schema = fs::read_to_string(schema).ok()?;
+                serde_json::from_str::<Value>(&schema).ok()
+            })
+            .map(|schema| cases::schema_values(&schema, &event_path))
+            .unwrap_or_default()
+    }
+
+    // completions for documents that don't tokenize as they are (eg. half way through typing
+    // a string), with the names coming from the last version that parsed
+    fn get_fallback_completions(
+        &self,
+        uri: &Url,
+        text: &str,
+        parsed_text: &str,
+        position: Position,
+    ) -> Vec<CompletionItem> {
+        let parsed_tokens = match self.language.tokenize(uri, parsed_text) {
+            Some(tokens) => tokens,
+            None => return vec![],
+        };
+        let line = text.lines().nth(position.line as usize).unwrap_or_default();
+        let before: Vec<char> = line
+            .chars()
+            .take(lsp_utils::to_char_column(line, position.character))
+            .collect();
+        // the (possibly module qualified) name being typed
+        let typed: String = before
+            .iter()
+            .rev()
+            .take_while(|c| c.is_alphanumeric() || **c == '_' || **c == ':')
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect();
+
+        let module_parts: Vec<&str> = typed.rsplitn(2, "::").collect();
+        match (module_parts.first(), module_parts.get(1)) {
+            (Some(prefix), Some(module_name)) => {
+                let builtin_module = imports::unalias(
+                    &imports::parse(&parsed_tokens),
+                    module_name,
+                    &self.language.modules(uri),
+                );
+                let mut candidates: Vec<completion::Candidate> = self
+                    .language
+                    .functions(uri, &builtin_module)
+                    .into_iter()
+                    .map(|function_name| {
+                        let detail = self
+                            .language
+                            .function_doc(uri, &format!("{}::{}", builtin_module, function_name))
+                            .map(|doc| doc.signature.to_string());
+                        completion::Candidate {
+                            item: CompletionItem {
+                                label: function_name,
+                                kind: Some(CompletionItemKind::Function),
+                                detail,
+                                ..CompletionItem::default()
+                            },
+                            score: 0,
+                        }
+                    })
+                    .collect();
+                let path: Vec<String> = module_name.split("::").map(String::from).collect();
+                if let Some((module_uri, module_text)) =
+                    self.get_imported_module(uri, &parsed_tokens, &path)
+                {
+                    if let Some(module_tokens) = self.language.tokenize(&module_uri, &module_text) {
+                        candidates.extend(completion::function_candidates(
+                            &module_text,
+                            &module_tokens,
+                        ));
+                    }
+                }
+                completion::rank(candidates, prefix)
+            }
+            (prefix, _) => {
+                let mut candidates = completion::module_candidates(self.language.as_ref(), uri);
+                candidates.extend(completion::function_candidates(parsed_text, &parsed_tokens));
+                completion::rank(candidates, prefix.unwrap_or(&""))
+            }
+        }
+    }
+
+    fn get_hover_content(&self, uri: &Url, text: &str, position: Position) -> Option<Hover> {
+        if test_files::kind(uri).is_some() {
+            return None;
+        }
+        // TODO merge the repeated tokenize operation with get_completions()?
+        if let Some(tokens) = self.language.tokenize(uri, text) {
+            if let Some((value, range)) = metadata::hover(text, &tokens, position) {
+                return Some(Hover {
+                    contents: HoverContents::Markup(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value,
+                    }),
+                    range: Some(range),
+                });
+            }
+            if let Some(token) = lsp_utils::get_token(text, &tokens, position) {
+                file_dbg("get_hover_content_token", &token.value);
+                let function_name = self.unalias(uri, &tokens, &token.value);
+                if let Some(function_doc) = self.language.function_doc(uri, &function_name) {
+                    file_dbg("get_hover_content_function_doc", &function_doc.description);
+                    let mut value = function_doc.to_string();
+                    if let Some(note) = aggregates::semantics(&function_name) {
+                        value = format!("{}\n\n{}", value, note);
+                    }
+                    return Some(Hover {
+                        contents: HoverContents::Markup(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value,
+                        }),
+                        range: Some(token.range),
+                    });
+                }
+                let value = self
+                    .get_const_hover_content(uri, text, &tokens, &token)
+                    .or_else(|| self.get_function_hover_content(uri, text, &tokens, &token))
+                    .or_else(|| self.get_window_hover_content(uri, text, &tokens, &token))
+                    .or_else(|| self.get_node_hover_content(uri, text, &tokens, &token))
+                    .or_else(|| self.get_module_hover_content(uri, &tokens, &token));
+                if let Some(value) = value {
+                    return Some(Hover {
+                        contents: HoverContents::Markup(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value,
+                        }),
+                        range: Some(token.range),
+                    });
+                }
+            }
+            if let Some((value, range)) = binary::hover(text, &tokens, position) {
+                return Some(Hover {
+                    contents: HoverContents::Markup(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value,
+                    }),
+                    range: Some(range),
+                });
+            }
+            // literals, where nothing else has anything to say
+            if let Some((value, range)) = literals::hover(text, &tokens, position) {
+                return Some(Hover {
+                    contents: HoverContents::Markup(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value,
+                    }),
+                    range: Some(range),
+                });
+            }
+        }
+        None
+    }
+
+    // the builtin function the (module qualified) name refers to, through the use statements of
+    // the document, eg: `string::lowercase` for `s::lowercase` with `use std::string as s;`
+    fn unalias(&self, uri: &Url, tokens: &[language::TokenSpan], name: &str) -> String {
+        match name.rsplitn(2, "::").collect::<Vec<_>>().as_slice() {
+            [function_name, module_path] => format!(
+                "{}::{}",
+                imports::unalias(
+                    &imports::parse(tokens),
+                    module_path,
+                    &self.language.modules(uri)
+                ),
+                function_name
+            ),
+            _ => name.to_string(),
+        }
+    }
+
+    // shows the (folded) value of a constant, along with where it was defined
+    fn get_const_hover_content(
+        &self,
+        uri: &Url,
+        text: &str,
+        tokens: &[language::TokenSpan],
+        token: &lsp_utils::PathToken,
+    ) -> Option<String> {
+        let document_imports = imports::parse(tokens);
+        let document_consts = consts::collect(text, tokens);
+
+        let mut path: Vec<String> = token.value.split("::").map(String::from).collect();
+        let name = path.pop()?;
+
+        let (definition_uri, definition) = if path.is_empty() {
+            let definition = document_consts.iter().find(|c| c.name == name)?.clone();
+            (uri.clone(), definition)
+        } else {
+            // `alias::NAME`, where the alias comes from a use statement
+            let import = document_imports
+                .iter()
+                .find(|import| Some(import.name()) == path.first().map(String::as_str))?;
+            let mut module_path = import.module_path.clone();
+            module_path.extend(path.into_iter().skip(1));
+
+            let module_file = modules::resolve(uri, &module_path)?;
+            let module_uri = paths::to_uri(&module_file)?;
+            let module_text = fs::read_to_string(&module_file).ok()?;
+            let module_tokens = self.language.tokenize(&module_uri, &module_text)?;
+            let definition = consts::collect(&module_text, &module_tokens)
+                .into_iter()
+                .find(|c| c.name == name)?;
+            (module_uri, definition)
+        };
+
+        // consts can only refer to other consts and modules, so this is enough to fold it
+        let script = document_imports
+            .iter()
+            .map(imports::Import::to_statement)
+            .chain(document_consts.iter().map(|c| c.statement.clone()))
+            .chain(std::iter::once(token.value.clone()))
+            .collect::<Vec<String>>()
+            .join("\n");
+        let value = match self.language.eval(uri, &script)? {
+            Ok(value) => value,
+            Err(e) => format!("<failed to evaluate: {}>", e),
+        };
+
+        let mut value = format!(
+            "```tremor\nconst {} = {}\n```\n\n{}",
+            name,
+            value,
+            lsp_utils::definition_link(&definition_uri, definition.range)
+        );
+        let parts: Vec<String> = token.value.split("::").map(String::from).collect();
+        if let Some(provenance) = parts
+            .split_last()
+            .and_then(|(_, module_path)| self.get_import_provenance(uri, tokens, module_path))
+        {
+            value = format!("{}\n\n{}", value, provenance);
+        }
+        Some(value)
+    }
+
+    // shows the signature and doc comment of a function defined in the document, or in a
+    // module it uses
+    fn get_function_hover_content(
+        &self,
+        uri: &Url,
+        text: &str,
+        tokens: &[language::TokenSpan],
+        token: &lsp_utils::PathToken,
+    ) -> Option<String> {
+        let mut path: Vec<String> = token.value.split("::").map(String::from).collect();
+        let name = path.pop()?;
+
+        let (definition_uri, definition_text) = if path.is_empty() {
+            (uri.clone(), text.to_string())
+        } else {
+            self.get_imported_module(uri, tokens, &path)?
+        };
+        let definition_tokens = self.language.tokenize(&definition_uri, &definition_text)?;
+        let symbol = symbols::collect(&definition_text, &definition_tokens)
+            .into_iter()
+            .find(|s| s.kind == SymbolKind::Function && s.name == name)?;
+
+        let mut value = format!(
+            "{}\n\n{}",
+            symbols::documentation(&definition_text, &symbol),
+            lsp_utils::definition_link(&definition_uri, symbol.range)
+        );
+        if let Some(provenance) = self.get_import_provenance(uri, tokens, &path) {
+            value = format!("{}\n\n{}", value, provenance);
+        }
+        Some(value)
+    }
+
+    // how the module path (eg: `alias::submodule`) got into the document: the module it stands
+    // for, the file of that and the use statement importing it (with any alias)
+    fn get_import_provenance(
+        &self,
+        uri: &Url,
+        tokens: &[language::TokenSpan],
+        path: &[String],
+    ) -> Option<String> {
+        let import = imports::parse(tokens)
+            .into_iter()
+            .find(|import| Some(import.name()) == path.first().map(String::as_str))?;
+        let mut module_path = import.module_path.clone();
+        module_path.extend(path.iter().skip(1).cloned());
+        // only for modules that are there
+        modules::resolve(uri, &module_path)?;
+
+        let alias = match &import.alias {
+            Some(alias) => format!(", aliased as `{}`", alias),
+            None => String::new(),
+        };
+        Some(format!(
+            "Imported from `{}` → `{}`{}, by `{}` at {}",
+            module_path.join("::"),
+            modules::module_file(&module_path).display(),
+            alias,
+            import.to_statement(),
+            lsp_utils::location_link(uri, import.range)
+        ))
+    }
+
+    // uri and text of the module file for the path, where the first part is the name of a
+    // module imported via a use statement, eg: `alias::submodule`
+    fn get_imported_module(
+        &self,
+        uri: &Url,
+        tokens: &[language::TokenSpan],
+        path: &[String],
+    ) -> Option<(Url, String)> {
+        let import = imports::parse(tokens)
+            .into_iter()
+            .find(|import| Some(import.name()) == path.first().map(String::as_str))?;
+        let mut module_path = import.module_path.clone();
+        module_path.extend(path.iter().skip(1).cloned());
+
+        let module_file = modules::resolve(uri, &module_path)?;
+        let module_uri = paths::to_uri(&module_file)?;
+        let module_text = fs::read_to_string(&module_file).ok()?;
+        Some((module_uri, module_text))
+    }
+
+    // summarizes the module imported by the use statement the token is in, along with how
+    // its file was resolved (or why it was not)
+    fn get_module_hover_content(
+        &self,
+        uri: &Url,
+        tokens: &[language::TokenSpan],
+        token: &lsp_utils::PathToken,
+    ) -> Option<String> {
+        let import = imports::parse(tokens).into_iter().find(|import| {
+            import.range.start <= token.range.start && token.range.end <= import.range.end
+        })?;
+        let module_name = import.module_name();
+        let relative = modules::module_file(&import.module_path);
+        let search_paths = modules::labeled_search_paths(uri);
+        // candidates in order of precedence, where the first one wins
+        let found: Vec<&(PathBuf, &str)> = search_paths
+            .iter()
+            .filter(|(dir, _)| dir.join(&relative).is_file())
+            .collect();
+
+        let (dir, source) = match found.first() {
+            Some(winner) => winner,
+            None => {
+                return modules::builtin_summary(self.language.as_ref(), uri, &module_name)
+                    .or_else(|| {
+                        let searched = if search_paths.is_empty() {
+                            "There are no search paths (TREMOR_PATH is not set)".to_string()
+                        } else {
+                            search_paths
+                                .iter()
+                                .map(|(dir, source)| format!("* `{}` ({})", dir.display(), source))
+                                .collect::<Vec<_>>()
+                                .join("\n")
+                        };
+                        Some(format!(
+                            "Module `{}` not found: there is no `{}` in the search paths, and \
+                             it's not a builtin module.\n\n{}",
+                            module_name,
+                            relative.display(),
+                            searched
+                        ))
+                    });
+            }
+        };
+        let module_file = dir.join(&relative);
+        let module_uri = paths::to_uri(&module_file)?;
+        let module_text = fs::read_to_string(&module_file).ok()?;
+        let module_tokens = self.language.tokenize(&module_uri, &module_text)?;
+
+        let mut resolution = format!(
+            "Resolved to `{}`, under `{}` ({})",
+            module_file.display(),
+            dir.display(),
+            source
+        );
+        for (dir, source) in found.iter().skip(1) {
+            resolution.push_str(&format!(
+                "\n\nShadows `{}` ({})",
+                dir.join(&relative).display(),
+                source
+            ));
+        }
+        Some(format!(
+            "{}\n\n{}\n\n{}",
+            modules::file_summary(&module_name, &module_text, &module_tokens),
+            resolution,
+            lsp_utils::definition_link(&module_uri, Range::default())
+        ))
+    }
+
+    // where the name at the position is defined, for windows and nodes of a query
+    fn get_definition(&self, uri: &Url, text: &str, position: Position) -> Option<Location> {
+        let tokens = self.language.tokenize(uri, text)?;
+        let token = lsp_utils::get_token(text, &tokens, position)?;
+        let within = |name: &pipeline::Name| {
+            name.range.start <= token.range.start && token.range.end <= name.range.end
+        };
+        let selects = pipeline::selects(text, &tokens);
+        let nodes = pipeline::nodes(text, &tokens);
+
+        // `from in[window]`
+        if selects.iter().flat_map(|s| s.windows.iter()).any(within) {
+            return self.find_definition(uri, text, &tokens, &token.value, SymbolKind::Struct);
+        }
+        // `from node` or `into node`, to where the node is created
+        if selects
+            .iter()
+            .flat_map(|s| s.from.iter().chain(s.into.iter()))
+            .any(within)
+        {
+            return nodes
+                .iter()
+                .find(|node| node.name.value == token.value)
+                .map(|node| Location::new(uri.clone(), node.name.range));
+        }
+        // `alias::function(...)`, to the function in the module file, or to the use statement
+        // for builtin modules
+        if let Some((_, module_path)) = token.value.split("::").collect::<Vec<_>>().split_last() {
+            let in_node = nodes.iter().any(|node| {
+                within(&node.name) || node.definition.as_ref().map_or(false, |d| within(d))
+            });
+            if !module_path.is_empty() && !in_node {
+                let definition =
+                    self.find_definition(uri, text, &tokens, &token.value, SymbolKind::Function);
+                return definition.or_else(|| {
+                    imports::parse(&tokens)
+                        .into_iter()
+                        .find(|import| module_path.first() == Some(&import.name()))
+                        .map(|import| Location::new(uri.clone(), import.range))
+                });
+            }
+        }
+        // `create operator name from definition`, to the definition
+        let node = nodes.iter().find(|node| {
+            within(&node.name) || node.definition.as_ref().map_or(false, |d| within(d))
+        })?;
+        let kind = match node.kind {
+            pipeline::NodeKind::Operator => SymbolKind::Operator,
+            pipeline::NodeKind::Script => SymbolKind::Class,
+            pipeline::NodeKind::Stream => return None,
+        };
+        let path = &node.definition.as_ref().unwrap_or(&node.name).value;
+        self.find_definition(uri, text, &tokens, path, kind)
+    }
+
+    // definition of the kind for the (module) path, in the document or in the module
+    // imported under the first part of the path
+    fn find_definition(
+        &self,
+        uri: &Url,
+        text: &str,
+        tokens: &[language::TokenSpan],
+        path: &str,
+        kind: SymbolKind,
+    ) -> Option<Location> {
+        let mut path: Vec<String> = path.split("::").map(String::from).collect();
+        let name = path.pop()?;
+        let (definition_uri, definition_text) = if path.is_empty() {
+            (uri.clone(), text.to_string())
+        } else {
+            self.get_imported_module(uri, tokens, &path)?
+        };
+        let definition_tokens = self.language.tokenize(&definition_uri, &definition_text)?;
+        symbols::collect(&definition_text, &definition_tokens)
+            .into_iter()
+            .find(|symbol| symbol.kind == kind && symbol.name == name)
+            .map(|symbol| Location::new(definition_uri.clone(), symbol.range))
+    }
+
+    // lists the select statements that write into the stream (or operator, script) and the
+    // ones that read from it
+    fn get_node_hover_content(
+        &self,
+        uri: &Url,
+        text: &str,
+        tokens: &[language::TokenSpan],
+        token: &lsp_utils::PathToken,
+    ) -> Option<String> {
+        let connections = pipeline::connections(text, tokens, token.range.start)?;
+        let kind = match connections.kind {
+            Some(pipeline::NodeKind::Stream) => "stream",
+            Some(pipeline::NodeKind::Operator) => "operator",
+            Some(pipeline::NodeKind::Script) => "script",
+            None => "default stream",
+        };
+        // the other end of each select, along with where it is
+        let list = |ends: Vec<(Option<&pipeline::Name>, Range)>, direction: &str| {
+            if ends.is_empty() {
+                return "none".to_string();
+            }
+            ends.iter()
+                .map(|(name, range)| {
+                    format!(
+                        "* {} `{}`: {}",
+                        direction,
+                        name.map_or("?", |name| name.value.as_str()),
+                        lsp_utils::location_link(uri, *range)
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n")
+        };
+        let producers = connections
+            .producers
+            .iter()
+            .map(|select| (select.from.as_ref(), select.range))
+            .collect();
+        let consumers = connections
+            .consumers
+            .iter()
+            .map(|select| (select.into.as_ref(), select.range))
+            .collect();
+
+        Some(format!(
+            "{} `{}`\n\nWritten by:\n\n{}\n\nRead by:\n\n{}",
+            kind,
+            connections.name,
+            list(producers, "from"),
+            list(consumers, "into"),
+        ))
+    }
+
+    // shows the definition of a window used in a select statement
+    fn get_window_hover_content(
+        &self,
+        uri: &Url,
+        text: &str,
+        tokens: &[language::TokenSpan],
+        token: &lsp_utils::PathToken,
+    ) -> Option<String> {
+        let in_window_list = pipeline::selects(text, tokens).iter().any(|select| {
+            select
+                .windows
+                .iter()
+                .any(|w| w.range.start <= token.range.start && token.range.end <= w.range.end)
+        });
+        if !in_window_list {
+            return None;
+        }
+
+        let mut path: Vec<String> = token.value.split("::").map(String::from).collect();
+        let name = path.pop()?;
+
+        let (definition_uri, definition_text) = if path.is_empty() {
+            (uri.clone(), text.to_string())
+        } else {
+            // `alias::window`, where the alias comes from a use statement
+            self.get_imported_module(uri, tokens, &path)?
+        };
+        let definition_tokens = self.language.tokenize(&definition_uri, &definition_text)?;
+        let window = pipeline::windows(&definition_text, &definition_tokens)
+            .into_iter()
+            .find(|w| w.name.value == name)?;
+        let doc = symbols::doc_comment(&definition_text, window.name.range.start.line)
+            .map(|doc| format!("{}\n\n", doc))
+            .unwrap_or_default();
+
+        Some(format!(
+            "{} window `{}`\n\n```trickle\n{}\n```\n\n{}{}",
+            window.kind,
+            name,
+            window.definition,
+            doc,
+            lsp_utils::definition_link(&definition_uri, window.name.range)
+        ))
+    }
+
+    // moves the definitions at the range into a new module file, in the `lib` directory of the
+    // workspace root (a module directory, so the module is used by its name), which the
+    // document then uses. with the title of the action.
+    fn get_move_to_module_edit(
+        &self,
+        uri: &Url,
+        text: &str,
+        range: Range,
+        roots: &[PathBuf],
+    ) -> Option<(String, WorkspaceEdit)> {
+        let tokens = self.language.tokenize(uri, text)?;
+        let moved = extract::moved_statements(text, &tokens, range)?;
+        let path = paths::to_path(uri)?;
+        let root = roots
+            .iter()
+            .filter(|root| path.starts_with(root))
+            .max_by_key(|root| root.components().count())?;
+        let (name, _) = moved.names.first()?;
+        let module_file =
+            root.join("lib")
+                .join(format!("{}.{}", name, modules::MODULE_FILE_EXTENSION));
+        // never overwrites a module
+        if module_file.exists() {
+            return None;
+        }
+        // just the name through `lib`, unless an earlier search path (eg: from TREMOR_PATH)
+        // has the file
+        let module_path = modules::module_path(uri, &module_file)?;
+        let module_uri = paths::to_uri(&module_file)?;
+
+        let edits = extract::module_edits(text, &tokens, &moved, &module_path);
+        let edit = WorkspaceEdit {
+            changes: None,
+            document_changes: Some(DocumentChanges::Operations(vec![
+                DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+                    uri: module_uri.clone(),
+                    options: None,
+                })),
+                DocumentChangeOperation::Edit(TextDocumentEdit {
+                    text_document: VersionedTextDocumentIdentifier {
+                        uri: module_uri,
+                        version: None,
+                    },
+                    edits: vec![TextEdit::new(Range::default(), moved.content)],
+                }),
+                DocumentChangeOperation::Edit(TextDocumentEdit {
+                    text_document: VersionedTextDocumentIdentifier {
+                        uri: uri.clone(),
+                        version: None,
+                    },
+                    edits,
+                }),
+            ])),
+        };
+        Some((format!("Move into module {}", module_path.join("::")), edit))
+    }
+
+    // renames the stream, operator, script or window at the position, within the document.
+    // streams are renamed in the urls of the deployment files that bind the pipeline as well.
+    fn get_rename_edit(
+        &self,
+        uri: &Url,
+        text: &str,
+        position: Position,
+        new_name: &str,
+        roots: &[PathBuf],
+    ) -> std::result::Result<Option<WorkspaceEdit>, String> {
+        let tokens = match self.language.tokenize(uri, text) {
+            Some(tokens) => tokens,
+            None => return Ok(None),
+        };
+        let ranges = pipeline::linked_ranges(text, &tokens, position);
+        let old_name = match ranges.first() {
+            Some(range) => lsp_utils::text_in_range(text, *range),
+            None => return Ok(None),
+        };
+        let valid = new_name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && new_name.chars().all(|c| c.is_alphanumeric() || c == '_');
+        if !valid {
+            return Err(format!("`{}` is not a valid name", new_name));
+        }
+        if pipeline::DEFAULT_STREAMS.contains(&old_name.as_str()) {
+            return Err(format!("`{}` is a default stream of the query", old_name));
+        }
+
+        let mut changes = std::collections::HashMap::new();
+        let edits = |ranges: Vec<Range>| -> Vec<TextEdit> {
+            ranges
+                .into_iter()
+                .map(|range| TextEdit::new(range, new_name.to_string()))
+                .collect()
+        };
+        changes.insert(uri.clone(), edits(ranges));
+
+        let is_stream = pipeline::nodes(text, &tokens)
+            .iter()
+            .any(|node| node.kind == pipeline::NodeKind::Stream && node.name.value == old_name);
+        // the pipeline is named after its file
+        let pipeline_name = paths::to_path(uri)
+            .filter(|path| path.extension().map_or(false, |e| e == "trickle"))
+            .and_then(|path| Some(path.file_stem()?.to_str()?.to_string()));
+        if let (true, Some(pipeline_name)) = (is_stream, pipeline_name) {
+            for file in roots
+                .iter()
+                .flat_map(|root| bindings::find_files(root, &self.exclusions(root)))
+            {
+                let ranges = match fs::read_to_string(&file) {
+                    Ok(text) => bindings::stream_ranges(&text, &pipeline_name, &old_name),
+                    Err(_) => continue,
+                };
+                if let (false, Some(file_uri)) = (ranges.is_empty(), paths::to_uri(&file)) {
+                    changes.insert(file_uri, edits(ranges));
+                }
+            }
+        }
+        Ok(Some(WorkspaceEdit::new(changes)))
+    }
+
+    fn get_organize_imports_edit(&self, uri: &Url, text: &str) -> Option<WorkspaceEdit> {
+        let tokens = self.language.tokenize(uri, text)?;
+        let edits = imports::organize(&tokens);
+        if edits.is_empty() {
+            return None;
+        }
+
+        Some(lsp_utils::workspace_edit(uri, edits))
+    }
+
+    // the text with the use statements replaced by the modules they refer to, recursively.
+    // including is the chain of module files being inlined, to catch cyclic uses.
+    fn get_inlined_text(
+        &self,
+        uri: &Url,
+        text: &str,
+        including: &mut Vec<PathBuf>,
+        unresolved: &mut Vec<String>,
+    ) -> std::result::Result<String, String> {
+        let tokens = self
+            .language
+            .tokenize(uri, text)
+            .ok_or_else(|| format!("Failed to tokenize {}", uri))?;
+
+        let mut inlined = String::new();
+        let mut rest = 0;
+        for import in imports::parse(&tokens) {
+            let module_name = import.module_name();
+            let module_file = match modules::resolve(uri, &import.module_path) {
+                Some(module_file) => module_file,
+                None => {
+                    unresolved.push(module_name);
+                    continue;
+                }
+            };
+            if including.contains(&module_file) {
+                return Err(format!("Cyclic use of module {}", module_name));
+            }
+            let module_uri = paths::to_uri(&module_file)
+                .ok_or_else(|| format!("Bad module file {}", module_file.display()))?;
+            let module_text = fs::read_to_string(&module_file)
+                .map_err(|e| format!("Failed to read {}: {}", module_file.display(), e))?;
+
+            including.push(module_file.clone());
+            let module_text =
+                self.get_inlined_text(&module_uri, &module_text, including, unresolved)?;
+            including.pop();
+
+            let start = lsp_utils::position_to_offset(text, import.range.start);
+            let end = lsp_utils::position_to_offset(text, import.range.end);
+            inlined.push_str(text.get(rest..start).unwrap_or_default());
+            inlined.push_str(&format!(
+                "# {} from {}\nmod {} with\n{}\nend;",
+                module_name,
+                module_file.display(),
+                import.name(),
+                module_text.trim_end()
+            ));
+            rest = end;
+        }
+        inlined.push_str(text.get(rest..).unwrap_or_default());
+
+        unresolved.sort();
+        unresolved.dedup();
+        Ok(inlined)
+    }
+
+    // signature of the (builtin, local or imported) function called around the position
+    fn get_signature_help(
+        &self,
+        uri: &Url,
+        text: &str,
+        position: Position,
+    ) -> Option<SignatureHelp> {
+        let tokens = self.language.tokenize(uri, text)?;
+        let call = signatures::call_site(text, &tokens, position)?;
+        let markdown = |value: String| {
+            if self.supports_markdown_documentation.load(Ordering::Relaxed) {
+                Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value,
+                })
+            } else {
+                Documentation::String(lsp_utils::to_plain_text(&value))
+            }
+        };
+
+        let function_name = self.unalias(uri, &tokens, &call.path.join("::"));
+        if let Some(function_doc) = self.language.function_doc(uri, &function_name) {
+            return Some(signatures::help(
+                &function_doc.signature.to_string(),
+                Some(markdown(function_doc.description.clone())),
+                call.argument,
+            ));
+        }
+
+        // functions of the document, or of a module it uses
+        let (name, module_path) = call.path.split_last()?;
+        let function_help = |text: &str, tokens: &[language::TokenSpan]| {
+            let symbol = symbols::collect(text, tokens)
+                .into_iter()
+                .find(|s| s.kind == SymbolKind::Function && s.name == *name)?;
+            Some(signatures::help(
+                &symbols::signature(text, &symbol),
+                symbol.doc.map(markdown),
+                call.argument,
+            ))
+        };
+        if module_path.is_empty() {
+            return function_help(text, &tokens);
+        }
+        let (module_uri, module_text) = self.get_imported_module(uri, &tokens, module_path)?;
+        let module_tokens = self.language.tokenize(&module_uri, &module_text)?;
+        function_help(&module_text, &module_tokens)
+    }
+
+    // hover in a format that the client supports
+    fn adapt_hover(&self, hover: Hover) -> Hover {
+        match hover.contents {
+            HoverContents::Markup(markup)
+                if markup.kind == MarkupKind::Markdown
+                    && !self.supports_markdown_hover.load(Ordering::Relaxed) =>
+            {
+                Hover {
+                    contents: HoverContents::Markup(MarkupContent {
+                        kind: MarkupKind::PlainText,
+                        value: lsp_utils::to_plain_text(&markup.value),
+                    }),
+                    ..hover
+                }
+            }
+            _ => hover,
+        }
+    }
+
+    // completion item without the features that the client does not support, and with as
+    // much detail as configured
+    fn adapt_completion(&self, item: CompletionItem, detail: CompletionDetail) -> CompletionItem {
+        let item = match detail {
+            CompletionDetail::Full => item,
+            CompletionDetail::Signature => CompletionItem {
+                documentation: None,
+                ..item
+            },
+            CompletionDetail::Label => CompletionItem {
+                detail: None,
+                documentation: None,
+                ..item
+            },
+        };
+        let documentation = match item.documentation {
+            Some(Documentation::MarkupContent(markup))
+                if markup.kind == MarkupKind::Markdown
+                    && !self.supports_markdown_documentation.load(Ordering::Relaxed) =>
+            {
+                Some(Documentation::String(lsp_utils::to_plain_text(
+                    &markup.value,
+                )))
+            }
+            documentation => documentation,
+        };
+        // the snippet would be inserted verbatim. functions get their plain name instead (as
+        // the argument placeholders would be left in), others the text of the snippet.
+        let is_snippet = item.insert_text_format == Some(InsertTextFormat::Snippet);
+        if is_snippet && !self.supports_snippets.load(Ordering::Relaxed) {
+            let insert_text = match item.kind {
+                Some(CompletionItemKind::Function) => None,
+                _ => item
+                    .insert_text
+                    .as_deref()
+                    .map(lsp_utils::snippet_to_plain_text),
+            };
+            return CompletionItem {
+                documentation,
+                insert_text,
+                insert_text_format: None,
+                ..item
+            };
+        }
+        CompletionItem {
+            documentation,
+            ..item
+        }
+    }
+
+    // fixes for the lints behind the diagnostics
+    fn get_quick_fixes(
+        &self,
+        uri: &Url,
+        text: &str,
+        diagnostics: &[Diagnostic],
+    ) -> Vec<CodeActionOrCommand> {
+        let lints = match (test_files::kind(uri), self.language.tokenize(uri, text)) {
+            (Some(kind), _) => test_files::check(kind, text),
+            (None, Some(tokens)) => lints::check(self.language.as_ref(), uri, text, &tokens),
+            (None, None) => return vec![],
+        };
+
+        let mut actions = Vec::new();
+        for diagnostic in diagnostics {
+            for lint in lints.iter().filter(|lint| lint.matches(diagnostic)) {
+                for fix in &lint.fixes {
+                    actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                        title: fix.title.clone(),
+                        kind: Some(CodeActionKind::QUICKFIX),
+                        diagnostics: Some(vec![diagnostic.clone()]),
+                        edit: Some(lsp_utils::workspace_edit(uri, fix.edits.clone())),
+                        ..CodeAction::default()
+                    }));
+                }
+            }
+        }
+        actions
+    }
+
+    async fn run_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        match params.command.as_str() {
+            commands::ORGANIZE_IMPORTS => {
+                let uri = match params.arguments.first().and_then(Value::as_str) {
+                    Some(uri) => Url::parse(uri).map_err(|_| Error::invalid_params("Bad uri"))?,
+                    None => return Err(Error::invalid_params("Missing document uri")),
+                };
+
+                let edit = match self.get_text(&uri).await {
+                    Some(text) => self
+                        .catch_panic("organizing imports", || {
+                            self.get_organize_imports_edit(&uri, &text)
+                        })
+                        .await
+                        .flatten(),
+                    None => None,
+                };
+
+                if let Some(edit) = edit {
+                    if let Err(e) = self.client.apply_edit(edit).await {
+                        self.client
+                            .log_message(
+                                MessageType::Error,
+                                format!("Failed to organize imports: {}", e),
+                            )
+                            .await;
+                    }
+                }
+                Ok(None)
+            }
+            commands::SERVER_STATUS => Ok(Some(self.get_server_status().await)),
+            commands::METRICS => {
+                let prometheus = params
+                    .arguments
+                    .first()
+                    .and_then(|args| args.get("format"))
+                    .and_then(Value::as_str)
+                    == Some("prometheus");
+                Ok(Some(self.get_metrics(prometheus).await))
+            }
+            commands::VALIDATE_WORKSPACE => Ok(Some(self.validate_workspace().await)),
+            commands::WORKSPACE_STATS => Ok(Some(self.workspace_stats().await)),
+            commands::RELOAD_DOCS => match self.load_docs() {
+                Ok(functions) => {
+                    self.publish_all_diagnostics().await;
+                    Ok(Some(json!({
+                        "path": self.config().docs_path,
+                        "functions": functions,
+                    })))
+                }
+                Err(e) => Ok(Some(json!({ "error": e }))),
+            },
+            #[cfg(feature = "runtime")]
+            commands::RUNTIME_ARTEFACTS => {
+                let url = self.config().runtime_url;
+                let api = runtime::Api::new(url.as_deref().unwrap_or(runtime::DEFAULT_URL));
+                match api.artefacts().await {
+                    Ok(artefacts) => Ok(Some(artefacts)),
+                    Err(e) => Ok(Some(json!({ "error": e }))),
+                }
+            }
+            #[cfg(feature = "runtime")]
+            commands::DEPLOY_TO_RUNTIME => {
+                let uri = match params.arguments.first().and_then(Value::as_str) {
+                    Some(uri) => Url::parse(uri).map_err(|_| Error::invalid_params("Bad uri"))?,
+                    None => return Err(Error::invalid_params("Missing document uri")),
+                };
+                let text = self
+                    .get_text(&uri)
+                    .await
+                    .ok_or_else(|| Error::invalid_params("Unknown document"))?;
+                let url = self.config_for(&uri).runtime_url;
+                let api = runtime::Api::new(url.as_deref().unwrap_or(runtime::DEFAULT_URL));
+                match api.publish_pipeline(&text).await {
+                    Ok(artefact) => Ok(Some(artefact)),
+                    Err(e) => Ok(Some(json!({ "error": e }))),
+                }
+            }
+            commands::PIPELINE_GRAPH => {
+                let uri = match params.arguments.first().and_then(Value::as_str) {
+                    Some(uri) => Url::parse(uri).map_err(|_| Error::invalid_params("Bad uri"))?,
+                    None => return Err(Error::invalid_params("Missing document uri")),
+                };
+                let text = self
+                    .get_text(&uri)
+                    .await
+                    .ok_or_else(|| Error::invalid_params("Unknown document"))?;
+                let tokens = self
+                    .language
+                    .tokenize(&uri, &text)
+                    .ok_or_else(|| Error::invalid_params("Document does not tokenize"))?;
+                Ok(Some(pipeline::graph(&text, &tokens)))
+            }
+            commands::SCAFFOLD_DEPLOYMENT => {
+                let uri = match params.arguments.first().and_then(Value::as_str) {
+                    Some(uri) => Url::parse(uri).map_err(|_| Error::invalid_params("Bad uri"))?,
+                    None => return Err(Error::invalid_params("Missing document uri")),
+                };
+                if language::file_extension(self.language.name()) != Some("trickle") {
+                    return Err(Error::invalid_params("Not a trickle document"));
+                }
+                let text = self
+                    .get_text(&uri)
+                    .await
+                    .ok_or_else(|| Error::invalid_params("Unknown document"))?;
+                let (inputs, outputs) = match self.language.tokenize(&uri, &text) {
+                    Some(tokens) => pipeline::ports(&text, &tokens),
+                    None => return Err(Error::invalid_params("Document does not tokenize")),
+                };
+                match self.scaffold_deployment(&uri, inputs, outputs).await {
+                    Ok(scaffold) => Ok(Some(scaffold)),
+                    Err(e) => Ok(Some(json!({ "error": e }))),
+                }
+            }
+            commands::EVAL => {
+                let args = params
+                    .arguments
+                    .first()
+                    .ok_or_else(|| Error::invalid_params("Missing eval arguments"))?;
+                let expression = args
+                    .get("expression")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| Error::invalid_params("Missing expression"))?;
+                let event = args
+                    .get("event")
+                    .map_or("null".to_string(), Value::to_string);
+                let state = args.get("state").map(Value::to_string);
+                // only used for module resolution
+                let uri = args
+                    .get("uri")
+                    .and_then(Value::as_str)
+                    .and_then(|uri| Url::parse(uri).ok())
+                    .or_else(|| Url::from_directory_path(std::env::current_dir().ok()?).ok())
+                    .ok_or_else(|| Error::invalid_params("Bad uri"))?;
+
+                let mut session = self.repl.lock().await;
+                if args.get("reset").and_then(Value::as_bool).unwrap_or(false) {
+                    *session = repl::Session::default();
+                }
+                let result = self
+                    .catch_panic("evaluating", || {
+                        session.eval(
+                            self.language.as_ref(),
+                            &uri,
+                            expression,
+                            &event,
+                            state.as_deref(),
+                        )
+                    })
+                    .await
+                    .unwrap_or_else(|| Err("Internal error".to_string()));
+                match result {
+                    Ok(output) => Ok(Some(output)),
+                    Err(e) => Ok(Some(json!({ "error": e }))),
+                }
+            }
+            commands::RUN_PIPELINE => {
+                let args = params
+                    .arguments
+                    .first()
+                    .ok_or_else(|| Error::invalid_params("Missing run arguments"))?;
+                let uri = args
+                    .get("uri")
+                    .and_then(Value::as_str)
+                    .and_then(|uri| Url::parse(uri).ok())
+                    .ok_or_else(|| Error::invalid_params("Bad uri"))?;
+                // the events can come along (eg: for scratchpads), rather than in a file
+                let events = match args.get("events").and_then(Value::as_array) {
+                    Some(events) => Ok(events.iter().map(Value::to_string).collect()),
+                    None => {
+                        let events_file = args
+                            .get("eventsFile")
+                            .and_then(Value::as_str)
+                            .ok_or_else(|| Error::invalid_params("Missing events file"))?;
+                        read_events(events_file)
+                    }
+                };
 
-    fn get_diagnostics(&self, uri: &Url, text: &str) -> Vec<Diagnostic> {
-        file_dbg("get_diagnostics", text);
+                let summary = match events {
+                    Ok(events) => self.run_pipeline(&uri, &events).await,
+                    Err(e) => Err(e),
+                };
+                match summary {
+                    Ok(summary) => Ok(Some(summary)),
+                    Err(e) => Ok(Some(json!({ "error": e }))),
+                }
+            }
+            commands::BENCHMARK_SCRIPT => {
+                let args = params
+                    .arguments
+                    .first()
+                    .ok_or_else(|| Error::invalid_params("Missing benchmark arguments"))?;
+                let uri = args
+                    .get("uri")
+                    .and_then(Value::as_str)
+                    .and_then(|uri| Url::parse(uri).ok())
+                    .ok_or_else(|| Error::invalid_params("Bad uri"))?;
+                let event = args
+                    .get("event")
+                    .map_or("null".to_string(), Value::to_string);
+                let iterations = args
+                    .get("iterations")
+                    .and_then(Value::as_u64)
+                    .map_or(bench::DEFAULT_ITERATIONS, |n| n as usize);
 
-        let mut diagnostics = Vec::new();
+                let text = self
+                    .get_text(&uri)
+                    .await
+                    .ok_or_else(|| Error::invalid_params("Document is not open"))?;
+                let result = self
+                    .catch_panic("benchmarking", || {
+                        self.language.benchmark(&uri, &text, &event, iterations)
+                    })
+                    .await
+                    .unwrap_or_else(|| Some(Err("Internal error".to_string())));
+                match result {
+                    Some(Ok(timings)) => Ok(Some(bench::summarize(timings))),
+                    Some(Err(e)) => Ok(Some(json!({ "error": e }))),
+                    None => Ok(Some(
+                        json!({ "error": "Benchmarking is not supported for this language" }),
+                    )),
+                }
+            }
+            commands::LINKED_EDITING_RANGES => {
+                let args = params
+                    .arguments
+                    .first()
+                    .ok_or_else(|| Error::invalid_params("Missing arguments"))?;
+                let uri = args
+                    .get("uri")
+                    .and_then(Value::as_str)
+                    .and_then(|uri| Url::parse(uri).ok())
+                    .ok_or_else(|| Error::invalid_params("Bad uri"))?;
+                let position: Position = args
+                    .get("position")
+                    .and_then(|p| serde_json::from_value(p.clone()).ok())
+                    .ok_or_else(|| Error::invalid_params("Bad position"))?;
 
-        if let Some(errors) = self.language.parse_errors(uri, text) {
-            for e in &errors {
-                let range = Range {
-                    start: lsp_utils::to_lsp_position(&e.start()),
-                    end: lsp_utils::to_lsp_position(&e.end()),
+                let ranges = match self.get_text(&uri).await {
+                    Some(text) => self
+                        .language
+                        .tokenize(&uri, &text)
+                        .map(|tokens| pipeline::linked_ranges(&text, &tokens, position))
+                        .unwrap_or_default(),
+                    None => vec![],
                 };
+                Ok(Some(json!({ "ranges": ranges })))
+            }
+            commands::DOCUMENT_DIAGNOSTICS => {
+                let args = params
+                    .arguments
+                    .first()
+                    .ok_or_else(|| Error::invalid_params("Missing arguments"))?;
+                let uri = args
+                    .get("uri")
+                    .and_then(Value::as_str)
+                    .and_then(|uri| Url::parse(uri).ok())
+                    .ok_or_else(|| Error::invalid_params("Bad uri"))?;
+                let previous_result_id = args.get("previousResultId").and_then(Value::as_str);
+
+                let text = self
+                    .get_text(&uri)
+                    .await
+                    .ok_or_else(|| Error::invalid_params("Document is not open"))?;
+                Ok(Some(
+                    self.get_diagnostic_report(&uri, &text, previous_result_id)
+                        .await,
+                ))
+            }
+            commands::WORKSPACE_DIAGNOSTICS => {
+                let previous_result_ids: HashMap<String, String> = params
+                    .arguments
+                    .first()
+                    .and_then(|args| args.get("previousResultIds"))
+                    .and_then(Value::as_array)
+                    .map(|ids| {
+                        ids.iter()
+                            .filter_map(|id| {
+                                Some((
+                                    id.get("uri")?.as_str()?.to_string(),
+                                    id.get("value")?.as_str()?.to_string(),
+                                ))
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
 
-                let mut message = e.callout().to_string();
-                if let Some(hint) = &e.hint() {
-                    // comma here splits the message into multiple lines
-                    message = format!("{}, Note: {}", message, hint);
+                let mut items = Vec::new();
+                for WorkspaceFile { uri, text, version } in self.workspace_files().await {
+                    let previous_result_id = previous_result_ids.get(uri.as_str());
+                    let mut report = self
+                        .get_diagnostic_report(&uri, &text, previous_result_id.map(String::as_str))
+                        .await;
+                    report["uri"] = json!(uri);
+                    report["version"] = json!(version);
+                    items.push(report);
                 }
+                Ok(Some(json!({ "items": items })))
+            }
+            commands::OPEN_DOCS => {
+                let args = params.arguments.first();
+                let name = args
+                    .and_then(|args| args.get("name"))
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                let docs_uri = Url::parse(&format!("tremor-docs:///{}.md", name))
+                    .map_err(|_| Error::invalid_params("Bad name"))?;
+                // docs are looked up in the context of the document, when there is one
+                let uri = args
+                    .and_then(|args| args.get("uri"))
+                    .and_then(Value::as_str)
+                    .and_then(|uri| Url::parse(uri).ok())
+                    .unwrap_or_else(|| docs_uri.clone());
 
-                diagnostics.push(Diagnostic {
-                    range,
-                    message,
-                    severity: Some(lsp_utils::to_lsp_severity(&e.level())),
-                    source: Some("tremor-language-server".to_string()),
-                    code: None,
-                    related_information: None,
-                    tags: None,
-                });
+                let content = modules::builtin_docs(self.language.as_ref(), &uri, name)
+                    .ok_or_else(|| {
+                        Error::invalid_params(format!("No documentation for {}", name))
+                    })?;
+                Ok(Some(json!({ "uri": docs_uri, "content": content })))
             }
-        }
+            commands::INLINE_MODULES => {
+                let uri = match params.arguments.first().and_then(Value::as_str) {
+                    Some(uri) => Url::parse(uri).map_err(|_| Error::invalid_params("Bad uri"))?,
+                    None => return Err(Error::invalid_params("Missing document uri")),
+                };
+                let text = self
+                    .get_text(&uri)
+                    .await
+                    .ok_or_else(|| Error::invalid_params("Unknown document"))?;
 
-        diagnostics
-    }
+                let mut including = paths::to_path(&uri).into_iter().collect();
+                let mut unresolved = Vec::new();
+                let result = self
+                    .catch_panic("inlining modules", || {
+                        self.get_inlined_text(&uri, &text, &mut including, &mut unresolved)
+                    })
+                    .await
+                    .unwrap_or_else(|| Err("Internal error".to_string()));
+                match result {
+                    Ok(text) => Ok(Some(json!({ "text": text, "unresolved": unresolved }))),
+                    Err(e) => Ok(Some(json!({ "error": e }))),
+                }
+            }
+            commands::SHOW_INLINED_MODULES => {
+                let uri = match params.arguments.first().and_then(Value::as_str) {
+                    Some(uri) => Url::parse(uri).map_err(|_| Error::invalid_params("Bad uri"))?,
+                    None => return Err(Error::invalid_params("Missing document uri")),
+                };
+                let text = self
+                    .get_text(&uri)
+                    .await
+                    .ok_or_else(|| Error::invalid_params("Unknown document"))?;
+                let inlined_uri = Url::parse(&format!("tremor-inlined://{}", uri.path()))
+                    .map_err(|_| Error::invalid_params("Bad uri"))?;
 
-    fn get_completions(&self, uri: &Url, text: &str, position: Position) -> Vec<CompletionItem> {
-        let pre_position = Position {
-            line: position.line,
-            character: position.character - 1,
-        };
+                let mut including = paths::to_path(&uri).into_iter().collect();
+                let mut unresolved = Vec::new();
+                let content = self
+                    .catch_panic("inlining modules", || {
+                        self.get_inlined_text(&uri, &text, &mut including, &mut unresolved)
+                    })
+                    .await
+                    .unwrap_or_else(|| Err("Internal error".to_string()))
+                    .map_err(Error::invalid_params)?;
+                Ok(Some(json!({
+                    "uri": inlined_uri,
+                    "content": content,
+                    "unresolved": unresolved,
+                })))
+            }
+            commands::AST => {
+                let uri = match params.arguments.first().and_then(Value::as_str) {
+                    Some(uri) => Url::parse(uri).map_err(|_| Error::invalid_params("Bad uri"))?,
+                    None => return Err(Error::invalid_params("Missing document uri")),
+                };
+                let text = self
+                    .get_text(&uri)
+                    .await
+                    .ok_or_else(|| Error::invalid_params("Unknown document"))?;
 
-        if let Some(tokens) = self.language.tokenize(uri, text) {
-            if let Some(token) = lsp_utils::get_token(tokens, pre_position) {
-                file_dbg("get_completions_token", &token);
-                // TODO eliminate the need for this by improving get_token()
-                let module_parts: Vec<&str> = token.rsplitn(2, "::").collect();
-
-                if let Some(module_name) = module_parts.get(1) {
-                    file_dbg("get_completions_module_name", module_name);
-                    return self
-                        .language
-                        .functions(uri, module_name)
-                        .iter()
-                        .map(|function_name| {
-                            let mut detail = None;
-                            let mut documentation = None;
-                            let mut insert_text = None;
-                            if let Some(function_doc) = self
-                                .language
-                                .function_doc(uri, &format!("{}::{}", module_name, function_name))
-                            {
-                                file_dbg("get_completions_function_doc", &function_doc.description);
-                                detail = Some(function_doc.signature.to_string());
-                                documentation = Some(Documentation::MarkupContent(MarkupContent {
-                                    kind: MarkupKind::Markdown,
-                                    value: function_doc.description.clone(),
-                                }));
-                                let args_snippet = function_doc
-                                    .signature
-                                    .args
-                                    .iter()
-                                    .enumerate()
-                                    // produces snippet text like ${1:arg} (where arg is the placeholder text)
-                                    // https://microsoft.github.io/language-server-protocol/specifications/specification-3-14/#snippet-syntax
-                                    .map(|(i, arg)| format!("${{{}:{}}}", i + 1, arg))
-                                    .collect::<Vec<String>>()
-                                    .join(", ");
-                                insert_text = Some(format!("{}({})", function_name, args_snippet));
-                            };
-                            CompletionItem {
-                                label: function_name.to_string(),
-                                kind: Some(CompletionItemKind::Function),
-                                detail,
-                                documentation,
-                                insert_text,
-                                insert_text_format: Some(InsertTextFormat::Snippet),
-                                ..CompletionItem::default()
-                            }
-                        })
-                        .collect();
+                let result = self
+                    .catch_panic("building the syntax tree", || {
+                        self.language.ast(&uri, &text)
+                    })
+                    .await
+                    .flatten()
+                    .unwrap_or_else(|| Err("Internal error".to_string()));
+                match result {
+                    Ok(ast) => Ok(Some(json!({ "ast": ast }))),
+                    Err(e) => Ok(Some(json!({ "error": e }))),
                 }
             }
-        }
+            commands::WILL_RENAME_FILES => {
+                let renames: Vec<(PathBuf, PathBuf)> = params
+                    .arguments
+                    .first()
+                    .and_then(|args| args.get("files"))
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| Error::invalid_params("Missing files"))?
+                    .iter()
+                    .filter_map(|file| {
+                        let path =
+                            |key: &str| paths::to_path(&Url::parse(file.get(key)?.as_str()?).ok()?);
+                        Some((path("oldUri")?, path("newUri")?))
+                    })
+                    .collect();
 
-        vec![]
-    }
+                let annotate = params
+                    .arguments
+                    .first()
+                    .and_then(|args| args.get("annotate"))
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false);
 
-    fn get_hover_content(
-        &self,
-        uri: &Url,
-        text: &str,
-        position: Position,
-    ) -> Option<MarkupContent> {
-        // TODO merge the repeated tokenize operation with get_completions()?
-        if let Some(tokens) = self.language.tokenize(uri, text) {
-            if let Some(token) = lsp_utils::get_token(tokens, position) {
-                file_dbg("get_hover_content_token", &token);
-                if let Some(function_doc) = self.language.function_doc(uri, &token) {
-                    file_dbg("get_hover_content_function_doc", &function_doc.description);
-                    return Some(MarkupContent {
-                        kind: MarkupKind::Markdown,
-                        value: function_doc.to_string(),
-                    });
+                let edit = self.get_module_rename_edit(&renames).await;
+                match edit.changes {
+                    // touching other modules, so worth a look before applying
+                    Some(changes) if annotate && changes.len() > 1 => {
+                        let description =
+                            format!("Updates the use statements of {} documents", changes.len());
+                        Ok(Some(lsp_utils::annotated_workspace_edit(
+                            changes,
+                            "Update module paths",
+                            &description,
+                        )))
+                    }
+                    changes => Ok(Some(json!(WorkspaceEdit::new(changes.unwrap_or_default())))),
                 }
             }
+            _ => Err(Error::invalid_params(format!(
+                "Unknown command: {}",
+                params.command
+            ))),
         }
-        None
     }
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let roots = match (params.workspace_folders, params.root_uri) {
+            (Some(folders), _) => folders.into_iter().map(|f| f.uri).collect(),
+            (None, Some(root_uri)) => vec![root_uri],
+            (None, None) => vec![],
+        };
+        self.workspace.lock().await.roots = roots.iter().filter_map(paths::to_path).collect();
+
+        if let Some(pid) = params.process_id {
+            watchdog::watch(pid);
+        }
+
+        let text_document = params.capabilities.text_document.as_ref();
+        let has_markdown = |formats: Option<&Vec<MarkupKind>>| {
+            formats.map_or(false, |formats| formats.contains(&MarkupKind::Markdown))
+        };
+        let hover_formats = text_document
+            .and_then(|t| t.hover.as_ref())
+            .and_then(|hover| hover.content_format.as_ref());
+        self.supports_markdown_hover
+            .store(has_markdown(hover_formats), Ordering::Relaxed);
+        let completion_item = text_document
+            .and_then(|t| t.completion.as_ref())
+            .and_then(|completion| completion.completion_item.as_ref());
+        self.supports_markdown_documentation.store(
+            has_markdown(completion_item.and_then(|item| item.documentation_format.as_ref())),
+            Ordering::Relaxed,
+        );
+        self.supports_snippets.store(
+            completion_item
+                .and_then(|item| item.snippet_support)
+                .unwrap_or(false),
+            Ordering::Relaxed,
+        );
+        self.supports_hierarchical_symbols.store(
+            text_document
+                .and_then(|t| t.document_symbol.as_ref())
+                .and_then(|symbol| symbol.hierarchical_document_symbol_support)
+                .unwrap_or(false),
+            Ordering::Relaxed,
+        );
+
+        let workspace_edit = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.workspace_edit.as_ref());
+        self.supports_create_files.store(
+            workspace_edit.map_or(false, |edit| {
+                edit.document_changes.unwrap_or(false)
+                    && edit
+                        .resource_operations
+                        .as_ref()
+                        .map_or(false, |operations| {
+                            operations.contains(&ResourceOperationKind::Create)
+                        })
+            }),
+            Ordering::Relaxed,
+        );
+
+        let supports_progress = params
+            .capabilities
+            .window
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false);
+        self.supports_progress
+            .store(supports_progress, Ordering::Relaxed);
+
+        if let Some(options) = &params.initialization_options {
+            self.set_config(Config::from_json(options));
+            self.apply_docs_path().await;
+        }
+
+        #[allow(unused_mut)]
+        let mut commands: Vec<String> = commands::ALL.iter().map(|c| c.to_string()).collect();
+        #[cfg(feature = "runtime")]
+        commands.extend(commands::RUNTIME.iter().map(|c| c.to_string()));
+
         Ok(InitializeResult {
             server_info: Some(ServerInfo {
                 name: "tremor-language-server".to_string(),
                 version: Some(env!("CARGO_PKG_VERSION").to_string()),
             }),
             capabilities: ServerCapabilities {
-                code_action_provider: None,
-                code_lens_provider: None, /*Some(CodeLensOptions {
-                                              resolve_provider: None,
-                                          }),*/
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                            CodeActionKind::REFACTOR_EXTRACT,
+                            CodeActionKind::REFACTOR_REWRITE,
+                        ]),
+                        work_done_progress_options: WorkDoneProgressOptions::default(),
+                    },
+                )),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: None,
+                }),
                 color_provider: None,
                 completion_provider: Some(CompletionOptions {
                     resolve_provider: None,
-                    trigger_characters: Some(vec![":".to_string()]),
+                    trigger_characters: Some(vec![
+                        ":".to_string(),
+                        "[".to_string(),
+                        "$".to_string(),
+                    ]),
                     work_done_progress_options: WorkDoneProgressOptions::default(),
                 }),
                 declaration_provider: None,
-                definition_provider: None,
+                definition_provider: Some(true),
                 document_formatting_provider: None,
-                document_highlight_provider: None,
-                document_link_provider: None,
+                document_highlight_provider: Some(true),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(false),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
                 document_on_type_formatting_provider: None,
                 document_range_formatting_provider: None,
-                document_symbol_provider: None,
-                execute_command_provider: None,
-                experimental: None,
+                document_symbol_provider: Some(true),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands,
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
+                // commands standing in for requests that are not part of the protocol
+                experimental: Some(json!({ "astCommand": commands::AST })),
                 selection_range_provider: None,
-                folding_range_provider: None,
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
                 implementation_provider: None,
                 references_provider: None,
-                rename_provider: None,
-                signature_help_provider: None,
+                rename_provider: Some(RenameProviderCapability::Simple(true)),
+                signature_help_provider: Some(SignatureHelpOptions {
+                    trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
+                    retrigger_characters: None,
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
                     TextDocumentSyncKind::Full,
                 )),
                 type_definition_provider: None,
-                workspace_symbol_provider: None,
+                workspace_symbol_provider: Some(true),
                 workspace: Some(WorkspaceCapability {
                     workspace_folders: Some(WorkspaceFolderCapability {
                         supported: Some(true),
@@ -236,34 +2730,423 @@ impl LanguageServer for Backend {
         self.client
             .log_message(MessageType::Info, "Initialized Trill!")
             .await;
-    }
 
-    // TODO do more here (as appropriate). manadatory implementations for the trait
+        // so that we can pick up manifest edits
+        let watchers = project::MANIFEST_FILES
+            .iter()
+            .map(|name| FileSystemWatcher {
+                glob_pattern: format!("**/{}", name),
+                kind: None,
+            })
+            .collect();
+        let registration = Registration {
+            id: "tremor-project-manifests".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
+                watchers,
+            })
+            .ok(),
+        };
+        // and keep the workspace index in step with files created or deleted outside of the
+        // editor (or without it telling us, as file operations need a newer protocol version)
+        let file_watchers = workspace::FILE_EXTENSIONS
+            .iter()
+            .map(|extension| FileSystemWatcher {
+                glob_pattern: format!("**/*.{}", extension),
+                kind: Some(WatchKind::Create | WatchKind::Delete),
+            })
+            .collect();
+        let file_registration = Registration {
+            id: "tremor-files".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
+                watchers: file_watchers,
+            })
+            .ok(),
+        };
+        if let Err(e) = self
+            .client
+            .register_capability(vec![registration, file_registration])
+            .await
+        {
+            self.client
+                .log_message(
+                    MessageType::Warning,
+                    format!("Failed to watch workspace files: {}", e),
+                )
+                .await;
+        }
+
+        self.load_projects().await;
+        self.index_workspace().await;
+    }
 
     async fn shutdown(&self) -> Result<()> {
         file_dbg("shutdown", "shutdown");
+        self.check_running()?;
+        // no diagnostics get published from here on, and the ones underway are waited for
+        self.shut_down.store(true, Ordering::SeqCst);
+        let _gate = self.diagnostics_gate.write().await;
+
+        // keeps what was re-indexed since (eg: on file changes) for the next start
+        let files: Vec<PathBuf> = self
+            .workspace
+            .lock()
+            .await
+            .files
+            .keys()
+            .filter_map(paths::to_path)
+            .collect();
+        self.save_index_cache(&files).await;
+        self.metrics.flush().await;
         Ok(())
     }
 
-    async fn symbol(&self, _: WorkspaceSymbolParams) -> Result<Option<Vec<SymbolInformation>>> {
-        file_dbg("symbol", "symbol");
-        Ok(None)
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        file_dbg("code_lens", "code_lens");
+        self.check_running()?;
+        let _timer = self
+            .metrics
+            .request("textDocument/codeLens")
+            .document(&params.text_document.uri);
+
+        let uri = params.text_document.uri;
+        let text = match self.get_tokenizable_text(&uri).await {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        if self.config_for(&uri).is_large(text.len() as u64) {
+            return Ok(None);
+        }
+        let tokens = match self.language.tokenize(&uri, &text) {
+            Some(tokens) => tokens,
+            None => return Ok(None),
+        };
+
+        // trickle queries are the pipeline, so the summary goes at the top
+        let mut lenses: Vec<CodeLens> = pipeline::summary(&text, &tokens)
+            .map(|summary| CodeLens {
+                range: Range::default(),
+                command: Some(Command {
+                    title: format!("Pipeline: {}", summary),
+                    command: commands::PIPELINE_GRAPH.to_string(),
+                    arguments: Some(vec![json!(uri)]),
+                }),
+                data: None,
+            })
+            .into_iter()
+            .collect();
+        // inlining only changes queries using modules
+        if !lenses.is_empty() && !imports::parse(&tokens).is_empty() {
+            lenses.push(CodeLens {
+                range: Range::default(),
+                command: Some(Command {
+                    title: "Show with modules inlined (constants not folded)".to_string(),
+                    command: commands::SHOW_INLINED_MODULES.to_string(),
+                    arguments: Some(vec![json!(uri)]),
+                }),
+                data: None,
+            });
+        }
+        Ok(Some(lenses))
+    }
+
+    async fn document_link(&self, params: DocumentLinkParams) -> Result<Option<Vec<DocumentLink>>> {
+        file_dbg("document_link", "document_link");
+        self.check_running()?;
+        let mut timer = self
+            .metrics
+            .request("textDocument/documentLink")
+            .document(&params.text_document.uri);
+
+        let uri = params.text_document.uri;
+        if test_files::kind(&uri).is_none() {
+            return Ok(None);
+        }
+        let text = match self.get_text(&uri).await {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        Ok(timer.caught(
+            self.catch_panic("computing document links", || {
+                test_files::links(&uri, &text)
+            })
+            .await,
+        ))
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        file_dbg("document_symbol", "document_symbol");
+        self.check_running()?;
+        let _timer = self
+            .metrics
+            .request("textDocument/documentSymbol")
+            .document(&params.text_document.uri);
+        let uri = params.text_document.uri;
+        let text = match self.get_tokenizable_text(&uri).await {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        if self.config_for(&uri).is_large(text.len() as u64) {
+            return Ok(None);
+        }
+        let tokens = match self.language.tokenize(&uri, &text) {
+            Some(tokens) => tokens,
+            None => return Ok(None),
+        };
+
+        let symbols = symbols::outline(&text, &tokens);
+        if self.supports_hierarchical_symbols.load(Ordering::Relaxed) {
+            Ok(Some(DocumentSymbolResponse::Nested(symbols)))
+        } else {
+            Ok(Some(DocumentSymbolResponse::Flat(symbols::flatten(
+                &uri, symbols,
+            ))))
+        }
+    }
+
+    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+        file_dbg("folding_range", "folding_range");
+        self.check_running()?;
+        let mut timer = self
+            .metrics
+            .request("textDocument/foldingRange")
+            .document(&params.text_document.uri);
+        let uri = params.text_document.uri;
+        let text = match self.get_tokenizable_text(&uri).await {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        if self.config_for(&uri).is_large(text.len() as u64) {
+            return Ok(None);
+        }
+        let tokens = match self.language.tokenize(&uri, &text) {
+            Some(tokens) => tokens,
+            None => return Ok(None),
+        };
+
+        Ok(timer.caught(
+            self.catch_panic("computing folding ranges", || {
+                regions::folding_ranges(&text, &tokens)
+            })
+            .await,
+        ))
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<SymbolInformation>>> {
+        file_dbg("symbol", &params.query);
+        self.check_running()?;
+        let _timer = self.metrics.request("workspace/symbol");
+        let query = workspace::SymbolQuery::parse(&params.query);
+        let uris: Vec<Url> = self.workspace.lock().await.files.keys().cloned().collect();
+        let mut matches = Vec::new();
+        for uris in uris.chunks(SYMBOL_SEARCH_CHUNK) {
+            // lets a cancellation of the request take effect
+            tokio::task::yield_now().await;
+            matches.extend(self.workspace.lock().await.matches(&query, uris));
+        }
+        Ok(Some(workspace::rank(matches)))
     }
 
     async fn document_highlight(
         &self,
-        _: DocumentHighlightParams,
+        params: DocumentHighlightParams,
     ) -> Result<Option<Vec<DocumentHighlight>>> {
         file_dbg("document_highlight", "document_highlight");
-        Ok(None)
+        self.check_running()?;
+        let uri = params.text_document_position_params.text_document.uri;
+        let _timer = self
+            .metrics
+            .request("textDocument/documentHighlight")
+            .document(&uri);
+        let position = params.text_document_position_params.position;
+        // the ranges need to be the ones of the text as it is
+        let text = match self.get_text(&uri).await {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        if self.config_for(&uri).is_large(text.len() as u64) {
+            return Ok(None);
+        }
+        let tokens = match self.language.tokenize(&uri, &text) {
+            Some(tokens) => tokens,
+            None => return Ok(None),
+        };
+
+        // `match`/`of`/`case`/`end`, and other keywords opening blocks with their `end`
+        let highlights = blocks::keyword_highlights(&text, &tokens, position).map(|ranges| {
+            ranges
+                .into_iter()
+                .map(|range| DocumentHighlight {
+                    range,
+                    kind: Some(DocumentHighlightKind::Text),
+                })
+                .collect()
+        });
+        Ok(highlights)
     }
 
-    async fn execute_command(&self, _: ExecuteCommandParams) -> Result<Option<Value>> {
-        file_dbg("execute", "execute");
-        self.client
-            .log_message(MessageType::Info, "executing command!")
-            .await;
-        Ok(None)
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        file_dbg("execute", &params.command);
+        self.check_running()?;
+        // commands are metrics labels, which should not grow with whatever clients send
+        let label = if commands::is_known(&params.command) {
+            params.command.as_str()
+        } else {
+            "unknown"
+        };
+        let mut timer = self.metrics.request(label);
+        // most commands take the uri of a document first, or along with other arguments
+        if let Some(uri) = params
+            .arguments
+            .first()
+            .and_then(|arg| arg.as_str().or_else(|| arg.get("uri")?.as_str()))
+            .and_then(|uri| Url::parse(uri).ok())
+        {
+            timer = timer.document(&uri);
+        }
+
+        let result = self.run_command(params).await;
+        // commands answer with `{"error": ...}` for what went wrong with the work itself
+        let failed = match &result {
+            Ok(Some(value)) => value.get("error").is_some(),
+            Ok(None) => false,
+            Err(_) => true,
+        };
+        if failed {
+            timer.fail();
+        }
+        result
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        file_dbg("code_action", "code_action");
+        self.check_running()?;
+        let mut timer = self
+            .metrics
+            .request("textDocument/codeAction")
+            .document(&params.text_document.uri);
+
+        let wants = |kind: CodeActionKind| match &params.context.only {
+            Some(only) => only.iter().any(|k| kind.as_str().starts_with(k.as_str())),
+            None => true,
+        };
+
+        let uri = &params.text_document.uri;
+        let text = match self.get_text(uri).await {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        tokio::task::yield_now().await;
+
+        let mut actions = Vec::new();
+
+        if wants(CodeActionKind::QUICKFIX) {
+            actions.extend(
+                timer
+                    .caught(
+                        self.catch_panic("computing quick fixes", || {
+                            self.get_quick_fixes(uri, &text, &params.context.diagnostics)
+                        })
+                        .await,
+                    )
+                    .unwrap_or_default(),
+            );
+        }
+
+        if wants(CodeActionKind::SOURCE_ORGANIZE_IMPORTS) {
+            let edit = timer
+                .caught(
+                    self.catch_panic("organizing imports", || {
+                        self.get_organize_imports_edit(uri, &text)
+                    })
+                    .await,
+                )
+                .flatten();
+            if let Some(edit) = edit {
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: "Organize imports".to_string(),
+                    kind: Some(CodeActionKind::SOURCE_ORGANIZE_IMPORTS),
+                    edit: Some(edit),
+                    ..CodeAction::default()
+                }));
+            }
+        }
+
+        // functions only go in scripts, not queries
+        let has_functions = self.language.statement_keywords().contains(&"fn");
+        if wants(CodeActionKind::REFACTOR_EXTRACT)
+            && has_functions
+            && params.range.start != params.range.end
+        {
+            let edits = timer
+                .caught(
+                    self.catch_panic("extracting a function", || {
+                        let tokens = self.language.tokenize(uri, &text)?;
+                        extract::function(&text, &tokens, params.range)
+                    })
+                    .await,
+                )
+                .flatten();
+            if let Some(edits) = edits {
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: "Extract into function".to_string(),
+                    kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+                    edit: Some(lsp_utils::workspace_edit(uri, edits)),
+                    ..CodeAction::default()
+                }));
+            }
+        }
+
+        if wants(CodeActionKind::REFACTOR_EXTRACT)
+            && self.supports_create_files.load(Ordering::Relaxed)
+        {
+            let roots = self.workspace.lock().await.roots.clone();
+            let moved = timer
+                .caught(
+                    self.catch_panic("moving into a module", || {
+                        self.get_move_to_module_edit(uri, &text, params.range, &roots)
+                    })
+                    .await,
+                )
+                .flatten();
+            if let Some((title, edit)) = moved {
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title,
+                    kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+                    edit: Some(edit),
+                    ..CodeAction::default()
+                }));
+            }
+        }
+
+        if wants(CodeActionKind::REFACTOR_REWRITE) {
+            let edit = timer
+                .caught(
+                    self.catch_panic("converting to interpolation", || {
+                        let tokens = self.language.tokenize(uri, &text)?;
+                        interpolation::from_concatenation(&text, &tokens, params.range.start)
+                    })
+                    .await,
+                )
+                .flatten();
+            if let Some(edit) = edit {
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: "Convert to string interpolation".to_string(),
+                    kind: Some(CodeActionKind::REFACTOR_REWRITE),
+                    edit: Some(lsp_utils::workspace_edit(uri, vec![edit])),
+                    ..CodeAction::default()
+                }));
+            }
+        }
+
+        Ok(Some(actions))
     }
 
     // backend state updates on text edits and reporting of diagnostics
@@ -273,78 +3156,382 @@ impl LanguageServer for Backend {
         file_dbg("didOpen_language", &params.text_document.language_id);
 
         let uri = params.text_document.uri;
-        if let Ok(path) = uri.to_file_path() {
-            // TODO pull this from params.text_document.text
-            // TODO cleanup
-            if let Ok(text) = fs::read_to_string(path) {
-                self.update(uri.clone(), &text).await;
-                let d = self.get_diagnostics(&uri, &text);
-                self.client.publish_diagnostics(uri, d, None).await;
+        let _gate = self.diagnostics_gate.read().await;
+        // scratchpads have no file extension to go by, but the language id
+        let is_other_language = if scratch::is_scratch(&uri) {
+            scratch::language(&uri, Some(params.text_document.language_id.as_str()))
+                != language::file_extension(self.language.name())
+        } else {
+            self.is_other_language(&uri).await
+        };
+        if self.is_shut_down() || is_other_language {
+            return;
+        }
+        // the client has the text as the user sees it, which can differ from the file (eg:
+        // restored unsaved edits). the file only stands in for clients sending no text.
+        let mut text = params.text_document.text;
+        if text.is_empty() {
+            if let Some(file_text) =
+                paths::to_path(&uri).and_then(|path| fs::read_to_string(path).ok())
+            {
+                text = file_text;
             }
         }
+
+        self.update(uri.clone(), &text, Some(params.text_document.version))
+            .await;
+        let generation = self.config_generation.load(Ordering::Relaxed);
+        let start = Instant::now();
+        let d = self
+            .catch_panic("computing diagnostics", || {
+                self.get_diagnostics(&uri, &text)
+            })
+            .await
+            .unwrap_or_default();
+        let took = start.elapsed();
+        self.update_parsed_text(&uri, &text, &d).await;
+        self.set_published(&uri, cache::content_hash(&text), generation)
+            .await;
+        self.client.publish_diagnostics(uri.clone(), d, None).await;
+        self.send_parse_stats(&uri, &text, Some(params.text_document.version), took)
+            .await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         file_dbg("didChange", "didChange");
         // TODO cleanup
         let uri = params.text_document.uri;
+        let version = params.text_document.version;
         let text = &params.content_changes[0].text;
-        self.update(uri.clone(), text).await;
-        self.client
-            .publish_diagnostics(uri.clone(), self.get_diagnostics(&uri, text), None)
-            .await;
+        if self.is_other_language(&uri).await {
+            return;
+        }
+        // scratchpads of the other language were never opened here
+        if scratch::is_scratch(&uri) && self.get_text(&uri).await.is_none() {
+            return;
+        }
+        self.update(uri.clone(), text, version).await;
+        let _gate = self.diagnostics_gate.read().await;
+        if self.is_shut_down() {
+            return;
+        }
+        // editors send changes that leave the text as it was too
+        if self.is_published(&uri).await {
+            return;
+        }
+        // with edits coming in quickly, only the diagnostics of the last one are computed
+        tokio::task::yield_now().await;
+        if !self.is_latest(&uri, version).await {
+            return;
+        }
+
+        let generation = self.config_generation.load(Ordering::Relaxed);
+        let start = Instant::now();
+        let diagnostics = self
+            .catch_panic("computing diagnostics", || self.get_diagnostics(&uri, text))
+            .await
+            .unwrap_or_default();
+        let took = start.elapsed();
+        self.update_parsed_text(&uri, text, &diagnostics).await;
+        // gives newer edits a chance to come in, so that we don't publish stale diagnostics
+        tokio::task::yield_now().await;
+        if self.is_latest(&uri, version).await {
+            self.set_published(&uri, cache::content_hash(text), generation)
+                .await;
+            self.client
+                .publish_diagnostics(uri.clone(), diagnostics, version)
+                .await;
+            self.send_parse_stats(&uri, text, version, took).await;
+        }
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        file_dbg("didChangeConfiguration", &params.settings.to_string());
+        self.set_config(Config::from_json(&params.settings));
+        self.apply_docs_path().await;
+        self.load_projects().await;
+        self.publish_all_diagnostics().await;
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        file_dbg("didChangeWatchedFiles", "didChangeWatchedFiles");
+        let manifest_changed = params.changes.iter().any(|change| {
+            paths::to_path(&change.uri).map_or(false, |path| Project::is_manifest(&path))
+        });
+        if manifest_changed {
+            self.load_projects().await;
+        }
+
+        let mut files_changed = false;
+        {
+            let mut workspace = self.workspace.lock().await;
+            let roots = workspace.roots.clone();
+            for change in &params.changes {
+                let path = match paths::to_path(&change.uri) {
+                    Some(path) => path,
+                    None => continue,
+                };
+                let mapped = roots.iter().any(|root| match path.strip_prefix(root) {
+                    Ok(relative) => self
+                        .config_for(&change.uri)
+                        .language_for(relative)
+                        .is_some(),
+                    Err(_) => false,
+                });
+                if !mapped && !workspace::is_tremor_file(&path) {
+                    continue;
+                }
+                let excluded = roots.iter().any(|root| match path.strip_prefix(root) {
+                    Ok(relative) => workspace::is_excluded(relative, &self.exclusions(root)),
+                    Err(_) => false,
+                });
+                if excluded || self.is_large_file(&path) {
+                    continue;
+                }
+                if change.typ == FileChangeType::Deleted {
+                    workspace.files.remove(&change.uri);
+                } else {
+                    workspace.index_file(&path);
+                }
+                files_changed = true;
+            }
+        }
+
+        // so that eg. "module not found" errors come and go with the module files
+        if manifest_changed || files_changed {
+            self.publish_all_diagnostics().await;
+        }
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        file_dbg("didChangeWorkspaceFolders", "didChangeWorkspaceFolders");
+        let to_paths = |folders: Vec<WorkspaceFolder>| -> Vec<PathBuf> {
+            folders
+                .into_iter()
+                .filter_map(|folder| paths::to_path(&folder.uri))
+                .collect()
+        };
+        let added = to_paths(params.event.added);
+        let removed = to_paths(params.event.removed);
+
+        {
+            let mut workspace = self.workspace.lock().await;
+            workspace.roots.retain(|root| !removed.contains(root));
+            for root in added {
+                if !workspace.roots.contains(&root) {
+                    workspace.roots.push(root);
+                }
+            }
+            // forget the files from the removed folders
+            let roots = workspace.roots.clone();
+            let stale: Vec<Url> = workspace
+                .files
+                .keys()
+                .filter(|uri| match paths::to_path(uri) {
+                    Some(path) => !roots.iter().any(|root| path.starts_with(root)),
+                    None => false,
+                })
+                .cloned()
+                .collect();
+            for uri in stale {
+                workspace.files.remove(&uri);
+            }
+        }
+
+        self.load_projects().await;
+        self.index_workspace().await;
+        self.publish_all_diagnostics().await;
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
         file_dbg("didClose", "didClose");
-        // TODO can cleanup backend state here
-        self.client
-            .publish_diagnostics(params.text_document.uri, vec![], None)
-            .await;
+        let uri = params.text_document.uri;
+        // closed documents are not republished (eg. after settings changes)
+        self.state.lock().await.remove(&uri);
+        // nothing is left of a closed scratchpad to index or check, and unsaved edits of files
+        // are dropped, leaving the file as it is on disk (if it's still there)
+        if scratch::is_scratch(&uri) {
+            self.workspace.lock().await.files.remove(&uri);
+        } else if test_files::kind(&uri).is_none() {
+            let mut workspace = self.workspace.lock().await;
+            if workspace.files.remove(&uri).is_some() {
+                if let Some(path) = paths::to_path(&uri) {
+                    if path.is_file() && !self.is_large_file(&path) {
+                        workspace.index_file(&path);
+                    }
+                }
+            }
+        }
+        self.client.publish_diagnostics(uri, vec![], None).await;
     }
 
     // other lsp features
 
     async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
         file_dbg("completion", "completion");
+        self.check_running()?;
+        let mut timer = self
+            .metrics
+            .request("textDocument/completion")
+            .document(&params.text_document_position.text_document.uri);
+
+        let uri = params.text_document_position.text_document.uri;
+        let (text, parsed_text) = match self.state.lock().await.get(&uri) {
+            Some(doc) => (doc.text.clone(), doc.parsed_text.clone()),
+            None => return Ok(None),
+        };
+        // lets a pending cancellation for this request take effect before the real work
+        tokio::task::yield_now().await;
+
+        let detail = self.config_for(&uri).completion_detail();
+        let completions = timer
+            .caught(
+                self.catch_panic("completing", || {
+                    let position = params.text_document_position.position;
+                    self.get_completions(&uri, &text, parsed_text.as_deref(), position)
+                        .into_iter()
+                        .map(|item| self.adapt_completion(item, detail))
+                        .collect::<Vec<_>>()
+                })
+                .await,
+            )
+            .unwrap_or_default();
+        Ok(Some(CompletionResponse::Array(completions)))
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        file_dbg("goto_definition", "goto_definition");
+        self.check_running()?;
+        let mut timer = self
+            .metrics
+            .request("textDocument/definition")
+            .document(&params.text_document_position_params.text_document.uri);
+        let uri = params.text_document_position_params.text_document.uri;
+        let text = match self.get_text(&uri).await {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        // lets a pending cancellation for this request take effect before the real work
+        tokio::task::yield_now().await;
+
+        Ok(timer
+            .caught(
+                self.catch_panic("finding the definition", || {
+                    self.get_definition(&uri, &text, params.text_document_position_params.position)
+                })
+                .await,
+            )
+            .flatten()
+            .map(GotoDefinitionResponse::Scalar))
+    }
+
+    async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+        file_dbg("signature_help", "signature_help");
+        self.check_running()?;
+        let mut timer = self
+            .metrics
+            .request("textDocument/signatureHelp")
+            .document(&params.text_document_position_params.text_document.uri);
+        let uri = params.text_document_position_params.text_document.uri;
+        let text = match self.get_text(&uri).await {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        // lets a pending cancellation for this request take effect before the real work
+        tokio::task::yield_now().await;
 
-        // TODO remove unwraps
-        let state = self.state.lock().await;
-        let doc = state
-            .get(&params.text_document_position.text_document.uri)
-            .unwrap();
+        Ok(timer
+            .caught(
+                self.catch_panic("computing signature help", || {
+                    self.get_signature_help(
+                        &uri,
+                        &text,
+                        params.text_document_position_params.position,
+                    )
+                })
+                .await,
+            )
+            .flatten())
+    }
+
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        file_dbg("rename", &params.new_name);
+        self.check_running()?;
+        let mut timer = self
+            .metrics
+            .request("textDocument/rename")
+            .document(&params.text_document_position.text_document.uri);
         let uri = params.text_document_position.text_document.uri;
+        let text = match self.get_text(&uri).await {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        let roots = self.workspace.lock().await.roots.clone();
 
-        Ok(Some(CompletionResponse::Array(self.get_completions(
-            &uri,
-            &doc.text,
-            params.text_document_position.position,
-        ))))
+        let result = timer
+            .caught(
+                self.catch_panic("renaming", || {
+                    self.get_rename_edit(
+                        &uri,
+                        &text,
+                        params.text_document_position.position,
+                        &params.new_name,
+                        &roots,
+                    )
+                })
+                .await,
+            )
+            .unwrap_or(Ok(None));
+        result.map_err(|e| {
+            timer.fail();
+            Error::invalid_params(e)
+        })
     }
 
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
         file_dbg("hover", "hover");
-        // TODO remove unwraps
-        // TODO bake state lookup in self
-        let state = self.state.lock().await;
+        self.check_running()?;
+        let mut timer = self
+            .metrics
+            .request("textDocument/hover")
+            .document(&params.text_document_position_params.text_document.uri);
         let uri = params.text_document_position_params.text_document.uri;
-        let doc = state.get(&uri).unwrap();
+        let text = match self.get_text(&uri).await {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        // lets a pending cancellation for this request take effect before the real work
+        tokio::task::yield_now().await;
 
-        let result = self
-            .get_hover_content(
-                &uri,
-                &doc.text,
-                params.text_document_position_params.position,
+        Ok(timer
+            .caught(
+                self.catch_panic("computing hover", || {
+                    self.get_hover_content(
+                        &uri,
+                        &text,
+                        params.text_document_position_params.position,
+                    )
+                    .map(|hover| self.adapt_hover(hover))
+                })
+                .await,
             )
-            .map(|hover_content| Hover {
-                contents: HoverContents::Markup(hover_content),
-                range: None,
-            });
-
-        Ok(result)
+            .flatten())
     }
 }
 
+// the events of a newline delimited json file
+fn read_events(events_file: &str) -> std::result::Result<Vec<String>, String> {
+    Ok(fs::read_to_string(events_file)
+        .map_err(|e| format!("Failed to read {}: {}", events_file, e))?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(String::from)
+        .collect())
+}
+
 // TODO remove. just for testing right now
 pub fn file_dbg(name: &str, content: &str) {
     use std::fs::File;
@@ -0,0 +1,328 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// quick structural checks (balanced `end`s, terminated strings) that work on partial or
+// broken documents as well, where the parser stops at the first error
+
+use crate::blocks;
+use crate::language::{Token, TokenSpan, Tokenizer};
+use crate::lints::{Lint, UNBALANCED_BLOCK, UNTERMINATED_STRING};
+use crate::lsp_utils;
+use tower_lsp::lsp_types::*;
+
+pub fn check(text: &str) -> Vec<Lint> {
+    let strings = unterminated_strings(text);
+    // tokens up to the first lexer error, if any
+    let tokens: Vec<TokenSpan> = Tokenizer::new(text)
+        .take_while(Result::is_ok)
+        .filter_map(Result::ok)
+        .collect();
+    // blocks are expected to be open still, where the lexer gave up on the rest
+    let is_complete = strings.is_empty() && Tokenizer::new(text).all(|t| t.is_ok());
+
+    let mut lints = strings;
+    lints.extend(unbalanced_blocks(text, &tokens, is_complete));
+    lints
+}
+
+fn lint(code: &'static str, range: Range, message: String) -> Lint {
+    Lint {
+        code,
+        range,
+        severity: DiagnosticSeverity::Error,
+        message,
+        fixes: vec![],
+    }
+}
+
+// an open construct, waiting for its `end`
+struct Opener<'a, 'input> {
+    token: &'a Token<'input>,
+    range: Range,
+    // constructs like `match` need an `of` before their cases
+    needs_of: bool,
+    has_of: bool,
+}
+
+fn unbalanced_blocks(text: &str, tokens: &[TokenSpan], is_complete: bool) -> Vec<Lint> {
+    let tokens = blocks::significant(tokens);
+
+    let mut lints = Vec::new();
+    let mut stack: Vec<Opener> = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        let range = lsp_utils::token_range(text, t);
+        // within a patch, `merge` is one of the operations rather than an expression
+        let in_patch = matches!(stack.last(), Some(o) if *o.token == Token::Patch && o.has_of);
+
+        match &t.value {
+            Token::Merge if in_patch => (),
+            v if blocks::opens_expression(v) => stack.push(Opener {
+                token: v,
+                range,
+                needs_of: true,
+                has_of: false,
+            }),
+            Token::Fn | Token::Module | Token::Define if blocks::has_body(&tokens, i) => stack
+                .push(Opener {
+                    token: &t.value,
+                    range,
+                    needs_of: false,
+                    has_of: false,
+                }),
+            Token::Of => match stack.last_mut() {
+                Some(opener) if !opener.has_of && *opener.token != Token::Module => {
+                    opener.has_of = true
+                }
+                _ => lints.push(lint(
+                    UNBALANCED_BLOCK,
+                    range,
+                    "Unexpected `of`, without a `match`, `for`, `patch` or `merge` before it"
+                        .to_string(),
+                )),
+            },
+            Token::Case => {
+                let in_cases = matches!(
+                    stack.last(),
+                    Some(o) if o.has_of && matches!(o.token, Token::Match | Token::For | Token::Fn)
+                );
+                if !in_cases {
+                    lints.push(lint(
+                        UNBALANCED_BLOCK,
+                        range,
+                        "Unexpected `case`, outside of a `match ... of` or `for ... of`"
+                            .to_string(),
+                    ));
+                }
+            }
+            Token::End => match stack.pop() {
+                Some(opener) if opener.needs_of && !opener.has_of => lints.push(lint(
+                    UNBALANCED_BLOCK,
+                    opener.range,
+                    format!("Missing `of` for this `{}`", opener.token),
+                )),
+                Some(_) => (),
+                None => lints.push(lint(
+                    UNBALANCED_BLOCK,
+                    range,
+                    "Unexpected `end`, there is nothing to close here".to_string(),
+                )),
+            },
+            _ => (),
+        }
+    }
+
+    if is_complete {
+        lints.extend(stack.into_iter().map(|opener| {
+            lint(
+                UNBALANCED_BLOCK,
+                opener.range,
+                format!("This `{}` is not closed with an `end`", opener.token),
+            )
+        }));
+    }
+
+    lints
+}
+
+// chars of the text along with their (lsp) positions, with every line ending in a `\n`
+pub fn positioned_chars(text: &str) -> Vec<(char, Position)> {
+    let mut chars = Vec::new();
+    for (line, line_text) in text.lines().enumerate() {
+        let mut character = 0;
+        for c in line_text.chars().chain(std::iter::once('\n')) {
+            chars.push((c, Position::new(line as u64, character)));
+            character += c.len_utf16() as u64;
+        }
+    }
+    chars
+}
+
+// strings and heredocs that are not closed by the end of the document
+fn unterminated_strings(text: &str) -> Vec<Lint> {
+    let chars = positioned_chars(text);
+    let starts_with = |i: usize, pattern: &str| {
+        pattern
+            .chars()
+            .enumerate()
+            .all(|(k, p)| chars.get(i + k).map(|(c, _)| *c) == Some(p))
+    };
+    let line_end = |i: usize| {
+        chars[i..]
+            .iter()
+            .find(|(c, _)| *c == '\n')
+            .map_or(chars[i].1, |(_, position)| *position)
+    };
+
+    let mut i = 0;
+    while i < chars.len() {
+        let (c, start) = chars[i];
+        match c {
+            '#' => {
+                // comments run to the end of the line
+                while i < chars.len() && chars[i].0 != '\n' {
+                    i += 1;
+                }
+            }
+            '`' => {
+                // quoted identifier
+                i += 1;
+                while i < chars.len() && chars[i].0 != '`' {
+                    i += 1;
+                }
+            }
+            '"' if starts_with(i, "\"\"\"") => {
+                match (i + 3..chars.len()).find(|k| starts_with(*k, "\"\"\"")) {
+                    Some(close) => i = close + 2,
+                    None => {
+                        return vec![lint(
+                            UNTERMINATED_STRING,
+                            Range::new(start, line_end(i)),
+                            "Unterminated heredoc, missing the closing `\"\"\"`".to_string(),
+                        )]
+                    }
+                }
+            }
+            '"' => match string_end(&chars, i + 1) {
+                Some(close) => i = close,
+                None => {
+                    return vec![lint(
+                        UNTERMINATED_STRING,
+                        Range::new(start, line_end(i)),
+                        "Unterminated string, missing the closing `\"`".to_string(),
+                    )]
+                }
+            },
+            _ => (),
+        }
+        i += 1;
+    }
+    vec![]
+}
+
+// index of the quote closing the string that starts at the index, skipping over escapes
+// and `#{...}` interpolations (which can have strings of their own)
+pub fn string_end(chars: &[(char, Position)], start: usize) -> Option<usize> {
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i].0 {
+            '\\' => i += 1,
+            '"' => return Some(i),
+            '#' if chars.get(i + 1).map(|(c, _)| *c) == Some('{') => {
+                let mut depth = 0;
+                i += 2;
+                while i < chars.len() {
+                    match chars[i].0 {
+                        '"' => i = string_end(chars, i + 1)?,
+                        '{' => depth += 1,
+                        '}' if depth == 0 => break,
+                        '}' => depth -= 1,
+                        _ => (),
+                    }
+                    i += 1;
+                }
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(text: &str) -> Vec<&'static str> {
+        check(text).into_iter().map(|lint| lint.code).collect()
+    }
+
+    #[test]
+    fn balanced_patch_merge() {
+        let text = "patch event of\n\
+                    \x20 merge \"a\" => {};\n\
+                    \x20 insert \"b\" => patch event.b of merge => {\"c\": 1} end;\n\
+                    \x20 upsert \"d\" => match event of case %{} => 1 default => 2 end\n\
+                    end;\n";
+        assert!(codes(text).is_empty());
+    }
+
+    #[test]
+    fn unclosed_patch() {
+        let text = "patch event of\n  merge \"a\" => {}\n";
+        let lints = check(text);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].code, UNBALANCED_BLOCK);
+        assert_eq!(lints[0].range.start, Position::new(0, 0));
+    }
+
+    #[test]
+    fn fn_without_body() {
+        let text = "intrinsic fn len(s) as string::len;\n\
+                    fn twice(x) with x * 2 end;\n\
+                    fn sign(x) of case 0 => 0 default => 1 end;\n";
+        assert!(codes(text).is_empty());
+
+        let text = "define passthrough operator pt;\n\
+                    define tumbling window w with interval = 1 end;\n\
+                    select event from in into out;\n";
+        assert!(codes(text).is_empty());
+    }
+
+    #[test]
+    fn brackets_in_strings_and_comments() {
+        let text = "# match end ) end\n\
+                    let a = \"end ) ] of case\";\n\
+                    let b = \"\"\"\n  match end\n\"\"\";\n\
+                    match a of case \"end\" => b default => null end # end\n";
+        assert!(codes(text).is_empty());
+
+        let text = "let a = \"end\";\nend\n";
+        let lints = check(text);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].range.start, Position::new(1, 0));
+    }
+
+    #[test]
+    fn interpolated_strings() {
+        let text = "let a = \"#{ \"end\" } and #{ {\"b\": 1}.b }\";\n";
+        assert!(codes(text).is_empty());
+        assert_eq!(
+            string_end(&positioned_chars(text), 9),
+            Some(text.find("\";").unwrap_or_default())
+        );
+
+        let text = "let a = \"#{ \"x\" }\n";
+        assert_eq!(codes(text), vec![UNTERMINATED_STRING]);
+    }
+
+    #[test]
+    fn unterminated_heredoc() {
+        let text = "let a = \"\"\"\n  text\n";
+        let lints = check(text);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].code, UNTERMINATED_STRING);
+        assert_eq!(
+            lints[0].range,
+            Range::new(Position::new(0, 8), Position::new(0, 11))
+        );
+    }
+
+    #[test]
+    fn missing_of_and_stray_end() {
+        let text = "match event case 1 => 1 end;\nend\n";
+        let lints = check(text);
+        assert_eq!(lints.len(), 3);
+        assert!(lints.iter().all(|lint| lint.code == UNBALANCED_BLOCK));
+    }
+}
@@ -0,0 +1,50 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// summary statistics for script benchmark runs
+
+use serde_json::{json, Value};
+use std::time::Duration;
+
+pub const DEFAULT_ITERATIONS: usize = 1000;
+
+// throughput and latency percentiles (in microseconds) for the run timings, as json
+pub fn summarize(mut timings: Vec<Duration>) -> Value {
+    timings.sort();
+    let total: Duration = timings.iter().sum();
+
+    let micros = |d: Duration| d.as_secs_f64() * 1_000_000.0;
+    let percentile = |p: usize| {
+        timings
+            .get((timings.len() * p / 100).min(timings.len().saturating_sub(1)))
+            .map_or(0.0, |d| micros(*d))
+    };
+    let throughput = if total.as_secs_f64() > 0.0 {
+        timings.len() as f64 / total.as_secs_f64()
+    } else {
+        0.0
+    };
+
+    json!({
+        "iterations": timings.len(),
+        "eventsPerSecond": throughput,
+        "latencyMicros": {
+            "mean": if timings.is_empty() { 0.0 } else { micros(total) / timings.len() as f64 },
+            "p50": percentile(50),
+            "p90": percentile(90),
+            "p99": percentile(99),
+            "max": timings.last().map_or(0.0, |d| micros(*d)),
+        },
+    })
+}
@@ -0,0 +1,412 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// hover for binary literals and patterns (`<< event.size:16/big, event.data/binary >>`),
+// describing their segments, with an example of what they encode to (or what a pattern binds)
+
+use crate::blocks;
+use crate::language::{Token, TokenSpan};
+use crate::lsp_utils;
+use tower_lsp::lsp_types::*;
+
+// integer segments take this many bits, unless given a size
+const DEFAULT_INTEGER_SIZE: u64 = 8;
+
+// examples are left out for larger binaries, as sizes come from the document
+const MAX_EXAMPLE_BYTES: u64 = 64;
+
+// words of the type of a segment, joined by `-` (eg: `signed-little-integer`)
+const TYPE_WORDS: &[&str] = &["binary", "integer", "signed", "unsigned", "big", "little"];
+
+struct Segment {
+    value: String,
+    // as written
+    size: Option<String>,
+    binary: bool,
+    signed: bool,
+    little: bool,
+}
+
+impl Segment {
+    fn size(&self) -> Option<u64> {
+        match &self.size {
+            Some(size) => size.parse().ok(),
+            None if self.binary => None,
+            None => Some(DEFAULT_INTEGER_SIZE),
+        }
+    }
+
+    fn describe_type(&self) -> String {
+        if self.binary {
+            "binary".to_string()
+        } else {
+            format!(
+                "{} {}-endian integer",
+                if self.signed { "signed" } else { "unsigned" },
+                if self.little { "little" } else { "big" }
+            )
+        }
+    }
+
+    fn describe_size(&self, is_last: bool) -> String {
+        match (&self.size, self.binary) {
+            (Some(size), false) => format!("{} bits", size),
+            (Some(size), true) => format!("{} bytes", size),
+            (None, false) => format!("{} bits (default)", DEFAULT_INTEGER_SIZE),
+            (None, true) if is_last => "the rest".to_string(),
+            (None, true) => "all of the value".to_string(),
+        }
+    }
+
+    // the value of literal segments, eg: `42` or `"abc"/binary`
+    fn literal(&self) -> Option<Value> {
+        let value = self.value.trim();
+        if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+            let content = &value[1..value.len() - 1];
+            // escapes and interpolation are beyond an example
+            if content.contains('\\') || content.contains("#{") {
+                return None;
+            }
+            return Some(Value::Bytes(content.as_bytes().to_vec()));
+        }
+        let (negative, digits) = match value.strip_prefix('-') {
+            Some(digits) => (true, digits.trim()),
+            None => (false, value),
+        };
+        let digits = digits.replace('_', "");
+        let magnitude = match digits.strip_prefix("0x") {
+            Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+            None => digits.parse::<i128>().ok()?,
+        };
+        Some(Value::Integer(if negative {
+            -magnitude
+        } else {
+            magnitude
+        }))
+    }
+}
+
+enum Value {
+    Integer(i128),
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    fn to_markdown(&self) -> String {
+        match self {
+            Value::Integer(value) => format!("`{}`", value),
+            Value::Bytes(bytes) => match std::str::from_utf8(bytes) {
+                Ok(s) if s.chars().all(|c| !c.is_control()) => format!("`\"{}\"`", s),
+                _ => format!("`{}`", format_bits(&to_bits(bytes))),
+            },
+        }
+    }
+}
+
+pub fn hover(text: &str, tokens: &[TokenSpan], position: Position) -> Option<(String, Range)> {
+    let tokens = blocks::significant(tokens);
+
+    // the innermost binary around the position closes first
+    let mut opened: Vec<usize> = Vec::new();
+    let (open, close) = tokens.iter().enumerate().find_map(|(i, t)| {
+        match written(text, t).as_str() {
+            // `<<` is also the left shift, when it follows a value
+            "<<" if i == 0 || !ends_value(&tokens[i - 1].value, &written(text, tokens[i - 1])) => {
+                opened.push(i);
+            }
+            ">>" => {
+                let open = opened.pop()?;
+                let start = lsp_utils::token_range(text, tokens[open]).start;
+                let end = lsp_utils::token_range(text, t).end;
+                if start <= position && position < end {
+                    return Some((open, i));
+                }
+            }
+            _ => (),
+        }
+        None
+    })?;
+    let range = Range {
+        start: lsp_utils::token_range(text, tokens[open]).start,
+        end: lsp_utils::token_range(text, tokens[close]).end,
+    };
+    let is_pattern = open > 0 && tokens[open - 1].value == Token::Case;
+    let segments = segments(text, &tokens[open + 1..close]);
+    Some((to_markdown(&segments, is_pattern), range))
+}
+
+fn written(text: &str, token: &TokenSpan) -> String {
+    lsp_utils::text_in_range(text, lsp_utils::token_range(text, token))
+}
+
+fn ends_value(token: &Token, text: &str) -> bool {
+    matches!(
+        token,
+        Token::Ident(..)
+            | Token::IntLiteral(_)
+            | Token::FloatLiteral(..)
+            | Token::RParen
+            | Token::RBracket
+            | Token::RBrace
+    ) || text == ">>"
+}
+
+// the segments of the binary, from the tokens between `<<` and `>>`
+fn segments(text: &str, tokens: &[&TokenSpan]) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, t) in tokens.iter().enumerate() {
+        let symbol = written(text, t);
+        match t.value {
+            Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+            Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+            _ if symbol == "<<" => depth += 1,
+            _ if symbol == ">>" => depth -= 1,
+            Token::Comma if depth == 0 => {
+                segments.extend(segment(text, &tokens[start..i]));
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    segments.extend(segment(text, &tokens[start..]));
+    segments
+}
+
+// `value[:size][/type]`
+fn segment(text: &str, tokens: &[&TokenSpan]) -> Option<Segment> {
+    let span = |tokens: &[&TokenSpan]| match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => lsp_utils::text_in_range(
+            text,
+            Range {
+                start: lsp_utils::token_range(text, first).start,
+                end: lsp_utils::token_range(text, last).end,
+            },
+        ),
+        _ => String::new(),
+    };
+    if tokens.is_empty() {
+        return None;
+    }
+
+    // the type is all words and dashes, after the last slash
+    let mut end = tokens.len();
+    let mut types = Vec::new();
+    if let Some(slash) = tokens.iter().rposition(|t| t.value == Token::Div) {
+        let words: Vec<String> = span(&tokens[slash + 1..])
+            .split('-')
+            .map(|w| w.trim().to_string())
+            .collect();
+        if words.iter().all(|w| TYPE_WORDS.contains(&w.as_str())) {
+            types = words;
+            end = slash;
+        }
+    }
+    // and the size after the last colon (`::` being a token of its own)
+    let mut size = None;
+    if let Some(colon) = tokens[..end].iter().rposition(|t| written(text, t) == ":") {
+        size = Some(span(&tokens[colon + 1..end]));
+        end = colon;
+    }
+
+    let has = |word: &str| types.iter().any(|t| t == word);
+    Some(Segment {
+        value: span(&tokens[..end]),
+        size,
+        binary: has("binary"),
+        signed: has("signed"),
+        little: has("little"),
+    })
+}
+
+fn to_markdown(segments: &[Segment], is_pattern: bool) -> String {
+    let mut lines = vec![format!(
+        "**Binary {}** ({} segment{})",
+        if is_pattern { "pattern" } else { "literal" },
+        segments.len(),
+        if segments.len() == 1 { "" } else { "s" }
+    )];
+    lines.push(String::new());
+    lines.push("| Segment | Type | Size |".to_string());
+    lines.push("|---|---|---|".to_string());
+    for (i, segment) in segments.iter().enumerate() {
+        lines.push(format!(
+            "| `{}` | {} | {} |",
+            segment.value.replace('|', "\\|"),
+            segment.describe_type(),
+            segment.describe_size(i + 1 == segments.len())
+        ));
+    }
+    let example = if is_pattern {
+        pattern_example(segments)
+    } else {
+        literal_example(segments)
+    };
+    if let Some(example) = example {
+        lines.push(String::new());
+        lines.push(example);
+    }
+    lines.join("\n")
+}
+
+// what the literal encodes to, with sample values for the segments that aren't literals
+fn literal_example(segments: &[Segment]) -> Option<String> {
+    let mut bits = Vec::new();
+    let mut samples = Vec::new();
+    for segment in segments {
+        let value = match segment.literal() {
+            Some(value) => value,
+            None => {
+                let sample = if segment.binary {
+                    let size = segment.size().unwrap_or(2);
+                    if size > MAX_EXAMPLE_BYTES {
+                        return None;
+                    }
+                    Value::Bytes((0..size).map(|i| b'a' + (i % 26) as u8).collect())
+                } else {
+                    Value::Integer(samples.len() as i128 + 1)
+                };
+                samples.push(format!("`{}` = {}", segment.value, sample.to_markdown()));
+                sample
+            }
+        };
+        bits.extend(encode(segment, &value)?);
+        if bits.len() as u64 > MAX_EXAMPLE_BYTES * 8 {
+            return None;
+        }
+    }
+    let encoded = format!("`{}`", format_bits(&bits));
+    Some(if samples.is_empty() {
+        format!("Encodes to {}", encoded)
+    } else {
+        format!(
+            "Example: with {}, encodes to {}",
+            samples.join(", "),
+            encoded
+        )
+    })
+}
+
+// what the pattern binds for a sample binary of the size it matches
+fn pattern_example(segments: &[Segment]) -> Option<String> {
+    let mut size: u64 = 0;
+    for (i, segment) in segments.iter().enumerate() {
+        let bits = match segment.size() {
+            Some(bytes) if segment.binary => bytes.checked_mul(8)?,
+            Some(bits) => bits,
+            // only the last segment can take the rest
+            None if i + 1 == segments.len() => 16,
+            None => return None,
+        };
+        size = size.checked_add(bits)?;
+    }
+    if size > MAX_EXAMPLE_BYTES * 8 {
+        return None;
+    }
+    let bytes: Vec<u8> = (1..=(size + 7) / 8).map(|b| b as u8).collect();
+    let bits = to_bits(&bytes);
+
+    let mut offset = 0;
+    let mut bindings = Vec::new();
+    for segment in segments {
+        let taken = match segment.size() {
+            Some(size) if segment.binary => size as usize * 8,
+            Some(size) => size as usize,
+            None => bits.len() - offset,
+        };
+        let value = decode(segment, bits.get(offset..offset + taken)?)?;
+        offset += taken;
+        bindings.push(format!("`{}` = {}", segment.value, value.to_markdown()));
+    }
+    Some(format!(
+        "Example: `{}` gives {}",
+        format_bits(&bits[..offset]),
+        bindings.join(", ")
+    ))
+}
+
+fn encode(segment: &Segment, value: &Value) -> Option<Vec<bool>> {
+    match value {
+        Value::Bytes(bytes) => {
+            let bytes = match segment.size() {
+                Some(size) => bytes.get(..size as usize)?,
+                None => &bytes[..],
+            };
+            Some(to_bits(bytes))
+        }
+        Value::Integer(value) => {
+            let size = segment.size()? as usize;
+            if size > 64 || (segment.little && size % 8 != 0) {
+                return None;
+            }
+            // two's complement, cut to the size
+            let big: Vec<bool> = (0..size)
+                .rev()
+                .map(|bit| (*value >> bit) & 1 == 1)
+                .collect();
+            Some(if segment.little {
+                big.chunks(8).rev().flatten().copied().collect()
+            } else {
+                big
+            })
+        }
+    }
+}
+
+fn decode(segment: &Segment, bits: &[bool]) -> Option<Value> {
+    if segment.binary {
+        return Some(Value::Bytes(
+            bits.chunks(8)
+                .map(|byte| byte.iter().fold(0, |b, bit| (b << 1) | *bit as u8))
+                .collect(),
+        ));
+    }
+    if bits.len() > 64 || (segment.little && bits.len() % 8 != 0) {
+        return None;
+    }
+    let big: Vec<bool> = if segment.little {
+        bits.chunks(8).rev().flatten().copied().collect()
+    } else {
+        bits.to_vec()
+    };
+    let mut value = big.iter().fold(0i128, |v, bit| (v << 1) | *bit as i128);
+    if segment.signed && big.first() == Some(&true) {
+        value -= 1 << big.len();
+    }
+    Some(Value::Integer(value))
+}
+
+fn to_bits(bytes: &[u8]) -> Vec<bool> {
+    bytes
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1 == 1))
+        .collect()
+}
+
+// as tremor shows binaries, with any bits past the last byte as `value:bits`
+fn format_bits(bits: &[bool]) -> String {
+    let parts: Vec<String> = bits
+        .chunks(8)
+        .map(|chunk| {
+            let value = chunk.iter().fold(0u8, |b, bit| (b << 1) | *bit as u8);
+            if chunk.len() == 8 {
+                value.to_string()
+            } else {
+                format!("{}:{}", value, chunk.len())
+            }
+        })
+        .collect();
+    format!("<<{}>>", parts.join(", "))
+}
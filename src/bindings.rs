@@ -0,0 +1,113 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// references to the streams of pipelines from the yaml deployment files (bindings), eg:
+// `'/onramp/in/{instance}/out': ['/pipeline/main/{instance}/in']`. pipelines are named
+// after their trickle file (`main.trickle` here), and the stream is the last part of the url.
+//
+// the yaml is looked at as text, which is all the urls in it need.
+
+use crate::lsp_utils;
+use crate::workspace;
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::*;
+
+pub const FILE_EXTENSIONS: &[&str] = &["yaml", "yml"];
+
+// characters that end a url in the yaml
+fn ends_url(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '\'' | '"' | ',' | ']' | '}' | '#')
+}
+
+pub fn find_files(dir: &Path, exclude: &[String]) -> Vec<PathBuf> {
+    workspace::find_files_with(dir, exclude, |path| {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .map_or(false, |e| FILE_EXTENSIONS.contains(&e))
+    })
+}
+
+// a deployment file for the pipeline to start from: an onramp for each of its input ports
+// and an offramp for each of its output ports (of types to replace), the binding linking them
+// to the pipeline, and a mapping deploying one instance of it
+pub fn scaffold(pipeline: &str, inputs: &[String], outputs: &[String]) -> String {
+    let mut lines =
+        vec!["# TODO replace the onramp and offramp types (and their config)".to_string()];
+    lines.push("onramp:".to_string());
+    for input in inputs {
+        lines.push(format!("  - id: {}-{}", pipeline, input));
+        lines.push("    type: stdin".to_string());
+        lines.push("    codec: json".to_string());
+    }
+    lines.push("offramp:".to_string());
+    for output in outputs {
+        lines.push(format!("  - id: {}-{}", pipeline, output));
+        // errors go to stderr, until they have a place of their own
+        let kind = if output == "err" { "stderr" } else { "stdout" };
+        lines.push(format!("    type: {}", kind));
+        lines.push("    codec: json".to_string());
+    }
+    lines.push("binding:".to_string());
+    lines.push(format!("  - id: {}", pipeline));
+    lines.push("    links:".to_string());
+    for input in inputs {
+        lines.push(format!(
+            "      '/onramp/{}-{}/{{instance}}/out': ['/pipeline/{}/{{instance}}/{}']",
+            pipeline, input, pipeline, input
+        ));
+    }
+    for output in outputs {
+        lines.push(format!(
+            "      '/pipeline/{}/{{instance}}/{}': ['/offramp/{}-{}/{{instance}}/in']",
+            pipeline, output, pipeline, output
+        ));
+    }
+    lines.push("mapping:".to_string());
+    lines.push(format!("  /binding/{}/01:", pipeline));
+    lines.push("    instance: '01'".to_string());
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+// ranges of the stream name in the urls of the pipeline's stream
+pub fn stream_ranges(text: &str, pipeline: &str, stream: &str) -> Vec<Range> {
+    let prefix = format!("/pipeline/{}/", pipeline);
+    let mut ranges = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        let mut from = 0;
+        while let Some(found) = line[from..].find(&prefix) {
+            let start = from + found;
+            let end = line[start..]
+                .find(ends_url)
+                .map_or(line.len(), |end| start + end);
+            from = end.max(start + prefix.len());
+
+            let url = &line[start..end];
+            let name_start = match url.rfind('/') {
+                Some(slash) => start + slash + 1,
+                None => continue,
+            };
+            if &line[name_start..end] != stream {
+                continue;
+            }
+            let character =
+                |offset: usize| lsp_utils::to_utf16_character(line, line[..offset].chars().count());
+            ranges.push(Range::new(
+                Position::new(line_number as u64, character(name_start)),
+                Position::new(line_number as u64, character(end)),
+            ));
+        }
+    }
+    ranges
+}
@@ -0,0 +1,306 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::language::{Token, TokenSpan};
+use crate::lsp_utils;
+use tower_lsp::lsp_types::*;
+
+// a `case ... =>` or `default =>` arm of a match expression
+#[derive(Debug, Clone)]
+pub struct Arm {
+    // covers the arm keyword
+    pub keyword_range: Range,
+    // from the arm keyword up to where the next arm (or the match end) starts
+    pub range: Range,
+    // pattern text, as written
+    pub pattern: String,
+    pub is_default: bool,
+    pub has_guard: bool,
+}
+
+// a `match ... of ... end` expression
+#[derive(Debug, Clone)]
+pub struct MatchBlock {
+    pub match_range: Range,
+    pub of_range: Option<Range>,
+    pub end_range: Option<Range>,
+    pub arms: Vec<Arm>,
+}
+
+// keywords starting expressions that are terminated with `end`
+pub fn opens_expression(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Match | Token::For | Token::Patch | Token::Merge
+    )
+}
+
+pub fn significant<'a, 'input>(tokens: &'a [TokenSpan<'input>]) -> Vec<&'a TokenSpan<'input>> {
+    tokens.iter().filter(|t| !t.value.is_ignorable()).collect()
+}
+
+// true if the definition starting at the index (of the significant tokens) has a body, that
+// then needs an `end`. eg: `fn f(x) with ... end` as opposed to `intrinsic fn f(x) as m::f;`
+pub fn has_body(tokens: &[&TokenSpan], i: usize) -> bool {
+    tokens[i + 1..]
+        .iter()
+        .map(|t| &t.value)
+        .find(|t| matches!(t, Token::With | Token::Of | Token::Script | Token::Semi))
+        .map_or(false, |t| *t != Token::Semi)
+}
+
+// index of the `end` closing the block opened at the index (of the significant tokens), by
+// an expression or a definition with a body. None if it's not closed.
+pub fn block_end(tokens: &[&TokenSpan], opener: usize) -> Option<usize> {
+    // tokens opening the blocks we are in, to tell the `merge` operations of patches from
+    // merge expressions
+    let mut stack: Vec<&Token> = Vec::new();
+    for (i, t) in tokens.iter().enumerate().skip(opener) {
+        let in_patch = stack.last() == Some(&&Token::Patch);
+        match &t.value {
+            Token::Merge if in_patch => (),
+            v if opens_expression(v) => stack.push(v),
+            Token::Fn | Token::Module | Token::Define if has_body(tokens, i) => {
+                stack.push(&t.value)
+            }
+            Token::End => {
+                stack.pop();
+                if stack.is_empty() {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+        if stack.is_empty() {
+            // the opener does not open a block after all
+            return None;
+        }
+    }
+    None
+}
+
+// all the match expressions in the document, including nested ones
+pub fn match_blocks(text: &str, tokens: &[TokenSpan]) -> Vec<MatchBlock> {
+    let tokens = significant(tokens);
+    let range = |t: &TokenSpan| Range {
+        start: lsp_utils::to_lsp_position_in(text, &t.span.start),
+        end: lsp_utils::to_lsp_position_in(text, &t.span.end),
+    };
+
+    let mut blocks = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        if t.value != Token::Match {
+            continue;
+        }
+
+        let mut block = MatchBlock {
+            match_range: range(t),
+            of_range: None,
+            end_range: None,
+            arms: vec![],
+        };
+        // arm keyword index, pattern end index and guard presence for each arm
+        let mut arm_starts: Vec<(usize, Option<usize>, bool)> = Vec::new();
+        let mut end_index = None;
+
+        // tokens opening the blocks we are in, as in block_end, with the match itself first
+        let mut stack: Vec<&Token> = Vec::new();
+        for (j, t) in tokens.iter().enumerate().skip(i) {
+            let in_patch = stack.last() == Some(&&Token::Patch);
+            if opens_expression(&t.value) && !(in_patch && t.value == Token::Merge) {
+                stack.push(&t.value);
+                continue;
+            }
+            if stack.len() > 1 {
+                if t.value == Token::End {
+                    stack.pop();
+                }
+                continue;
+            }
+            match &t.value {
+                Token::Of if block.of_range.is_none() => block.of_range = Some(range(t)),
+                Token::Case | Token::Default => arm_starts.push((j, None, false)),
+                Token::When => {
+                    if let Some(arm) = arm_starts.last_mut() {
+                        arm.1 = arm.1.or(Some(j));
+                        arm.2 = true;
+                    }
+                }
+                Token::EqArrow => {
+                    if let Some(arm) = arm_starts.last_mut() {
+                        arm.1 = arm.1.or(Some(j));
+                    }
+                }
+                Token::End => {
+                    block.end_range = Some(range(t));
+                    end_index = Some(j);
+                    break;
+                }
+                _ => (),
+            }
+        }
+
+        for (k, (start, pattern_end, has_guard)) in arm_starts.iter().enumerate() {
+            let next = arm_starts.get(k + 1).map(|arm| arm.0).or(end_index);
+            let keyword = tokens[*start];
+            let is_default = keyword.value == Token::Default;
+
+            let pattern = match pattern_end {
+                Some(pattern_end) if !is_default && *pattern_end > start + 1 => {
+                    let from = lsp_utils::to_offset(text, &tokens[start + 1].span.start);
+                    let to = lsp_utils::to_offset(text, &tokens[pattern_end - 1].span.end);
+                    text.get(from..to).unwrap_or_default().trim().to_string()
+                }
+                _ => String::new(),
+            };
+
+            let arm_end = match next {
+                Some(next) => lsp_utils::to_lsp_position_in(text, &tokens[next].span.start),
+                None => range(tokens[tokens.len() - 1]).end,
+            };
+
+            block.arms.push(Arm {
+                keyword_range: range(keyword),
+                range: Range {
+                    start: range(keyword).start,
+                    end: arm_end,
+                },
+                pattern,
+                is_default,
+                has_guard: *has_guard,
+            });
+        }
+
+        blocks.push(block);
+    }
+
+    blocks
+}
+
+// ranges of the keywords going together with the one at the position: `match`, `of`, the
+// `case`/`default` arms and `end` of a match expression, or the keyword opening any other
+// block and its `end`. None if the position is on none of them.
+pub fn keyword_highlights(
+    text: &str,
+    tokens: &[TokenSpan],
+    position: Position,
+) -> Option<Vec<Range>> {
+    let contains = |range: &Range| range.start <= position && position <= range.end;
+    // the innermost match, as nested ones come later
+    if let Some(block) = match_blocks(text, tokens).into_iter().rev().find(|block| {
+        contains(&block.match_range)
+            || block.of_range.as_ref().map_or(false, contains)
+            || block.end_range.as_ref().map_or(false, contains)
+            || block.arms.iter().any(|arm| contains(&arm.keyword_range))
+    }) {
+        let mut ranges = vec![block.match_range];
+        ranges.extend(block.of_range);
+        ranges.extend(block.arms.iter().map(|arm| arm.keyword_range));
+        ranges.extend(block.end_range);
+        return Some(ranges);
+    }
+
+    let tokens = significant(tokens);
+    let range = |i: usize| lsp_utils::token_range(text, tokens[i]);
+    // indices of the keywords opening the blocks we are in, as in block_end
+    let mut stack: Vec<usize> = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        let in_patch = stack
+            .last()
+            .map_or(false, |j| tokens[*j].value == Token::Patch);
+        match &t.value {
+            Token::Merge if in_patch => (),
+            v if opens_expression(v) => stack.push(i),
+            Token::Fn | Token::Module | Token::Define if has_body(&tokens, i) => stack.push(i),
+            Token::End => {
+                if let Some(opener) = stack.pop() {
+                    if contains(&range(opener)) || contains(&range(i)) {
+                        return Some(vec![range(opener), range(i)]);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::language::Tokenizer;
+
+    fn tokens(text: &str) -> Vec<TokenSpan> {
+        Tokenizer::new(text)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn match_blocks_skip_nested_blocks() {
+        let text = "match event of\n\
+                    \x20 case %{ a == 1 } => patch event of merge => {} end\n\
+                    \x20 case %{ b == 2 } => for event of case (k, v) => v end\n\
+                    \x20 default => match event.x of case 1 => 1 default => 2 end\n\
+                    end\n";
+        let tokens = tokens(text);
+        assert!(!tokens.is_empty());
+
+        let blocks = match_blocks(text, &tokens);
+        assert_eq!(blocks.len(), 2);
+        let outer = &blocks[0];
+        assert_eq!(outer.arms.len(), 3);
+        assert_eq!(outer.arms[0].pattern, "%{ a == 1 }");
+        assert_eq!(outer.arms[1].pattern, "%{ b == 2 }");
+        assert!(outer.arms[2].is_default);
+        assert_eq!(
+            outer.end_range,
+            Some(Range::new(Position::new(4, 0), Position::new(4, 3)))
+        );
+
+        let inner = &blocks[1];
+        assert_eq!(inner.arms.len(), 2);
+        assert_eq!(inner.end_range.map(|range| range.start.line), Some(3));
+    }
+
+    #[test]
+    fn match_blocks_in_patch_merge() {
+        let text = "match event of\n\
+                    \x20 case %{} => patch event of merge \"a\" => {} end\n\
+                    \x20 case _ => merge event of {} end\n\
+                    end\n";
+        let blocks = match_blocks(text, &tokens(text));
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].arms.len(), 2);
+        assert_eq!(blocks[0].end_range.map(|range| range.start.line), Some(3));
+    }
+
+    #[test]
+    fn keyword_highlights_of_nested_match() {
+        let text = "match event of\n\
+                    \x20 case 1 => patch event of merge => {} end\n\
+                    \x20 default => 2\n\
+                    end\n";
+        let tokens = tokens(text);
+        // on the `end` of the match, past the one of the patch
+        let ranges = keyword_highlights(text, &tokens, Position::new(3, 1));
+        assert_eq!(ranges.map(|ranges| ranges.len()), Some(5));
+        // on the `end` of the patch
+        let ranges = keyword_highlights(text, &tokens, Position::new(1, 41));
+        assert_eq!(
+            ranges.map(|ranges| ranges.iter().map(|r| r.start.line).collect::<Vec<_>>()),
+            Some(vec![1, 1])
+        );
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// on-disk cache of the workspace index, so that reopening a project does not need
+// re-indexing of the files that did not change since
+
+use crate::workspace::FileIndex;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+// used instead of halfbrown::Hashmap because bincode can't deserialize that
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// caches of workspaces not opened for this long are removed
+const MAX_CACHE_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedFile {
+    content_hash: u64,
+    index: FileIndex,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IndexCache {
+    // the cache format follows the server's, so entries from other versions are not used
+    version: String,
+    files: HashMap<PathBuf, CachedFile>,
+}
+
+impl IndexCache {
+    // loads the cache for the workspace roots, starting afresh if there's none (or if
+    // it's not readable)
+    pub fn load(roots: &[PathBuf]) -> Self {
+        let cache = cache_file(roots)
+            .and_then(|path| File::open(path).ok())
+            .and_then(|f| bincode::deserialize_from::<_, Self>(BufReader::new(f)).ok());
+
+        match cache {
+            Some(cache) if cache.version == env!("CARGO_PKG_VERSION") => cache,
+            _ => Self {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                files: HashMap::new(),
+            },
+        }
+    }
+
+    pub fn save(&self, roots: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+        let path = cache_file(roots).ok_or("No cache directory available")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut f = BufWriter::new(File::create(&path)?);
+        bincode::serialize_into(&mut f, self)?;
+        evict_stale(&path);
+        Ok(())
+    }
+
+    pub fn get(&self, path: &Path, content_hash: u64) -> Option<&FileIndex> {
+        self.files
+            .get(path)
+            .filter(|cached| cached.content_hash == content_hash)
+            .map(|cached| &cached.index)
+    }
+
+    pub fn insert(&mut self, path: &Path, content_hash: u64, index: FileIndex) {
+        self.files.insert(
+            path.to_path_buf(),
+            CachedFile {
+                content_hash,
+                index,
+            },
+        );
+    }
+
+    // drops the entries for files that are gone from the workspace
+    pub fn retain(&mut self, paths: &[PathBuf]) {
+        let paths: HashSet<&PathBuf> = paths.iter().collect();
+        self.files.retain(|path, _| paths.contains(path));
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+}
+
+pub fn content_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        None
+    };
+    dir.or_else(|| env::var_os("XDG_CACHE_HOME").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .map(|dir| dir.join(env!("CARGO_PKG_NAME")))
+}
+
+// one cache file per set of workspace roots
+fn cache_file(roots: &[PathBuf]) -> Option<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    roots.hash(&mut hasher);
+    cache_dir().map(|dir| dir.join(format!("index-{:016x}.bin", hasher.finish())))
+}
+
+// removes the cache files (of other workspaces) that were not written to in a while, so
+// that the caches of workspaces long gone don't pile up. errors are ignored (eg. for files
+// another server removed first).
+fn evict_stale(current: &Path) {
+    let entries = match current.parent().map(fs::read_dir) {
+        Some(Ok(entries)) => entries,
+        _ => return,
+    };
+    let now = SystemTime::now();
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let is_cache_file = path != current
+            && path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| {
+                    name.starts_with("index-") && name.ends_with(".bin")
+                });
+        if !is_cache_file {
+            continue;
+        }
+        let is_stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .map_or(false, |age| age > MAX_CACHE_AGE);
+        if is_stale {
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
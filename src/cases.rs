@@ -0,0 +1,185 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// arms to scaffold in a match expression, with a `case` per value of the matched expression
+// where those are known: from an enum-like const array named after the matched field (eg:
+// `const LEVELS = ["info", "warn"];` for `match event.level of`), or from the `enum` of the
+// field in the event schema of the project.
+
+use crate::blocks;
+use crate::consts;
+use crate::language::{Token, TokenSpan};
+use crate::lsp_utils;
+use serde_json::Value;
+use tower_lsp::lsp_types::*;
+
+// a match expression that an arm can be added to at the completion position
+#[derive(Debug)]
+pub struct Slot {
+    // the matched expression, as written (eg: `event.level`)
+    pub subject: String,
+    // patterns of the existing arms, as written
+    pub covered: Vec<String>,
+    pub has_default: bool,
+    // of the line the arm goes on
+    pub indentation: String,
+}
+
+impl Slot {
+    // the fields of the event that the subject is (eg: `["level"]` for `event.level`). None
+    // if it is not a path into the event.
+    pub fn event_path(&self) -> Option<Vec<&str>> {
+        let mut parts = self.subject.split('.').map(str::trim);
+        if parts.next() != Some("event") {
+            return None;
+        }
+        let path: Vec<&str> = parts.collect();
+        let is_name =
+            |part: &&str| !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || c == '_');
+        Some(path).filter(|path| !path.is_empty() && path.iter().all(is_name))
+    }
+
+    // the name of the subject, or of the field it is (eg: `level` for `event.level`)
+    pub fn field(&self) -> &str {
+        self.subject.rsplit('.').next().unwrap_or_default().trim()
+    }
+}
+
+// the innermost match expression taking an arm at the position: right after its `of`, or at
+// the start of a line within it
+pub fn slot(text: &str, tokens: &[TokenSpan], position: Position) -> Option<Slot> {
+    let line = text.lines().nth(position.line as usize).unwrap_or_default();
+    let before = &line[..line
+        .char_indices()
+        .nth(lsp_utils::to_char_column(line, position.character))
+        .map_or(line.len(), |(i, _)| i)];
+    // without the arm keyword being typed
+    let typed = before.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+    let indentation: String = line.chars().take_while(|c| c.is_whitespace()).collect();
+
+    let block = blocks::match_blocks(text, tokens)
+        .into_iter()
+        .filter(|block| {
+            block.of_range.map_or(false, |of| of.end <= position)
+                && block.end_range.map_or(true, |end| position <= end.start)
+        })
+        .max_by_key(|block| block.match_range.start)?;
+    let of = block.of_range?;
+    let after_of = lsp_utils::text_in_range(
+        text,
+        Range::new(
+            of.end,
+            Position::new(position.line, typed.encode_utf16().count() as u64),
+        ),
+    );
+    if !typed.trim().is_empty() && !after_of.trim().is_empty() {
+        return None;
+    }
+
+    let subject = lsp_utils::text_in_range(text, Range::new(block.match_range.end, of.start));
+    Some(Slot {
+        subject: subject.trim().to_string(),
+        covered: block
+            .arms
+            .iter()
+            .filter(|arm| !arm.is_default)
+            .map(|arm| arm.pattern.clone())
+            .collect(),
+        has_default: block.arms.iter().any(|arm| arm.is_default),
+        indentation,
+    })
+}
+
+// string values of the const array named after the field (eg: `LEVELS` or `LEVEL` for
+// `level`), as written between their quotes. empty unless all the elements are strings.
+pub fn const_values(text: &str, tokens: &[TokenSpan], field: &str) -> Vec<String> {
+    let names = [field.to_uppercase(), format!("{}S", field.to_uppercase())];
+    let definition = match consts::collect(text, tokens)
+        .into_iter()
+        .find(|c| names.contains(&c.name.to_uppercase()))
+    {
+        Some(definition) => definition,
+        None => return vec![],
+    };
+    let tokens = blocks::significant(tokens);
+    let start = tokens.iter().position(|t| {
+        matches!(&t.value, Token::Ident(name, _) if name.as_ref() == definition.name.as_str())
+            && lsp_utils::token_range(text, t) == definition.range
+    });
+    let elements = match start {
+        // `NAME = [`
+        Some(i) if tokens.get(i + 2).map(|t| &t.value) == Some(&Token::LBracket) => {
+            &tokens[i + 3..]
+        }
+        _ => return vec![],
+    };
+
+    let mut values = Vec::new();
+    let mut opening = None;
+    for t in elements {
+        match (&t.value, opening) {
+            (Token::RBracket, None) => return values,
+            (Token::Comma, None) => (),
+            (Token::DQuote, None) => opening = Some(lsp_utils::token_range(text, t).end),
+            (Token::DQuote, Some(start)) => {
+                let end = lsp_utils::token_range(text, t).start;
+                values.push(lsp_utils::text_in_range(text, Range::new(start, end)));
+                opening = None;
+            }
+            (Token::StringLiteral(_), Some(_)) => (),
+            _ => return vec![],
+        }
+    }
+    vec![]
+}
+
+// string values of the `enum` of the field at the path in the json schema, escaped as in a
+// tremor string
+pub fn schema_values(schema: &Value, path: &[&str]) -> Vec<String> {
+    let field = path.iter().try_fold(schema, |schema, name| {
+        schema
+            .get("properties")
+            .and_then(|properties| properties.get(name))
+    });
+    field
+        .and_then(|field| field.get("enum"))
+        .and_then(Value::as_array)
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(Value::as_str)
+                .map(escape)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// the value as written between the quotes of a tremor string: with quotes, backslashes and
+// line breaks escaped, and no `#{` starting an interpolation
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '#' if chars.peek() == Some(&'{') => escaped.push_str("\\#"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,458 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// diagnostics of a document, as published by the server and as reported by the `check`
+// subcommand (for running the same checks in CI)
+
+use crate::backend::TREMOR_SCRIPT_VERSION;
+use crate::config::Config;
+use crate::language::{self, Language};
+use crate::project::Project;
+use crate::{balance, lints, lsp_utils, paths, test_files, workspace};
+use std::fs;
+use std::io::{self, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tower_lsp::lsp_types::*;
+
+// a document to compute the diagnostics of on a worker thread
+pub struct Job {
+    pub uri: Url,
+    pub text: String,
+    pub config: Config,
+}
+
+// diagnostics of the document, without duplicates and in the order of their positions (so that
+// republishing them gives the same list), up to the maxNumberOfProblems setting
+pub fn diagnostics(
+    language: &dyn Language,
+    uri: &Url,
+    text: &str,
+    config: &Config,
+) -> Vec<Diagnostic> {
+    let diagnostics = sorted(collect(language, uri, text, config));
+    match config.max_number_of_problems {
+        Some(max) => truncated(diagnostics, max as usize),
+        None => diagnostics,
+    }
+}
+
+fn sorted(mut diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    diagnostics.sort_by(|a, b| {
+        (a.range.start, a.range.end)
+            .cmp(&(b.range.start, b.range.end))
+            .then_with(|| a.message.cmp(&b.message))
+    });
+    let mut unique: Vec<Diagnostic> = Vec::with_capacity(diagnostics.len());
+    for diagnostic in diagnostics {
+        // duplicates are next to each other, but for ones with the same range and message
+        let duplicate = unique
+            .iter()
+            .rev()
+            .take_while(|u| u.range == diagnostic.range)
+            .any(|u| *u == diagnostic);
+        if !duplicate {
+            unique.push(diagnostic);
+        }
+    }
+    unique
+}
+
+// the most severe of the diagnostics (sorted by position) up to the maximum, with one saying
+// how many more there are
+fn truncated(diagnostics: Vec<Diagnostic>, max: usize) -> Vec<Diagnostic> {
+    if diagnostics.len() <= max {
+        return diagnostics;
+    }
+    let rank = |d: &Diagnostic| match d.severity {
+        Some(DiagnosticSeverity::Error) | None => 0,
+        Some(DiagnosticSeverity::Warning) => 1,
+        Some(DiagnosticSeverity::Information) => 2,
+        Some(DiagnosticSeverity::Hint) => 3,
+    };
+    let omitted = diagnostics.len() - max;
+    let mut kept: Vec<(usize, Diagnostic)> = diagnostics.into_iter().enumerate().collect();
+    kept.sort_by_key(|(i, d)| (rank(d), *i));
+    kept.truncate(max);
+    kept.sort_by_key(|(i, _)| *i);
+
+    let mut diagnostics: Vec<Diagnostic> = kept.into_iter().map(|(_, d)| d).collect();
+    diagnostics.push(Diagnostic {
+        range: Range::default(),
+        message: format!(
+            "{} more problems not shown (maxNumberOfProblems is {})",
+            omitted, max
+        ),
+        severity: Some(DiagnosticSeverity::Information),
+        source: Some("tremor-language-server".to_string()),
+        code: None,
+        related_information: None,
+        tags: None,
+    });
+    diagnostics
+}
+
+fn collect(language: &dyn Language, uri: &Url, text: &str, config: &Config) -> Vec<Diagnostic> {
+    if let Some(kind) = test_files::kind(uri) {
+        return test_files::check(kind, text)
+            .iter()
+            .filter_map(|lint| {
+                config
+                    .lint_severity(lint.code, lint.severity)
+                    .map(|severity| Diagnostic {
+                        severity: Some(severity),
+                        ..lint.to_diagnostic()
+                    })
+            })
+            .collect();
+    }
+
+    let mut diagnostics = Vec::new();
+
+    // there's one grammar to check with, so the setting can only tell that it's the wrong one
+    if let Some(target) = &config.tremor_version {
+        if !is_release(target, TREMOR_SCRIPT_VERSION) {
+            diagnostics.push(Diagnostic {
+                range: Range::default(),
+                message: format!(
+                    "Checked with the syntax of tremor {} rather than {} (see the tremorVersion setting), as that's the only one this server knows",
+                    TREMOR_SCRIPT_VERSION, target
+                ),
+                severity: Some(DiagnosticSeverity::Information),
+                source: Some("tremor-language-server".to_string()),
+                code: None,
+                related_information: None,
+                tags: None,
+            });
+        }
+    }
+
+    // large (eg: generated) files only get the structural checks, which are cheap
+    let large = config.is_large(text.len() as u64);
+    if large {
+        diagnostics.push(Diagnostic {
+            range: Range::default(),
+            message: format!(
+                "File is larger than {} KB, so it only gets syntax checks (see the maxFileSize setting)",
+                config.max_file_size()
+            ),
+            severity: Some(DiagnosticSeverity::Information),
+            source: Some("tremor-language-server".to_string()),
+            code: None,
+            related_information: None,
+            tags: None,
+        });
+    } else if let Some(errors) = language.parse_errors(uri, text) {
+        for e in &errors {
+            let range = Range {
+                start: lsp_utils::to_lsp_position(&e.start()),
+                end: lsp_utils::to_lsp_position(&e.end()),
+            };
+
+            let mut message = e.callout().to_string();
+            let mut hint = None;
+            match (&e.hint(), config.hint_severity()) {
+                (Some(note), Some(severity)) => {
+                    hint = Some(Diagnostic {
+                        range,
+                        message: note.to_string(),
+                        severity: Some(severity),
+                        source: Some("tremor-language-server".to_string()),
+                        code: None,
+                        related_information: None,
+                        tags: None,
+                    })
+                }
+                // comma here splits the message into multiple lines
+                (Some(note), None) => message = format!("{}, Note: {}", message, note),
+                (None, _) => (),
+            }
+
+            diagnostics.push(Diagnostic {
+                range,
+                message,
+                severity: Some(config.severity(lsp_utils::to_lsp_severity(&e.level()))),
+                source: Some("tremor-language-server".to_string()),
+                code: None,
+                related_information: None,
+                tags: None,
+            });
+            diagnostics.extend(hint);
+        }
+    }
+
+    // structural checks work where the tokenizer fails as well
+    let mut document_lints = balance::check(text);
+    if !large {
+        if let Some(tokens) = language.tokenize(uri, text) {
+            document_lints.extend(lints::check(language, uri, text, &tokens));
+        }
+    }
+    diagnostics.extend(document_lints.iter().filter_map(|lint| {
+        config
+            .lint_severity(lint.code, lint.severity)
+            .map(|severity| Diagnostic {
+                severity: Some(severity),
+                ..lint.to_diagnostic()
+            })
+    }));
+
+    diagnostics
+}
+
+// whether the version (eg: `0.9.4`) is of the release (eg: `0.9`, `0.9.x` or `0.9.4`). an
+// unknown version is taken to be of any release.
+fn is_release(release: &str, version: &str) -> bool {
+    if version == "unknown" {
+        return true;
+    }
+    let version: Vec<&str> = version
+        .split(|c| c == '.' || c == '-' || c == '+')
+        .collect();
+    release
+        .trim_start_matches('v')
+        .split('.')
+        .enumerate()
+        .all(|(i, part)| part == "x" || part == "*" || version.get(i) == Some(&part))
+}
+
+// diagnostics sent back by the workers, as they are done. dropping it (eg. with the request
+// it is for cancelled) stops the workers before their next job.
+pub struct DiagnosticsResults {
+    receiver: mpsc::UnboundedReceiver<(usize, Option<Vec<Diagnostic>>)>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl DiagnosticsResults {
+    pub async fn recv(&mut self) -> Option<(usize, Option<Vec<Diagnostic>>)> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for DiagnosticsResults {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+// computes the diagnostics of the documents on up to the given number of threads (each with
+// a language instance of its own), sending them back as they are done, along with the index
+// of the job. None for documents whose diagnostics failed (ie. panicked).
+pub fn spawn_diagnostics(
+    language_name: &str,
+    jobs: Vec<Job>,
+    workers: usize,
+) -> DiagnosticsResults {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let workers = workers.min(jobs.len()).max(1);
+    let queue = Arc::new(Mutex::new(jobs.into_iter().enumerate()));
+    for _ in 0..workers {
+        let queue = queue.clone();
+        let sender = sender.clone();
+        let cancelled = cancelled.clone();
+        let language_name = language_name.to_string();
+        std::thread::spawn(move || {
+            let language = match language::lookup(&language_name) {
+                Some(language) => language,
+                None => return,
+            };
+            loop {
+                if cancelled.load(Ordering::Relaxed) {
+                    break;
+                }
+                // a worker that panicked while taking a job doesn't stop the others
+                let next = match queue.lock() {
+                    Ok(mut queue) => queue.next(),
+                    Err(poisoned) => poisoned.into_inner().next(),
+                };
+                let (i, job) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    diagnostics(language.as_ref(), &job.uri, &job.text, &job.config)
+                }));
+                // the receiving end is gone, eg. on shutdown
+                if sender.send((i, result.ok())).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    DiagnosticsResults {
+        receiver,
+        cancelled,
+    }
+}
+
+// settings of the project manifest in the current directory
+fn cli_config(strict: bool) -> Config {
+    let mut config = std::env::current_dir()
+        .ok()
+        .and_then(|dir| Project::load(&dir).ok())
+        .map(|project| project.config)
+        .unwrap_or_default();
+    if strict {
+        config.strict = Some(true);
+    }
+    // all of them count in ci
+    config.max_number_of_problems = None;
+    config
+}
+
+// checks the files (and the tremor files in the directories) with the settings of the
+// project in the current directory, printing the diagnostics. false if there are errors.
+pub fn run(paths: &[PathBuf], strict: bool) -> bool {
+    let config = cli_config(strict);
+
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let exclude = workspace::exclusions(path, &config.index_exclude);
+            files.extend(workspace::find_files_with(path, &exclude, |file| {
+                workspace::is_tremor_file(file) || config.language_for(&relative(file)).is_some()
+            }));
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    let (mut errors, mut warnings) = (0, 0);
+    for file in &files {
+        let diagnostics = match check_file(file, &config) {
+            Ok(diagnostics) => diagnostics,
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                errors += 1;
+                continue;
+            }
+        };
+        for diagnostic in diagnostics {
+            let severity = match diagnostic.severity {
+                Some(DiagnosticSeverity::Error) => {
+                    errors += 1;
+                    "error"
+                }
+                Some(DiagnosticSeverity::Warning) => {
+                    warnings += 1;
+                    "warning"
+                }
+                Some(DiagnosticSeverity::Information) => "info",
+                _ => "hint",
+            };
+            let code = match diagnostic.code {
+                Some(NumberOrString::String(code)) => format!("[{}]", code),
+                Some(NumberOrString::Number(code)) => format!("[{}]", code),
+                None => String::new(),
+            };
+            println!(
+                "{}:{}:{}: {}{}: {}",
+                file.display(),
+                diagnostic.range.start.line + 1,
+                diagnostic.range.start.character + 1,
+                severity,
+                code,
+                diagnostic.message
+            );
+        }
+    }
+
+    eprintln!(
+        "{} files checked: {} errors, {} warnings",
+        files.len(),
+        errors,
+        warnings
+    );
+    errors == 0
+}
+
+// checks the source on stdin, printing its diagnostics (as the server publishes them) as a json
+// array, for editors linting unsaved buffers and for pre-commit hooks. the source is taken to be
+// a file in the current directory, for resolving its imports.
+pub fn run_stdin(language_name: &str, strict: bool) -> bool {
+    let config = cli_config(strict);
+    let mut text = String::new();
+    if let Err(e) = io::stdin().read_to_string(&mut text) {
+        eprintln!("Error: failed to read stdin: {}", e);
+        return false;
+    }
+    let (language, extension) = match (
+        language::lookup(language_name),
+        language::file_extension(language_name),
+    ) {
+        (Some(language), Some(extension)) => (language, extension),
+        _ => {
+            eprintln!("Error: unknown tremor language {}", language_name);
+            return false;
+        }
+    };
+    let uri = match std::env::current_dir()
+        .ok()
+        .and_then(|dir| paths::to_uri(&dir.join(format!("stdin.{}", extension))))
+    {
+        Some(uri) => uri,
+        None => {
+            eprintln!("Error: failed to get the current directory");
+            return false;
+        }
+    };
+
+    let diagnostics = diagnostics(language.as_ref(), &uri, &text, &config);
+    match serde_json::to_string(&diagnostics) {
+        Ok(json) => println!("{}", json),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return false;
+        }
+    }
+    !diagnostics
+        .iter()
+        .any(|d| d.severity == Some(DiagnosticSeverity::Error))
+}
+
+// the path relative to the current directory (where the project settings come from), if it's
+// under it
+fn relative(path: &Path) -> PathBuf {
+    let absolute = fs::canonicalize(path).map(|path| paths::normalize(&path));
+    match (std::env::current_dir(), absolute) {
+        (Ok(dir), Ok(absolute)) => absolute
+            .strip_prefix(paths::normalize(&dir))
+            .map_or_else(|_| absolute.clone(), Path::to_path_buf),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn check_file(file: &Path, config: &Config) -> Result<Vec<Diagnostic>, String> {
+    let text = fs::read_to_string(file).map_err(|e| e.to_string())?;
+    let path = paths::normalize(&fs::canonicalize(file).map_err(|e| e.to_string())?);
+    let uri = paths::to_uri(&path).ok_or("not a valid file path")?;
+    // the language goes by the fileLanguages setting or the file extension, with test files
+    // checked as such
+    let extension = config
+        .language_for(&relative(&path))
+        .or_else(|| path.extension().and_then(|e| e.to_str()))
+        .unwrap_or_default();
+    let language = match language::lookup(extension) {
+        Some(language) => language,
+        None if test_files::kind(&uri).is_some() => {
+            language::lookup(language::DEFAULT_LANGUAGE_NAME).ok_or("no tremor language")?
+        }
+        None => return Err(format!("not a tremor file (.{})", extension)),
+    };
+    Ok(diagnostics(language.as_ref(), &uri, &text, config))
+}
@@ -0,0 +1,169 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// commands supported via workspace/executeCommand
+
+// argument: uri of the document to organize
+pub const ORGANIZE_IMPORTS: &str = "tremor.organizeImports";
+
+// no arguments. returns server version and state information, as a json object
+pub const SERVER_STATUS: &str = "tremor.serverStatus";
+
+// optional argument: `{"format": "prometheus"}`. returns the number and durations of the
+// requests (per method, or command), the durations of computing diagnostics and the index
+// cache hit rate, as `{"requests": {"textDocument/hover": {"count": 3, "sumMs": ...,
+// "meanMs": ..., "buckets": {"1": 2, ...}}}, "diagnostics": {...}, "indexCache": {...}}`, or
+// as a string in the prometheus text format.
+pub const METRICS: &str = "tremor.metrics";
+
+// argument: an object like
+// `{"expression": "...", "event": {...}, "state": {...}, "uri": "...", "reset": false}`
+// where all but the expression are optional. evaluates the expression in a persistent
+// session (definitions and state carry over), returning the emitted value and new state.
+pub const EVAL: &str = "tremor.eval";
+
+// argument: an object like `{"uri": "...", "eventsFile": "..."}`. runs each event from the
+// (newline delimited json) events file through the document, logging the outputs as they
+// come. returns counts of the emitted, dropped and failed events. trickle queries need the
+// runtime feature.
+pub const RUN_PIPELINE: &str = "tremor.runPipeline";
+
+// argument: an object like `{"uri": "...", "event": {...}, "iterations": 1000}`, where
+// the event and iterations are optional. runs the document against the event repeatedly,
+// returning the throughput and latency percentiles.
+pub const BENCHMARK_SCRIPT: &str = "tremor.benchmarkScript";
+
+// argument: an object like `{"uri": "...", "position": {"line": 0, "character": 0}}`.
+// returns `{"ranges": [...]}` with all the occurrences of the stream or window name at the
+// position, for editing them together. stands in for textDocument/linkedEditingRange,
+// which the protocol version we implement does not have.
+pub const LINKED_EDITING_RANGES: &str = "tremor.linkedEditingRanges";
+
+// argument: an object like `{"uri": "...", "previousResultId": "..."}`. returns the
+// diagnostics of the open document as a document diagnostic report (lsp 3.17), that is
+// `{"kind": "full", "resultId": "...", "items": [...]}`, or `{"kind": "unchanged", ...}`
+// if they are the same as for the previous result id. stands in for textDocument/diagnostic.
+pub const DOCUMENT_DIAGNOSTICS: &str = "tremor.documentDiagnostics";
+
+// argument: an optional object like `{"previousResultIds": [{"uri": "...", "value": "..."}]}`.
+// returns `{"items": [...]}`, with a document diagnostic report (along with the uri and
+// version) for each file in the workspace. stands in for workspace/diagnostic.
+pub const WORKSPACE_DIAGNOSTICS: &str = "tremor.workspaceDiagnostics";
+
+// argument: an object like `{"files": [{"oldUri": "...", "newUri": "..."}]}`, for module
+// files (or directories) about to be renamed. returns a workspace edit updating the use
+// statements across the workspace to the new module paths. stands in for
+// workspace/willRenameFiles, which the protocol version we implement does not have. with
+// `"annotate": true` (for clients supporting change annotations), edits spanning several
+// documents are marked as needing confirmation, so that the client previews them first.
+pub const WILL_RENAME_FILES: &str = "tremor.willRenameFiles";
+
+// argument: an object like `{"name": "string::format", "uri": "..."}`, where the name is
+// of a module or a function (or empty, for the list of modules) and the uri is optional.
+// returns `{"uri": "tremor-docs:///...", "content": "..."}`, with the full markdown
+// documentation for the client to show as a (virtual) document.
+pub const OPEN_DOCS: &str = "tremor.openDocs";
+
+// argument: uri of the document to expand. returns `{"text": "...", "unresolved": [...]}`,
+// with the text of the document where the local modules it uses (and the modules they use in
+// turn) are inlined as `mod ... with ... end;` blocks, the way the runtime sees it. use
+// statements for modules that are not found (eg. builtin ones) are kept and listed.
+pub const INLINE_MODULES: &str = "tremor.inlineModules";
+
+// argument: uri of the query. returns `{"uri": "tremor-inlined:///...", "content": "...",
+// "unresolved": [...]}`, with the query where the modules it uses are inlined (as for
+// tremor.inlineModules), for the client to show as a (virtual) document. constants and
+// `with` arguments are not folded.
+pub const SHOW_INLINED_MODULES: &str = "tremor.showInlinedModules";
+
+// argument: uri of the document. returns `{"ast": {...}}` with the syntax tree of the
+// document as tremor-script builds it, or `{"error": "..."}` if it does not parse. nodes
+// refer to their spans (start and end line, column and offset) by their `mid`, an index
+// into `node_meta`. experimental: the structure follows the tremor-script version.
+pub const AST: &str = "tremor.ast";
+
+// no arguments. computes and publishes the diagnostics of every tremor file in the workspace
+// (with unsaved edits for the open ones), returning `{"files": 3, "errors": 1, "warnings": 0,
+// "failed": ["file:///..."]}`, where the failed files are the ones with errors.
+pub const VALIDATE_WORKSPACE: &str = "tremor.validateWorkspace";
+
+// argument: uri of a trickle document. returns its pipeline graph, as
+// `{"nodes": [{"name": "...", "kind": "stream"}], "edges": [{"from": "...", "into": "...",
+// "windows": [...]}], "dot": "digraph ..."}`, for clients to show.
+pub const PIPELINE_GRAPH: &str = "tremor.pipelineGraph";
+
+// no arguments. returns `{"files": [{"uri": "...", "parse": "ok", "defines": 2, "scripts": 1,
+// "windows": 1, "operators": 0, "streams": 1, "functions": 0, "errors": 0, "warnings": 1,
+// "lints": {"unconnected-stream": 1}}], "totals": {...}}` for every tremor file in the
+// workspace (with unsaved edits for the open ones), where parse is `ok`, `failed` or
+// `skipped` (for files above the maxFileSize setting). the totals have the same counts, along
+// with the number of files per parse status. for clients to show the health of the project.
+pub const WORKSPACE_STATS: &str = "tremor.workspaceStats";
+
+// argument: uri of a trickle document. creates a deployment file next to it (`main.yaml` for
+// `main.trickle`), with an onramp and offramp for each of the pipeline's ports, the binding
+// linking them and a mapping, as a starting point. returns `{"uri": "...", "content": "..."}`,
+// where the uri is null for scratchpads (with no directory to create it in).
+pub const SCAFFOLD_DEPLOYMENT: &str = "tremor.scaffoldDeployment";
+
+// no arguments. reloads the function docs from the `docsPath` setting (eg: after updating the
+// docs there), republishing the diagnostics. returns `{"path": "...", "functions": 120}`, with
+// the number of functions documented there, or `{"error": "..."}` if they can't be loaded
+// (leaving the built-in docs in use).
+pub const RELOAD_DOCS: &str = "tremor.reloadDocs";
+
+// no arguments. returns the ids of the artefacts deployed to the tremor node (at the
+// `runtimeUrl` setting), per kind, eg: `{"pipeline": ["main"], "onramp": [], ...}`.
+#[cfg(feature = "runtime")]
+pub const RUNTIME_ARTEFACTS: &str = "tremor.runtimeArtefacts";
+
+// argument: uri of a trickle document. publishes it as a pipeline to the tremor node (at the
+// `runtimeUrl` setting), returning the pipeline artefact as tremor has it.
+#[cfg(feature = "runtime")]
+pub const DEPLOY_TO_RUNTIME: &str = "tremor.deployToRuntime";
+
+pub const ALL: &[&str] = &[
+    ORGANIZE_IMPORTS,
+    SERVER_STATUS,
+    METRICS,
+    EVAL,
+    RUN_PIPELINE,
+    BENCHMARK_SCRIPT,
+    LINKED_EDITING_RANGES,
+    DOCUMENT_DIAGNOSTICS,
+    WORKSPACE_DIAGNOSTICS,
+    WILL_RENAME_FILES,
+    OPEN_DOCS,
+    INLINE_MODULES,
+    SHOW_INLINED_MODULES,
+    AST,
+    VALIDATE_WORKSPACE,
+    PIPELINE_GRAPH,
+    WORKSPACE_STATS,
+    SCAFFOLD_DEPLOYMENT,
+    RELOAD_DOCS,
+];
+
+// only there with the runtime feature
+#[cfg(feature = "runtime")]
+pub const RUNTIME: &[&str] = &[RUNTIME_ARTEFACTS, DEPLOY_TO_RUNTIME];
+
+// true for the commands the server advertises (and so can handle)
+pub fn is_known(command: &str) -> bool {
+    #[cfg(feature = "runtime")]
+    let runtime = RUNTIME.contains(&command);
+    #[cfg(not(feature = "runtime"))]
+    let runtime = false;
+    ALL.contains(&command) || runtime
+}
@@ -0,0 +1,526 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::cases;
+use crate::fuzzy;
+use crate::imports::{self, Import};
+use crate::language::{Language, Token, TokenSpan};
+use crate::lsp_utils;
+use crate::metadata;
+use crate::modules;
+use crate::params;
+use crate::patch;
+use crate::pipeline;
+use crate::symbols;
+use tower_lsp::lsp_types::*;
+
+// kind of name expected at the completion position, within a select statement
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SelectSlot {
+    // after `from` or `into`
+    Node,
+    // in the window list, eg: `from in[`
+    Window,
+}
+
+// what we know about the code surrounding the completion position
+#[derive(Debug, Default)]
+pub struct Context {
+    // cursor is within a select statement (trickle)
+    pub in_select: bool,
+    // cursor follows a string expression, eg: `"prefix" + `
+    pub after_string: bool,
+    pub select_slot: Option<SelectSlot>,
+}
+
+impl Context {
+    pub fn new(text: &str, tokens: &[TokenSpan], position: Position) -> Self {
+        let location = lsp_utils::to_language_location_in(text, &position);
+        let preceding: Vec<&Token> = tokens
+            .iter()
+            .filter(|t| {
+                !t.value.is_ignorable()
+                    && (t.span.end.line(), t.span.end.column())
+                        <= (location.line(), location.column())
+            })
+            .map(|t| &t.value)
+            .collect();
+
+        // statement level keyword that the cursor position belongs to
+        let statement = preceding.iter().rev().find(|t| {
+            matches!(
+                t,
+                Token::Semi | Token::Select | Token::Define | Token::Create | Token::Let
+            )
+        });
+
+        // skip over the partially typed `module::function` path
+        let before_path = preceding
+            .iter()
+            .rev()
+            .skip_while(|t| matches!(t, Token::Ident(_, _) | Token::ColonColon))
+            .take(2)
+            .collect::<Vec<_>>();
+
+        let in_select = matches!(statement, Some(Token::Select));
+
+        // skip over the partially typed name, and earlier names in a window list
+        let before_name = preceding
+            .iter()
+            .rev()
+            .skip_while(|t| matches!(t, Token::Ident(_, _)))
+            .collect::<Vec<_>>();
+        let before_list = before_name
+            .iter()
+            .skip_while(|t| matches!(t, Token::Ident(_, _) | Token::Comma))
+            .take(5)
+            .collect::<Vec<_>>();
+        let select_slot = match (before_name.first(), before_list.as_slice()) {
+            _ if !in_select => None,
+            (Some(Token::From), _) | (Some(Token::Into), _) => Some(SelectSlot::Node),
+            // `from node[` or `from node/port[`
+            (_, [Token::LBracket, Token::Ident(_, _), Token::From, ..])
+            | (_, [Token::LBracket, Token::Ident(_, _), _, Token::Ident(_, _), Token::From]) => {
+                Some(SelectSlot::Window)
+            }
+            _ => None,
+        };
+
+        Self {
+            in_select,
+            select_slot,
+            after_string: matches!(
+                before_path.as_slice(),
+                [Token::Add, Token::DQuote, ..] | [Token::Add, Token::StringLiteral(_), ..]
+            ),
+        }
+    }
+}
+
+// a completion candidate along with how relevant it is
+pub struct Candidate {
+    pub item: CompletionItem,
+    pub score: i64,
+}
+
+// names defined in the query, for the select statement slot
+pub fn select_candidates(text: &str, tokens: &[TokenSpan], slot: SelectSlot) -> Vec<Candidate> {
+    let item = |label: &str, kind, detail: &str| Candidate {
+        item: CompletionItem {
+            label: label.to_string(),
+            kind: Some(kind),
+            detail: Some(detail.to_string()),
+            ..CompletionItem::default()
+        },
+        score: 0,
+    };
+
+    match slot {
+        SelectSlot::Node => pipeline::DEFAULT_STREAMS
+            .iter()
+            .map(|name| item(name, CompletionItemKind::Event, "default stream"))
+            .chain(
+                pipeline::nodes(text, tokens)
+                    .iter()
+                    .map(|node| match node.kind {
+                        pipeline::NodeKind::Stream => {
+                            item(&node.name.value, CompletionItemKind::Event, "stream")
+                        }
+                        pipeline::NodeKind::Operator => {
+                            item(&node.name.value, CompletionItemKind::Operator, "operator")
+                        }
+                        pipeline::NodeKind::Script => {
+                            item(&node.name.value, CompletionItemKind::Class, "script")
+                        }
+                    }),
+            )
+            .collect(),
+        SelectSlot::Window => pipeline::windows(text, tokens)
+            .iter()
+            .map(|window| {
+                item(
+                    &window.name.value,
+                    CompletionItemKind::Struct,
+                    &format!("{} window", window.kind),
+                )
+            })
+            .collect(),
+    }
+}
+
+// the builtin modules, documented with the functions they have
+pub fn module_candidates(language: &dyn Language, uri: &Url) -> Vec<Candidate> {
+    language
+        .modules(uri)
+        .into_iter()
+        .map(|module_name| {
+            let summary = modules::builtin_summary(language, uri, &module_name);
+
+            Candidate {
+                item: CompletionItem {
+                    label: module_name,
+                    kind: Some(CompletionItemKind::Module),
+                    documentation: summary.map(|value| {
+                        Documentation::MarkupContent(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value,
+                        })
+                    }),
+                    ..CompletionItem::default()
+                },
+                score: 0,
+            }
+        })
+        .collect()
+}
+
+// the modules the document uses, by the name it uses them with (eg: `s` for
+// `use std::string as s;`), ahead of the ones it could use
+pub fn import_candidates(imports: &[Import]) -> Vec<Candidate> {
+    imports
+        .iter()
+        .map(|import| Candidate {
+            item: CompletionItem {
+                label: import.name().to_string(),
+                kind: Some(CompletionItemKind::Module),
+                detail: Some(import.to_statement()),
+                ..CompletionItem::default()
+            },
+            score: 10,
+        })
+        .collect()
+}
+
+// the functions of the builtin modules the document uses, qualified by the name it uses them
+// with (eg: `s::lowercase` for `use std::string as s;`), so that they complete by their name
+pub fn imported_function_candidates(
+    language: &dyn Language,
+    uri: &Url,
+    document_imports: &[Import],
+) -> Vec<Candidate> {
+    let modules = language.modules(uri);
+    let mut candidates = Vec::new();
+    for import in document_imports {
+        let module_name = imports::unalias(document_imports, import.name(), &modules);
+        if !modules.contains(&module_name) {
+            continue;
+        }
+        for function_name in language.functions(uri, &module_name) {
+            let label = format!("{}::{}", import.name(), function_name);
+            let doc = language.function_doc(uri, &format!("{}::{}", module_name, function_name));
+            let args: Vec<String> = doc.map_or(vec![], |doc| {
+                doc.signature
+                    .args
+                    .iter()
+                    .enumerate()
+                    .map(|(i, arg)| format!("${{{}:{}}}", i + 1, arg))
+                    .collect()
+            });
+            candidates.push(Candidate {
+                item: CompletionItem {
+                    insert_text: Some(format!("{}({})", label, args.join(", "))),
+                    insert_text_format: Some(InsertTextFormat::Snippet),
+                    label,
+                    kind: Some(CompletionItemKind::Function),
+                    detail: doc.map(|doc| doc.signature.to_string()),
+                    documentation: doc.map(|doc| {
+                        Documentation::MarkupContent(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value: doc.description.clone(),
+                        })
+                    }),
+                    ..CompletionItem::default()
+                },
+                score: 0,
+            });
+        }
+    }
+    candidates
+}
+
+// functions defined in the text, documented with their doc comments
+pub fn function_candidates(text: &str, tokens: &[TokenSpan]) -> Vec<Candidate> {
+    symbols::collect(text, tokens)
+        .into_iter()
+        .filter(|symbol| symbol.kind == SymbolKind::Function)
+        .map(|symbol| Candidate {
+            item: CompletionItem {
+                label: symbol.name.clone(),
+                kind: Some(CompletionItemKind::Function),
+                detail: Some(symbols::signature(text, &symbol)),
+                documentation: symbol.doc.map(|value| {
+                    Documentation::MarkupContent(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value,
+                    })
+                }),
+                ..CompletionItem::default()
+            },
+            score: 0,
+        })
+        .collect()
+}
+
+// keywords that the grammar allows at the position, eg: `of` after `match event`
+pub fn keyword_candidates(
+    language: &dyn Language,
+    text: &str,
+    tokens: &[TokenSpan],
+    position: Position,
+) -> Vec<Candidate> {
+    let location = lsp_utils::to_language_location_in(text, &position);
+    let location = (location.line(), location.column());
+    let mut preceding: Vec<&Token> = tokens
+        .iter()
+        .filter(|t| !t.value.is_ignorable() && (t.span.end.line(), t.span.end.column()) <= location)
+        .map(|t| &t.value)
+        .collect();
+    // the keyword being typed, if any
+    let is_typing = tokens.iter().any(|t| {
+        matches!(t.value, Token::Ident(_, _))
+            && (t.span.end.line(), t.span.end.column()) == location
+    });
+    if is_typing {
+        preceding.pop();
+    }
+
+    // innermost construct that takes an `of`, and whether it got it already
+    let opener = preceding
+        .iter()
+        .rposition(|t| matches!(t, Token::Match | Token::For | Token::Patch | Token::Merge));
+    let has_of = opener.map_or(false, |i| preceding[i..].contains(&&Token::Of));
+
+    let keywords: &[&str] = match preceding.last() {
+        None | Some(Token::Semi) => language.statement_keywords(),
+        Some(Token::Define) => &["tumbling", "sliding", "script"],
+        // `define tumbling window`
+        Some(Token::Ident(_, _)) if preceding.iter().rev().nth(1) == Some(&&Token::Define) => {
+            &["window", "operator"]
+        }
+        Some(Token::Create) => &["stream", "operator", "script"],
+        Some(Token::Of) if opener.map(|i| preceding[i]) == Some(&Token::Match) => {
+            &["case", "default"]
+        }
+        // `match event ` (the keyword has something following it already)
+        _ if opener.map_or(false, |i| i + 1 < preceding.len()) && !has_of => &["of"],
+        _ => &[],
+    };
+
+    keywords
+        .iter()
+        .map(|keyword| Candidate {
+            item: CompletionItem {
+                label: keyword.to_string(),
+                kind: Some(CompletionItemKind::Keyword),
+                ..CompletionItem::default()
+            },
+            // what the grammar expects next is more likely than a module name
+            score: 5,
+        })
+        .collect()
+}
+
+// operation keywords or field names, for the slot in a patch expression
+pub fn patch_candidates(tokens: &[TokenSpan], slot: &patch::Slot) -> Vec<Candidate> {
+    match slot {
+        patch::Slot::Operation => patch::OPERATIONS
+            .iter()
+            .map(|(keyword, description)| Candidate {
+                item: CompletionItem {
+                    label: keyword.to_string(),
+                    kind: Some(CompletionItemKind::Keyword),
+                    documentation: Some(Documentation::MarkupContent(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value: description.to_string(),
+                    })),
+                    ..CompletionItem::default()
+                },
+                score: 0,
+            })
+            .collect(),
+        patch::Slot::Key(target) => patch::known_fields(tokens, target)
+            .into_iter()
+            .map(|field| Candidate {
+                item: CompletionItem {
+                    label: field.clone(),
+                    kind: Some(CompletionItemKind::Field),
+                    detail: Some(format!("field of {}", target)),
+                    insert_text: Some(format!("\"{}\"", field)),
+                    ..CompletionItem::default()
+                },
+                score: 0,
+            })
+            .collect(),
+    }
+}
+
+// parameters of the window or operator kind that are not set yet
+pub fn param_candidates(definition: &params::Definition) -> Vec<Candidate> {
+    definition
+        .kind
+        .params
+        .iter()
+        .filter(|(name, _)| !definition.params.iter().any(|p| p.value == *name))
+        .map(|(name, description)| Candidate {
+            item: CompletionItem {
+                label: name.to_string(),
+                kind: Some(CompletionItemKind::Property),
+                detail: Some(format!("{} parameter", definition.kind.name)),
+                documentation: Some(Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: description.to_string(),
+                })),
+                ..CompletionItem::default()
+            },
+            score: 0,
+        })
+        .collect()
+}
+
+// arm scaffolds for the match expression: a `case` per value not covered yet (and all of them
+// at once, with a `default`) if the values are known, or a blank `case` otherwise. the values
+// are escaped as in a tremor string already.
+pub fn case_candidates(slot: &cases::Slot, values: &[String]) -> Vec<Candidate> {
+    let item = |label: String, insert_text: String, detail: &str| Candidate {
+        item: CompletionItem {
+            label,
+            kind: Some(CompletionItemKind::Snippet),
+            detail: Some(detail.to_string()),
+            insert_text: Some(insert_text),
+            insert_text_format: Some(InsertTextFormat::Snippet),
+            ..CompletionItem::default()
+        },
+        score: 5,
+    };
+    let detail = format!("arm of match {}", slot.subject);
+
+    let missing: Vec<String> = values
+        .iter()
+        .map(|value| format!("\"{}\"", value))
+        .filter(|pattern| !slot.covered.contains(pattern))
+        .collect();
+    if values.is_empty() {
+        return vec![item(
+            "case \"\" =>".to_string(),
+            "case \"$1\" => $0".to_string(),
+            &detail,
+        )];
+    }
+
+    let mut candidates: Vec<Candidate> = missing
+        .iter()
+        .map(|pattern| {
+            item(
+                format!("case {} =>", pattern),
+                format!("case {} => $0", lsp_utils::escape_snippet(pattern)),
+                &detail,
+            )
+        })
+        .collect();
+    if missing.len() > 1 {
+        let mut arms: Vec<String> = missing
+            .iter()
+            .enumerate()
+            .map(|(i, pattern)| {
+                format!(
+                    "case {} => ${{{}:null}}",
+                    lsp_utils::escape_snippet(pattern),
+                    i + 1
+                )
+            })
+            .collect();
+        if !slot.has_default {
+            arms.push(format!("default => ${{{}:null}}", missing.len() + 1));
+        }
+        candidates.push(item(
+            format!("case ... (all {} values)", missing.len()),
+            arms.join(&format!("\n{}", slot.indentation)),
+            &detail,
+        ));
+    }
+    candidates
+}
+
+// well-known metadata names, or the fields of one, documented with their structure
+pub fn metadata_candidates(slot: &metadata::Slot) -> Vec<Candidate> {
+    let item = |label: &str, kind, detail: String, doc: String| Candidate {
+        item: CompletionItem {
+            label: label.to_string(),
+            kind: Some(kind),
+            detail: Some(detail),
+            documentation: Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: doc,
+            })),
+            ..CompletionItem::default()
+        },
+        score: 0,
+    };
+
+    match slot {
+        metadata::Slot::Name => metadata::FIELDS
+            .iter()
+            .map(|field| {
+                item(
+                    field.name,
+                    CompletionItemKind::Variable,
+                    "event metadata".to_string(),
+                    metadata::document(field),
+                )
+            })
+            .collect(),
+        metadata::Slot::Field(field) => field
+            .fields
+            .iter()
+            .map(|(name, description)| {
+                item(
+                    name,
+                    CompletionItemKind::Property,
+                    format!("${} field", field.name),
+                    description.to_string(),
+                )
+            })
+            .collect(),
+    }
+}
+
+// applies the typed prefix as a filter and orders the remaining items by relevance,
+// encoding the order in sort_text (clients sort by that, not by the order we send).
+pub fn rank(candidates: Vec<Candidate>, prefix: &str) -> Vec<CompletionItem> {
+    let mut candidates: Vec<Candidate> = candidates
+        .into_iter()
+        .filter_map(|mut candidate| {
+            fuzzy::score(prefix, &candidate.item.label).map(|score| {
+                candidate.score += score;
+                candidate
+            })
+        })
+        .collect();
+
+    candidates.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| a.item.label.cmp(&b.item.label))
+    });
+
+    candidates
+        .into_iter()
+        .enumerate()
+        .map(|(i, candidate)| CompletionItem {
+            sort_text: Some(format!("{:04}", i)),
+            filter_text: Some(candidate.item.label.clone()),
+            ..candidate.item
+        })
+        .collect()
+}
@@ -0,0 +1,272 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// user settings, as received from the client (via initialization options or
+// workspace/didChangeConfiguration). eg:
+//
+// {
+//   "tremor": {
+//     "lints": { "unreachable-case": "error", "some-other-lint": "off" },
+//     "path": ["lib"],
+//     "completionDetail": "signature",
+//     "runtimeUrl": "http://localhost:9898",
+//     "strict": true,
+//     "hintSeverity": "hint",
+//     "indexExclude": ["vendor", "**/*.gen.tremor"],
+//     "maxFileSize": 2048,
+//     "maxNumberOfProblems": 100,
+//     "tremorVersion": "0.9",
+//     "fileLanguages": { "*.tq": "trickle" },
+//     "parseStats": false,
+//     "docsPath": "/path/to/tremor-www-docs"
+//   }
+// }
+//
+// workspace roots can also have their own settings, in their project manifest (see
+// project.rs).
+
+use crate::language;
+use crate::workspace;
+use halfbrown::HashMap;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::DiagnosticSeverity;
+
+// all the settings live under this key
+pub const SECTION: &str = "tremor";
+
+// size (in KB) above which files only get syntax checks, unless set otherwise
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 2048;
+
+// how much of what we know goes into completion items
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompletionDetail {
+    // signatures and documentation
+    Full,
+    Signature,
+    Label,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    // severity per lint code, with None meaning that the lint is disabled
+    pub lint_severities: HashMap<String, Option<DiagnosticSeverity>>,
+    // module directories, on top of TREMOR_PATH
+    pub path: Vec<PathBuf>,
+    // None if not set (ie. full)
+    pub completion_detail: Option<CompletionDetail>,
+    // api of the tremor node that the runtime commands talk to
+    pub runtime_url: Option<String>,
+    // warnings are reported as errors, None if not set (ie. off)
+    pub strict: Option<bool>,
+    // severity of separate diagnostics for the hints of parser errors, with None meaning
+    // that hints go into the message of the error. outer None if not set (ie. inline)
+    pub hint_severity: Option<Option<DiagnosticSeverity>>,
+    // patterns of the paths not to index (on top of target, node_modules and .gitignore)
+    pub index_exclude: Vec<String>,
+    // size (in KB) above which files only get syntax checks (no linting, interpreting or
+    // indexing), with 0 for no limit. None if not set (ie. the default)
+    pub max_file_size: Option<u64>,
+    // diagnostics reported per document, None if not set (ie. all of them)
+    pub max_number_of_problems: Option<u64>,
+    // tremor release the documents are for (eg: `0.9`), None if not set (ie. the one the
+    // server is built with)
+    pub tremor_version: Option<String>,
+    // (pattern, file extension of the language) for files of non-standard names, with the
+    // ones of more specific settings first
+    pub file_languages: Vec<(String, &'static str)>,
+    // send a tremor/parseStats notification along with the diagnostics, None if not set (ie.
+    // off)
+    pub parse_stats: Option<bool>,
+    // directory of tremor docs to take the function docs from, over the built-in ones
+    pub docs_path: Option<PathBuf>,
+}
+
+impl Config {
+    // settings can come with or without the section wrapper, depending on the client
+    pub fn from_json(value: &Value) -> Self {
+        let settings = value.get(SECTION).unwrap_or(value);
+
+        let mut config = Self::default();
+        if let Some(lints) = settings.get("lints").and_then(Value::as_object) {
+            for (code, severity) in lints {
+                match severity.as_str().map(parse_severity) {
+                    Some(Ok(severity)) => {
+                        config.lint_severities.insert(code.clone(), severity);
+                    }
+                    // bad values are ignored, leaving the lint at its default severity
+                    _ => continue,
+                }
+            }
+        }
+        config.path = match settings.get("path") {
+            Some(Value::String(path)) => std::env::split_paths(path).collect(),
+            Some(Value::Array(paths)) => paths
+                .iter()
+                .filter_map(Value::as_str)
+                .map(PathBuf::from)
+                .collect(),
+            _ => vec![],
+        };
+        config.completion_detail = match settings.get("completionDetail").and_then(Value::as_str) {
+            Some("full") => Some(CompletionDetail::Full),
+            Some("signature") => Some(CompletionDetail::Signature),
+            Some("label") => Some(CompletionDetail::Label),
+            _ => None,
+        };
+        config.runtime_url = settings
+            .get("runtimeUrl")
+            .and_then(Value::as_str)
+            .map(String::from);
+        config.strict = settings.get("strict").and_then(Value::as_bool);
+        config.parse_stats = settings.get("parseStats").and_then(Value::as_bool);
+        config.docs_path = settings
+            .get("docsPath")
+            .and_then(Value::as_str)
+            .filter(|path| !path.trim().is_empty())
+            .map(PathBuf::from);
+        config.index_exclude = match settings.get("indexExclude") {
+            Some(Value::String(pattern)) => vec![pattern.clone()],
+            Some(Value::Array(patterns)) => patterns
+                .iter()
+                .filter_map(Value::as_str)
+                .map(String::from)
+                .collect(),
+            _ => vec![],
+        };
+        config.max_file_size = settings.get("maxFileSize").and_then(Value::as_u64);
+        config.max_number_of_problems = settings.get("maxNumberOfProblems").and_then(Value::as_u64);
+        config.tremor_version = settings
+            .get("tremorVersion")
+            .and_then(Value::as_str)
+            .map(|version| version.trim().to_string())
+            .filter(|version| !version.is_empty());
+        if let Some(file_languages) = settings.get("fileLanguages").and_then(Value::as_object) {
+            for (pattern, name) in file_languages {
+                // unknown languages are ignored
+                if let Some(extension) = name.as_str().and_then(language::file_extension) {
+                    config.file_languages.push((pattern.clone(), extension));
+                }
+            }
+        }
+        config.hint_severity = match settings.get("hintSeverity").and_then(Value::as_str) {
+            Some("inline") => Some(None),
+            Some("hint") => Some(Some(DiagnosticSeverity::Hint)),
+            Some("information") | Some("info") => Some(Some(DiagnosticSeverity::Information)),
+            _ => None,
+        };
+        config
+    }
+
+    // these settings, with the ones set in the other taking precedence
+    pub fn merged(&self, other: &Self) -> Self {
+        let mut config = self.clone();
+        for (code, severity) in &other.lint_severities {
+            config.lint_severities.insert(code.clone(), *severity);
+        }
+        config.path.splice(0..0, other.path.iter().cloned());
+        config.completion_detail = other.completion_detail.or(self.completion_detail);
+        config.runtime_url = other
+            .runtime_url
+            .clone()
+            .or_else(|| self.runtime_url.clone());
+        config.strict = other.strict.or(self.strict);
+        config.parse_stats = other.parse_stats.or(self.parse_stats);
+        config.docs_path = other.docs_path.clone().or_else(|| self.docs_path.clone());
+        config.hint_severity = other.hint_severity.or(self.hint_severity);
+        config.max_file_size = other.max_file_size.or(self.max_file_size);
+        config.max_number_of_problems =
+            other.max_number_of_problems.or(self.max_number_of_problems);
+        config.tremor_version = other
+            .tremor_version
+            .clone()
+            .or_else(|| self.tremor_version.clone());
+        config
+            .index_exclude
+            .extend(other.index_exclude.iter().cloned());
+        config
+            .file_languages
+            .splice(0..0, other.file_languages.iter().cloned());
+        config
+    }
+
+    pub fn completion_detail(&self) -> CompletionDetail {
+        self.completion_detail.unwrap_or(CompletionDetail::Full)
+    }
+
+    pub fn strict(&self) -> bool {
+        self.strict.unwrap_or(false)
+    }
+
+    pub fn parse_stats(&self) -> bool {
+        self.parse_stats.unwrap_or(false)
+    }
+
+    pub fn max_file_size(&self) -> u64 {
+        self.max_file_size.unwrap_or(DEFAULT_MAX_FILE_SIZE)
+    }
+
+    // whether a file of the size (in bytes) is too large for full analysis
+    pub fn is_large(&self, size: u64) -> bool {
+        match self.max_file_size() {
+            0 => false,
+            limit => size > limit * 1024,
+        }
+    }
+
+    // file extension of the language of the file (relative to its workspace root) by the
+    // fileLanguages setting, if it's mapped to one
+    pub fn language_for(&self, relative: &Path) -> Option<&'static str> {
+        self.file_languages
+            .iter()
+            .find(|(pattern, _)| workspace::matches_pattern(relative, pattern))
+            .map(|(_, extension)| *extension)
+    }
+
+    pub fn hint_severity(&self) -> Option<DiagnosticSeverity> {
+        self.hint_severity.flatten()
+    }
+
+    // severity to report a diagnostic with, raising warnings in strict mode
+    pub fn severity(&self, severity: DiagnosticSeverity) -> DiagnosticSeverity {
+        if self.strict() && severity == DiagnosticSeverity::Warning {
+            DiagnosticSeverity::Error
+        } else {
+            severity
+        }
+    }
+
+    // severity to use for the lint, or None if it should not be reported at all
+    pub fn lint_severity(
+        &self,
+        code: &str,
+        default: DiagnosticSeverity,
+    ) -> Option<DiagnosticSeverity> {
+        match self.lint_severities.get(code) {
+            Some(severity) => severity.map(|severity| self.severity(severity)),
+            None => Some(self.severity(default)),
+        }
+    }
+}
+
+fn parse_severity(s: &str) -> Result<Option<DiagnosticSeverity>, ()> {
+    match s.to_lowercase().as_str() {
+        "error" => Ok(Some(DiagnosticSeverity::Error)),
+        "warning" => Ok(Some(DiagnosticSeverity::Warning)),
+        "information" | "info" => Ok(Some(DiagnosticSeverity::Information)),
+        "hint" => Ok(Some(DiagnosticSeverity::Hint)),
+        "off" | "none" => Ok(None),
+        _ => Err(()),
+    }
+}
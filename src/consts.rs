@@ -0,0 +1,75 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::language::{Token, TokenSpan};
+use crate::lsp_utils;
+use tower_lsp::lsp_types::*;
+
+// a `const NAME = ...;` definition from a document
+#[derive(Debug, Clone)]
+pub struct Const {
+    pub name: String,
+    // covers just the name
+    pub range: Range,
+    // the full statement text, including the terminating `;`
+    pub statement: String,
+}
+
+pub fn collect(text: &str, tokens: &[TokenSpan]) -> Vec<Const> {
+    let tokens: Vec<&TokenSpan> = tokens.iter().filter(|t| !t.value.is_ignorable()).collect();
+
+    let mut consts = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        if t.value != Token::Const {
+            continue;
+        }
+        let name_token = match tokens.get(i + 1) {
+            Some(name_token) => name_token,
+            None => continue,
+        };
+        let name = match &name_token.value {
+            Token::Ident(name, _) => name.to_string(),
+            _ => continue,
+        };
+
+        // statement ends at the first `;` outside of any brackets
+        let mut depth = 0;
+        let end = tokens.iter().skip(i).find(|t| {
+            match t.value {
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+                _ => (),
+            }
+            depth <= 0 && t.value == Token::Semi
+        });
+
+        if let Some(end) = end {
+            let start_offset = lsp_utils::to_offset(text, &t.span.start);
+            let end_offset = lsp_utils::to_offset(text, &end.span.end);
+            consts.push(Const {
+                name,
+                range: Range {
+                    start: lsp_utils::to_lsp_position_in(text, &name_token.span.start),
+                    end: lsp_utils::to_lsp_position_in(text, &name_token.span.end),
+                },
+                statement: text
+                    .get(start_offset..end_offset)
+                    .unwrap_or_default()
+                    .to_string(),
+            });
+        }
+    }
+
+    consts
+}
@@ -0,0 +1,375 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Debug Adapter Protocol server for tremor-script (over stdio).
+//
+// The interpreter has no hooks for pausing evaluation, so stepping works on top-level
+// statements instead: stopping at a statement runs the script up to (and excluding) it
+// against the test event, and captures `event`, `state` and the `let` bindings so far.
+//
+// https://microsoft.github.io/debug-adapter-protocol/specification
+
+use crate::blocks;
+use crate::language::{Language, Token};
+use crate::lsp_utils;
+use crate::paths;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+use tower_lsp::lsp_types::Url;
+
+const THREAD_ID: i64 = 1;
+
+// variable references for the scopes
+const LOCALS_REFERENCE: i64 = 1;
+const EVENT_REFERENCE: i64 = 2;
+const STATE_REFERENCE: i64 = 3;
+
+// a top-level statement of the script being debugged
+struct Statement {
+    // zero-based, as in lsp
+    line: u64,
+    text: String,
+    // name bound via `let`, if any
+    binding: Option<String>,
+}
+
+#[derive(Default)]
+struct Session {
+    uri: Option<Url>,
+    statements: Vec<Statement>,
+    // json
+    event: String,
+    breakpoints: Vec<u64>,
+    // index of the statement we are stopped at
+    current: Option<usize>,
+    // event, state and locals at the current statement
+    snapshot: Value,
+}
+
+pub fn serve(language: Box<dyn Language>) -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut output = Output {
+        out: stdout.lock(),
+        seq: 1,
+    };
+    let mut session = Session {
+        event: "null".to_string(),
+        ..Session::default()
+    };
+
+    while let Some(request) = read_message(&mut input)? {
+        let command = request["command"].as_str().unwrap_or_default().to_string();
+        let args = &request["arguments"];
+
+        let body = match command.as_str() {
+            "initialize" => Ok(json!({ "supportsConfigurationDoneRequest": true })),
+            "launch" => launch(&mut session, language.as_ref(), args),
+            "setBreakpoints" => {
+                session.breakpoints = args["breakpoints"]
+                    .as_array()
+                    .map(|bps| {
+                        bps.iter()
+                            .filter_map(|bp| bp["line"].as_u64())
+                            // dap lines are one-based here
+                            .map(|line| line.saturating_sub(1))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let breakpoints: Vec<Value> = session
+                    .breakpoints
+                    .iter()
+                    .map(|line| json!({ "verified": true, "line": line + 1 }))
+                    .collect();
+                Ok(json!({ "breakpoints": breakpoints }))
+            }
+            "configurationDone" | "continue" | "next" => Ok(json!({})),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            "stackTrace" => Ok(stack_trace(&session)),
+            "scopes" => Ok(json!({
+                "scopes": [
+                    { "name": "Locals", "variablesReference": LOCALS_REFERENCE, "expensive": false },
+                    { "name": "Event", "variablesReference": EVENT_REFERENCE, "expensive": false },
+                    { "name": "State", "variablesReference": STATE_REFERENCE, "expensive": false },
+                ]
+            })),
+            "variables" => Ok(variables(&session, args["variablesReference"].as_i64())),
+            "disconnect" => {
+                output.respond(&request, Ok(json!({})))?;
+                return Ok(());
+            }
+            _ => Err(format!("Unsupported request: {}", command)),
+        };
+        output.respond(&request, body)?;
+
+        // execution control happens after the response, as the protocol expects
+        match command.as_str() {
+            "initialize" => output.event("initialized", json!({}))?,
+            "configurationDone" | "continue" => {
+                let next = session.current.map_or(0, |i| i + 1);
+                let stop = (next..session.statements.len())
+                    .find(|i| session.breakpoints.contains(&session.statements[*i].line));
+                advance(
+                    &mut session,
+                    language.as_ref(),
+                    stop,
+                    "breakpoint",
+                    &mut output,
+                )?;
+            }
+            "next" => {
+                let next = session.current.map_or(0, |i| i + 1);
+                let stop = Some(next).filter(|i| *i < session.statements.len());
+                advance(&mut session, language.as_ref(), stop, "step", &mut output)?;
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+fn launch(session: &mut Session, language: &dyn Language, args: &Value) -> Result<Value, String> {
+    let program = args["program"].as_str().ok_or("Missing program")?;
+    let text = fs::read_to_string(program).map_err(|e| e.to_string())?;
+    let uri = paths::to_uri(Path::new(program)).ok_or("Bad program path")?;
+
+    session.event = match (&args["event"], args["eventFile"].as_str()) {
+        (_, Some(event_file)) => fs::read_to_string(event_file).map_err(|e| e.to_string())?,
+        (Value::Null, None) => "null".to_string(),
+        (event, None) => event.to_string(),
+    };
+    session.statements = statements(language, &uri, &text).ok_or("Failed to tokenize program")?;
+    session.uri = Some(uri);
+    session.current = None;
+    Ok(json!({}))
+}
+
+// runs up to the statement at index (or the whole script if there's none), reporting
+// the stop or the end of the debugging session to the client
+fn advance<W: Write>(
+    session: &mut Session,
+    language: &dyn Language,
+    stop: Option<usize>,
+    reason: &str,
+    output: &mut Output<W>,
+) -> io::Result<()> {
+    let uri = match &session.uri {
+        Some(uri) => uri.clone(),
+        None => return output.event("terminated", json!({})),
+    };
+
+    match stop {
+        Some(index) => {
+            let before = &session.statements[..index];
+            let locals: Vec<String> = before
+                .iter()
+                .filter_map(|s| s.binding.as_ref())
+                .map(|name| format!("\"{}\": {}", name, name))
+                .collect();
+            let script = format!(
+                "{}\n{{\"event\": event, \"state\": state, \"locals\": {{{}}}}}",
+                before
+                    .iter()
+                    .map(|s| s.text.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                locals.join(", ")
+            );
+
+            session.snapshot = match language.run(&uri, &script, &session.event, "null") {
+                Some(Ok(run)) => run
+                    .value
+                    .and_then(|v| serde_json::from_str(&v).ok())
+                    .unwrap_or(Value::Null),
+                Some(Err(e)) => {
+                    output.event("output", json!({ "category": "stderr", "output": e }))?;
+                    Value::Null
+                }
+                None => Value::Null,
+            };
+            session.current = Some(index);
+            output.event(
+                "stopped",
+                json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+            )
+        }
+        None => {
+            let script = session
+                .statements
+                .iter()
+                .map(|s| s.text.as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+            let result = match language.run(&uri, &script, &session.event, "null") {
+                Some(Ok(run)) => match run.value {
+                    Some(value) => format!("emit {}\n", value),
+                    None => "drop\n".to_string(),
+                },
+                Some(Err(e)) => format!("error: {}\n", e),
+                None => "evaluation is not supported\n".to_string(),
+            };
+            output.event("output", json!({ "category": "stdout", "output": result }))?;
+            output.event("terminated", json!({}))
+        }
+    }
+}
+
+fn stack_trace(session: &Session) -> Value {
+    let frames: Vec<Value> = session
+        .current
+        .and_then(|i| session.statements.get(i))
+        .map(|statement| {
+            json!({
+                "id": 1,
+                "name": "script",
+                "line": statement.line + 1,
+                "column": 1,
+                "source": { "path": session.uri.as_ref().and_then(|u| u.to_file_path().ok()) },
+            })
+        })
+        .into_iter()
+        .collect();
+    json!({ "stackFrames": frames, "totalFrames": frames.len() })
+}
+
+fn variables(session: &Session, reference: Option<i64>) -> Value {
+    let value = match reference {
+        Some(LOCALS_REFERENCE) => &session.snapshot["locals"],
+        Some(EVENT_REFERENCE) => &session.snapshot["event"],
+        Some(STATE_REFERENCE) => &session.snapshot["state"],
+        _ => &Value::Null,
+    };
+    let variables: Vec<Value> = match value {
+        Value::Object(map) => map
+            .iter()
+            .map(|(name, value)| json!({ "name": name, "value": value.to_string(), "variablesReference": 0 }))
+            .collect(),
+        Value::Null => vec![],
+        other => vec![json!({ "name": "value", "value": other.to_string(), "variablesReference": 0 })],
+    };
+    json!({ "variables": variables })
+}
+
+// splits the script into its top-level statements
+fn statements(language: &dyn Language, uri: &Url, text: &str) -> Option<Vec<Statement>> {
+    let tokens = language.tokenize(uri, text)?;
+    let tokens = blocks::significant(&tokens);
+
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut depth = 0;
+    for (i, t) in tokens.iter().enumerate() {
+        match &t.value {
+            v if blocks::opens_expression(v) || matches!(v, Token::Fn | Token::Module) => {
+                depth += 1
+            }
+            Token::End => depth -= 1,
+            Token::Semi if depth <= 0 => {
+                let first = tokens[start];
+                let binding = match (&first.value, tokens.get(start + 1).map(|t| &t.value)) {
+                    (Token::Let, Some(Token::Ident(name, _))) => Some(name.to_string()),
+                    _ => None,
+                };
+                let from = lsp_utils::to_offset(text, &first.span.start);
+                let to = lsp_utils::to_offset(text, &t.span.end);
+                statements.push(Statement {
+                    line: lsp_utils::to_lsp_position_in(text, &first.span.start).line,
+                    text: text.get(from..to).unwrap_or_default().to_string(),
+                    binding,
+                });
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    // the last statement does not need a terminating `;`
+    if let (Some(first), Some(last)) = (tokens.get(start), tokens.last()) {
+        let from = lsp_utils::to_offset(text, &first.span.start);
+        let to = lsp_utils::to_offset(text, &last.span.end);
+        statements.push(Statement {
+            line: lsp_utils::to_lsp_position_in(text, &first.span.start).line,
+            text: text.get(from..to).unwrap_or_default().to_string(),
+            binding: None,
+        });
+    }
+
+    Some(statements)
+}
+
+// dap uses the same framing as lsp
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(length) = line.strip_prefix("Content-Length:") {
+            content_length = length.trim().parse::<usize>().ok();
+        }
+    }
+
+    let mut content = vec![0; content_length.unwrap_or(0)];
+    input.read_exact(&mut content)?;
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+struct Output<W: Write> {
+    out: W,
+    seq: i64,
+}
+
+impl<W: Write> Output<W> {
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+        let content = message.to_string();
+        write!(
+            self.out,
+            "Content-Length: {}\r\n\r\n{}",
+            content.len(),
+            content
+        )?;
+        self.out.flush()
+    }
+
+    fn respond(&mut self, request: &Value, body: Result<Value, String>) -> io::Result<()> {
+        let (success, body, message) = match body {
+            Ok(body) => (true, body, None),
+            Err(e) => (false, json!({}), Some(e)),
+        };
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": success,
+            "message": message,
+            "body": body,
+        }))
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// tremor-script embedded in trickle queries, ie. the bodies of
+// `define script <name> [with <args>] script <body> end;` statements
+
+use crate::blocks;
+use crate::language::{Token, TokenSpan};
+use crate::lsp_utils;
+use tower_lsp::lsp_types::*;
+
+pub struct Script<'a, 'input> {
+    // between the `script` keyword opening the body and the closing `end`
+    pub range: Range,
+    // of the body, taken from the ones of the whole query
+    pub tokens: &'a [TokenSpan<'input>],
+}
+
+pub fn scripts<'a, 'input>(text: &str, tokens: &'a [TokenSpan<'input>]) -> Vec<Script<'a, 'input>> {
+    // where each of the significant tokens is in all the tokens
+    let indices: Vec<usize> = (0..tokens.len())
+        .filter(|i| !tokens[*i].value.is_ignorable())
+        .collect();
+    let significant = blocks::significant(tokens);
+
+    let mut scripts = Vec::new();
+    for (i, t) in significant.iter().enumerate() {
+        if t.value != Token::Define
+            || significant.get(i + 1).map(|t| &t.value) != Some(&Token::Script)
+        {
+            continue;
+        }
+        let end = match blocks::block_end(&significant, i) {
+            Some(end) => end,
+            None => continue,
+        };
+        // the second `script` keyword opens the body
+        let body = match (i + 2..end).find(|j| significant[*j].value == Token::Script) {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        scripts.push(Script {
+            range: Range::new(
+                lsp_utils::token_range(text, significant[body]).end,
+                lsp_utils::token_range(text, significant[end]).start,
+            ),
+            tokens: &tokens[indices[body] + 1..indices[end]],
+        });
+    }
+    scripts
+}
+
+// true if the position is within the body of an embedded script
+pub fn contains(text: &str, tokens: &[TokenSpan], position: Position) -> bool {
+    scripts(text, tokens)
+        .iter()
+        .any(|script| script.range.start <= position && position <= script.range.end)
+}
@@ -0,0 +1,402 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// extracting the selected expression of a script into a function, and moving definitions
+// into a module
+
+use crate::blocks;
+use crate::imports;
+use crate::language::{Token, TokenSpan};
+use crate::lsp_utils;
+use crate::symbols;
+use tower_lsp::lsp_types::*;
+
+// tokens that make the selection more than an expression
+fn is_statement_token(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Semi
+            | Token::Let
+            | Token::Emit
+            | Token::Drop
+            | Token::Fn
+            | Token::Const
+            | Token::Use
+            | Token::Module
+            | Token::Define
+            | Token::Create
+            | Token::Select
+    )
+}
+
+// edits adding a function with the selected expression as its body (above the statement the
+// selection is in) and calling it in place of the selection. the variables that the
+// expression uses from before it become the parameters. None if the selection is not a
+// whole expression.
+pub fn function(text: &str, tokens: &[TokenSpan], selection: Range) -> Option<Vec<TextEdit>> {
+    let defined: Vec<String> = symbols::collect(text, tokens)
+        .into_iter()
+        .map(|s| s.name)
+        .collect();
+    let tokens = blocks::significant(tokens);
+    let ranges: Vec<Range> = tokens
+        .iter()
+        .map(|t| lsp_utils::token_range(text, t))
+        .collect();
+
+    // the selection has to cover whole tokens
+    let first = ranges.iter().position(|r| r.end > selection.start)?;
+    let last = ranges.iter().rposition(|r| r.start < selection.end)?;
+    if first > last || ranges[first].start < selection.start || ranges[last].end > selection.end {
+        return None;
+    }
+
+    // ... and be balanced, without any statements or separators of its own
+    let (mut depth, mut nesting) = (0, 0);
+    for t in &tokens[first..=last] {
+        match &t.value {
+            v if is_statement_token(v) => return None,
+            v if blocks::opens_expression(v) => nesting += 1,
+            Token::End => nesting -= 1,
+            Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+            Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+            Token::Comma | Token::EqArrow | Token::Of | Token::Case
+                if depth == 0 && nesting == 0 =>
+            {
+                return None
+            }
+            _ => (),
+        }
+        if depth < 0 || nesting < 0 {
+            return None;
+        }
+    }
+    if depth != 0 || nesting != 0 {
+        return None;
+    }
+
+    let statement = statement_start(&tokens, first);
+    let bound = bound_names(&tokens, statement, first);
+    let mut params: Vec<String> = Vec::new();
+    for k in first..=last {
+        let name = match &tokens[k].value {
+            Token::Ident(name, _) => name.to_string(),
+            _ => continue,
+        };
+        // field names, module paths and function names are not variables
+        let previous = k.checked_sub(1).map(|j| &tokens[j].value);
+        let next = tokens.get(k + 1).map(|t| &t.value);
+        if matches!(previous, Some(Token::Dot) | Some(Token::ColonColon))
+            || matches!(next, Some(Token::ColonColon) | Some(Token::LParen))
+        {
+            continue;
+        }
+        if bound.contains(&name) && !params.contains(&name) {
+            params.push(name);
+        }
+    }
+
+    // a name that the document does not define yet
+    let name = std::iter::once("extracted".to_string())
+        .chain((2..).map(|n| format!("extracted_{}", n)))
+        .find(|name| !defined.contains(name))?;
+
+    let statement_range = ranges[statement];
+    let line = text
+        .lines()
+        .nth(statement_range.start.line as usize)
+        .unwrap_or_default();
+    let indentation: String = line.chars().take_while(|c| c.is_whitespace()).collect();
+    let body = lsp_utils::text_in_range(text, Range::new(ranges[first].start, ranges[last].end));
+    let function = format!(
+        "{indent}fn {}({}) with\n{indent}  {}\n{indent}end;\n\n",
+        name,
+        params.join(", "),
+        body,
+        indent = indentation
+    );
+    let insert_at = Position::new(statement_range.start.line, 0);
+
+    Some(vec![
+        TextEdit::new(Range::new(insert_at, insert_at), function),
+        TextEdit::new(
+            Range::new(ranges[first].start, ranges[last].end),
+            format!("{}({})", name, params.join(", ")),
+        ),
+    ])
+}
+
+// index of the first token of the statement (at the top level, or within a module) that the
+// token at the index is in
+fn statement_start(tokens: &[&TokenSpan], index: usize) -> usize {
+    let mut stack: Vec<&Token> = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, t) in tokens.iter().enumerate().take(index) {
+        let in_patch = stack.last() == Some(&&Token::Patch);
+        match &t.value {
+            Token::Merge if in_patch => (),
+            v if blocks::opens_expression(v) => stack.push(v),
+            Token::Fn | Token::Module | Token::Define if blocks::has_body(tokens, i) => {
+                stack.push(&t.value)
+            }
+            Token::End => {
+                stack.pop();
+            }
+            Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+            Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+            Token::Semi if depth == 0 && stack.iter().all(|t| **t == Token::Module) => {
+                start = i + 1
+            }
+            // the body of a module starts a statement too
+            Token::With if stack.last() == Some(&&Token::Module) && depth == 0 => start = i + 1,
+            _ => (),
+        }
+    }
+    start
+}
+
+// names bound from the start of the statement up to the index, by lets, function parameters
+// and case patterns (`case x = ...`, `case (k, v)`), along with the lets at the top level
+fn bound_names(tokens: &[&TokenSpan], statement: usize, index: usize) -> Vec<String> {
+    let value = |k: Option<usize>| k.and_then(|k| tokens.get(k)).map(|t| &t.value);
+    let mut names = Vec::new();
+    let mut in_params = false;
+    for k in statement..index {
+        let bound = match &tokens[k].value {
+            Token::LParen => {
+                in_params = matches!(value(k.checked_sub(2)), Some(Token::Fn))
+                    || matches!(value(k.checked_sub(1)), Some(Token::Case));
+                false
+            }
+            Token::RParen => {
+                in_params = false;
+                false
+            }
+            Token::Ident(_, _) => {
+                in_params
+                    || matches!(value(k.checked_sub(1)), Some(Token::Let))
+                    || (matches!(value(k.checked_sub(1)), Some(Token::Case))
+                        && matches!(value(Some(k + 1)), Some(Token::Equal)))
+            }
+            _ => false,
+        };
+        if let (true, Token::Ident(name, _)) = (bound, &tokens[k].value) {
+            names.push(name.to_string());
+        }
+    }
+    for k in 1..statement {
+        if let (Token::Ident(name, _), Token::Let) = (&tokens[k].value, &tokens[k - 1].value) {
+            // lets that make up statements of their own, as opposed to ones in expressions
+            if statement_start(tokens, k) == k - 1 {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names
+}
+
+// top level statements moved out of a document into a module of their own
+pub struct Moved {
+    // names of what the statements define, with the kind of definition
+    pub names: Vec<(String, SymbolKind)>,
+    // of the statements, with the rest of their last lines
+    pub ranges: Vec<Range>,
+    // text of the module file
+    pub content: String,
+}
+
+// the `define script` statements (or `fn` and `const` ones) that the selection touches, or
+// the one with the cursor. None if it touches any other top level statement.
+pub fn moved_statements(text: &str, tokens: &[TokenSpan], selection: Range) -> Option<Moved> {
+    let tokens = blocks::significant(tokens);
+    let touched: Vec<(usize, usize)> = top_level_statements(&tokens)
+        .into_iter()
+        .filter(|(start, end)| {
+            let range = Range::new(
+                lsp_utils::token_range(text, tokens[*start]).start,
+                lsp_utils::token_range(text, tokens[*end]).end,
+            );
+            range.start <= selection.end && selection.start <= range.end
+        })
+        .collect();
+    if touched.is_empty() {
+        return None;
+    }
+
+    let mut moved = Moved {
+        names: Vec::new(),
+        ranges: Vec::new(),
+        content: String::new(),
+    };
+    for (start, end) in touched {
+        let (name, kind) = match (
+            &tokens[start].value,
+            tokens.get(start + 1).map(|t| &t.value),
+            tokens.get(start + 2).map(|t| &t.value),
+        ) {
+            (Token::Define, Some(Token::Script), Some(Token::Ident(name, _))) => {
+                (name.to_string(), SymbolKind::Class)
+            }
+            (Token::Fn, Some(Token::Ident(name, _)), _) => (name.to_string(), SymbolKind::Function),
+            (Token::Const, Some(Token::Ident(name, _)), _) => {
+                (name.to_string(), SymbolKind::Constant)
+            }
+            _ => return None,
+        };
+        let start = lsp_utils::token_range(text, tokens[start]).start;
+        let end = lsp_utils::token_range(text, tokens[end]).end;
+        moved.names.push((name, kind));
+        moved
+            .content
+            .push_str(&lsp_utils::text_in_range(text, Range::new(start, end)));
+        moved.content.push_str("\n\n");
+        // from the start of the line, unless something else comes before on it
+        let before =
+            lsp_utils::text_in_range(text, Range::new(Position::new(start.line, 0), start));
+        let from = if before.trim().is_empty() {
+            Position::new(start.line, 0)
+        } else {
+            start
+        };
+        moved
+            .ranges
+            .push(Range::new(from, Position::new(end.line + 1, 0)));
+    }
+    moved.content = format!("{}\n", moved.content.trim_end());
+    Some(moved)
+}
+
+// edits to the document for the statements moving into the module at the path: removing
+// them, using the module and referring to what it defines through it
+pub fn module_edits(
+    text: &str,
+    tokens: &[TokenSpan],
+    moved: &Moved,
+    module_path: &[String],
+) -> Vec<TextEdit> {
+    let import = imports::Import {
+        module_path: module_path.to_vec(),
+        alias: None,
+        range: Range::default(),
+    };
+    let alias = import.name().to_string();
+    // below the last use statement, if any
+    let use_line = imports::parse(tokens)
+        .iter()
+        .map(|import| import.range.end.line + 1)
+        .max()
+        .unwrap_or(0);
+
+    let mut edits: Vec<TextEdit> = moved
+        .ranges
+        .iter()
+        .map(|range| TextEdit::new(*range, String::new()))
+        .collect();
+    edits.push(TextEdit::new(
+        Range::new(Position::new(use_line, 0), Position::new(use_line, 0)),
+        format!("{}\n", import.to_statement()),
+    ));
+
+    let tokens = blocks::significant(tokens);
+    let in_moved = |range: Range| {
+        moved
+            .ranges
+            .iter()
+            .any(|r| r.start <= range.start && range.end <= r.end)
+    };
+    let kind_of = |name: &str| {
+        moved
+            .names
+            .iter()
+            .find(|(moved_name, _)| moved_name == name)
+            .map(|(_, kind)| *kind)
+    };
+    let value = |k: Option<usize>| k.and_then(|k| tokens.get(k)).map(|t| &t.value);
+    for (k, t) in tokens.iter().enumerate() {
+        let name = match &t.value {
+            Token::Ident(name, _) => name.to_string(),
+            _ => continue,
+        };
+        let range = lsp_utils::token_range(text, t);
+        if in_moved(range) {
+            continue;
+        }
+        let previous = value(k.checked_sub(1));
+        let next = value(Some(k + 1));
+        if matches!(previous, Some(Token::Dot) | Some(Token::ColonColon))
+            || matches!(next, Some(Token::ColonColon))
+        {
+            continue;
+        }
+        let qualified = format!("{}::{}", alias, name);
+        match kind_of(&name) {
+            // `create script name from <name>`
+            Some(SymbolKind::Class) if previous == Some(&Token::From) => {
+                edits.push(TextEdit::new(range, qualified));
+            }
+            // `create script <name>`, which is named after the definition
+            Some(SymbolKind::Class)
+                if previous == Some(&Token::Script)
+                    && value(k.checked_sub(2)) == Some(&Token::Create)
+                    && next != Some(&Token::From) =>
+            {
+                edits.push(TextEdit::new(
+                    Range::new(range.end, range.end),
+                    format!(" from {}", qualified),
+                ));
+            }
+            Some(SymbolKind::Function) if next == Some(&Token::LParen) => {
+                edits.push(TextEdit::new(range, qualified));
+            }
+            Some(SymbolKind::Constant)
+                if !matches!(previous, Some(Token::Let) | Some(Token::Const))
+                    && next != Some(&Token::LParen) =>
+            {
+                edits.push(TextEdit::new(range, qualified));
+            }
+            _ => (),
+        }
+    }
+    edits
+}
+
+// (first, last) token indices of the statements at the top level, ending with their `;`
+fn top_level_statements(tokens: &[&TokenSpan]) -> Vec<(usize, usize)> {
+    let mut statements = Vec::new();
+    let mut stack: Vec<&Token> = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, t) in tokens.iter().enumerate() {
+        let in_patch = stack.last() == Some(&&Token::Patch);
+        match &t.value {
+            Token::Merge if in_patch => (),
+            v if blocks::opens_expression(v) => stack.push(v),
+            Token::Fn | Token::Module | Token::Define if blocks::has_body(tokens, i) => {
+                stack.push(&t.value)
+            }
+            Token::End => {
+                stack.pop();
+            }
+            Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+            Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+            Token::Semi if depth == 0 && stack.is_empty() => {
+                statements.push((start, i));
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    statements
+}
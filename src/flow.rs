@@ -0,0 +1,154 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// statement level structure of scripts, for following where `emit` and `drop` happen.
+// statements are ranges of (significant) token indices, with the end being exclusive.
+
+use crate::blocks;
+use crate::language::{Token, TokenSpan};
+
+pub type Statement = (usize, usize);
+
+// an arm of a match expression
+pub struct Arm {
+    // index of the `case` or `default`
+    pub keyword: usize,
+    pub body: Vec<Statement>,
+}
+
+pub struct Flow<'a, 'input> {
+    pub tokens: &'a [&'a TokenSpan<'input>],
+    // nesting level of each token, where the keywords opening and closing a block are at
+    // the level of the block itself, and the tokens within it one deeper
+    levels: Vec<i32>,
+}
+
+impl<'a, 'input> Flow<'a, 'input> {
+    pub fn new(tokens: &'a [&'a TokenSpan<'input>]) -> Self {
+        let mut levels = Vec::with_capacity(tokens.len());
+        // opening tokens, to tell the `merge` operations of patches from merge expressions
+        let mut stack: Vec<&Token> = Vec::new();
+        for t in tokens {
+            let in_patch = stack.last() == Some(&&Token::Patch);
+            match &t.value {
+                Token::Merge if in_patch => levels.push(stack.len() as i32),
+                v if blocks::opens_expression(v)
+                    || matches!(
+                        v,
+                        Token::Fn | Token::Module | Token::LParen | Token::LBracket | Token::LBrace
+                    ) =>
+                {
+                    levels.push(stack.len() as i32);
+                    stack.push(v);
+                }
+                Token::End | Token::RParen | Token::RBracket | Token::RBrace => {
+                    stack.pop();
+                    levels.push(stack.len() as i32);
+                }
+                _ => levels.push(stack.len() as i32),
+            }
+        }
+        Self { tokens, levels }
+    }
+
+    // statements from the start index up to the first token out of the start's level (or
+    // the end of the document), stopping at arm keywords of the level as well
+    fn statements_from(&self, start: usize) -> Vec<Statement> {
+        let level = match self.levels.get(start) {
+            Some(level) => *level,
+            None => return vec![],
+        };
+        let mut statements = Vec::new();
+        let mut statement_start = start;
+        let mut i = start;
+        while i < self.tokens.len() {
+            if self.levels[i] < level
+                || (self.levels[i] == level
+                    && matches!(
+                        self.tokens[i].value,
+                        Token::Case | Token::Default | Token::End
+                    ))
+            {
+                break;
+            }
+            if self.levels[i] == level && self.tokens[i].value == Token::Semi {
+                statements.push((statement_start, i));
+                statement_start = i + 1;
+            }
+            i += 1;
+        }
+        if statement_start < i {
+            statements.push((statement_start, i));
+        }
+        statements
+    }
+
+    pub fn top_level(&self) -> Vec<Statement> {
+        self.statements_from(0)
+    }
+
+    // arms of the match expression at the index
+    pub fn arms(&self, match_index: usize) -> Vec<Arm> {
+        let level = self.levels[match_index];
+        let mut arms = Vec::new();
+        for i in match_index + 1..self.tokens.len() {
+            if self.levels[i] == level {
+                // the match `end`
+                break;
+            }
+            if self.levels[i] != level + 1
+                || !matches!(self.tokens[i].value, Token::Case | Token::Default)
+            {
+                continue;
+            }
+            let arrow = (i + 1..self.tokens.len())
+                .take_while(|j| self.levels[*j] > level)
+                .find(|j| self.levels[*j] == level + 1 && self.tokens[*j].value == Token::EqArrow);
+            arms.push(Arm {
+                keyword: i,
+                body: arrow.map_or_else(Vec::new, |arrow| self.statements_from(arrow + 1)),
+            });
+        }
+        arms
+    }
+
+    // true if the statement ends processing of the event, on every path through it
+    pub fn terminates(&self, statement: Statement) -> bool {
+        match self.tokens.get(statement.0).map(|t| &t.value) {
+            Some(Token::Emit) | Some(Token::Drop) => true,
+            Some(Token::Match) => {
+                let arms = self.arms(statement.0);
+                arms.iter()
+                    .any(|arm| self.tokens[arm.keyword].value == Token::Default)
+                    && arms.iter().all(|arm| {
+                        arm.body
+                            .last()
+                            .map_or(false, |statement| self.terminates(*statement))
+                    })
+            }
+            _ => false,
+        }
+    }
+
+    // all the statement sequences: the top level one and those of the match arms
+    pub fn sequences(&self) -> Vec<Vec<Statement>> {
+        let mut sequences = vec![self.top_level()];
+        for (i, t) in self.tokens.iter().enumerate() {
+            if t.value == Token::Match {
+                sequences.extend(self.arms(i).into_iter().map(|arm| arm.body));
+            }
+        }
+        sequences
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// function docs loaded at runtime from a directory of tremor docs (the `docsPath` setting),
+// eg: a checkout of tremor-www-docs. they take precedence over the ones built into the
+// server, so newer docs can be used without a new server release.
+//
+// the markdown files in it (at any depth) document a function per `### ` section, with the
+// signature as its heading, eg: `### string::format(format, ...) -> string`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+use tremor_script::docs::{FunctionDoc, FunctionSignatureDoc};
+
+struct Loaded {
+    path: PathBuf,
+    // shared with whoever still uses them after a reload
+    docs: HashMap<String, Arc<FunctionDoc>>,
+}
+
+static LOADED: RwLock<Option<Loaded>> = RwLock::new(None);
+
+fn loaded() -> RwLockReadGuard<'static, Option<Loaded>> {
+    match LOADED.read() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+// the loaded doc of the (fully qualified) function, if there is one
+pub fn get(full_function_name: &str) -> Option<Arc<FunctionDoc>> {
+    loaded().as_ref()?.docs.get(full_function_name).cloned()
+}
+
+// directory the docs were loaded from, if any
+pub fn path() -> Option<PathBuf> {
+    loaded().as_ref().map(|loaded| loaded.path.clone())
+}
+
+// (re)loads the docs from the directory, returning the number of functions documented in it.
+// None (or a directory that isn't there) goes back to the built-in docs.
+pub fn load(path: Option<&Path>) -> Result<usize, String> {
+    let (loaded, result) = match path {
+        Some(path) if path.is_dir() => {
+            let mut docs = HashMap::new();
+            read_dir(path, &mut docs);
+            let count = docs.len();
+            let loaded = Loaded {
+                path: path.to_path_buf(),
+                docs,
+            };
+            (Some(loaded), Ok(count))
+        }
+        Some(path) => (None, Err(format!("{} is not a directory", path.display()))),
+        None => (None, Ok(0)),
+    };
+    match LOADED.write() {
+        Ok(mut guard) => *guard = loaded,
+        Err(poisoned) => *poisoned.into_inner() = loaded,
+    }
+    result
+}
+
+fn read_dir(dir: &Path, docs: &mut HashMap<String, Arc<FunctionDoc>>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let (path, file_type) = match entry.file_type() {
+            Ok(file_type) => (entry.path(), file_type),
+            Err(_) => continue,
+        };
+        if file_type.is_dir() {
+            read_dir(&path, docs);
+        } else if file_type.is_symlink() && path.is_dir() {
+            // not followed, as links back up the tree would make the walk endless
+            continue;
+        } else if path.extension().map_or(false, |e| e == "md") {
+            if let Ok(text) = fs::read_to_string(&path) {
+                for doc in text.split("\n### ").skip(1).filter_map(parse_section) {
+                    docs.insert(doc.signature.full_name.clone(), Arc::new(doc));
+                }
+            }
+        }
+    }
+}
+
+// the doc of a section, if its heading is a function signature (and not some other heading)
+fn parse_section(section: &str) -> Option<FunctionDoc> {
+    let mut parts = section.splitn(2, '\n');
+    let heading = parts.next()?.trim();
+    let description = parts.next().unwrap_or_default().trim();
+
+    let open = heading.find('(')?;
+    let close = heading.rfind(')')?;
+    let full_name = heading[..open].trim();
+    if close < open || !full_name.contains("::") || full_name.contains(char::is_whitespace) {
+        return None;
+    }
+    let result = heading[close + 1..].trim().strip_prefix("->")?.trim();
+    let args = heading[open + 1..close]
+        .split(',')
+        .map(str::trim)
+        .filter(|arg| !arg.is_empty())
+        .map(String::from)
+        .collect();
+
+    Some(FunctionDoc {
+        signature: FunctionSignatureDoc {
+            full_name: full_name.to_string(),
+            args,
+            result: result.to_string(),
+        },
+        description: description.to_string(),
+        summary: None,
+        examples: None,
+    })
+}
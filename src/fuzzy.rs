@@ -0,0 +1,72 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// scores how well the pattern matches the candidate, as a case-insensitive subsequence.
+// higher is better and None means no match at all. empty patterns match everything.
+pub fn score(pattern: &str, candidate: &str) -> Option<i64> {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+
+    let mut score = 0;
+    let mut pattern_index = 0;
+    let mut last_match: Option<usize> = None;
+
+    for (i, c) in candidate.iter().enumerate() {
+        match pattern.get(pattern_index) {
+            Some(p) if p == c => {
+                score += match last_match {
+                    // consecutive matches are what users type most of the time
+                    Some(last) if last + 1 == i => 5,
+                    _ => 1,
+                };
+                // matches at word boundaries (eg: `lc` for `lower_case`)
+                if i == 0 || candidate[i - 1] == '_' || candidate[i - 1] == ':' {
+                    score += 3;
+                }
+                // penalize the gap since the last match
+                score -= (i - last_match.map_or(0, |last| last + 1)) as i64;
+                last_match = Some(i);
+                pattern_index += 1;
+            }
+            Some(_) => (),
+            None => break,
+        }
+    }
+
+    if pattern_index < pattern.len() {
+        return None;
+    }
+
+    if candidate.starts_with(&pattern) {
+        score += 10;
+    }
+
+    Some(score)
+}
+
+// number of single char insertions, deletions or substitutions to turn one string into the
+// other, for typo suggestions
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
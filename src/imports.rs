@@ -0,0 +1,186 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::language::{Token, TokenSpan};
+use crate::lsp_utils;
+use tower_lsp::lsp_types::*;
+
+// a single `use` statement from a document, eg: `use std::string as s;`
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub module_path: Vec<String>,
+    pub alias: Option<String>,
+    // covers the whole statement, from `use` to the terminating `;`
+    pub range: Range,
+}
+
+impl Import {
+    // name by which the module is referred to in the rest of the document
+    pub fn name(&self) -> &str {
+        match &self.alias {
+            Some(alias) => alias,
+            None => self.module_path.last().map_or("", |s| s.as_str()),
+        }
+    }
+
+    pub fn module_name(&self) -> String {
+        self.module_path.join("::")
+    }
+
+    pub fn to_statement(&self) -> String {
+        match &self.alias {
+            Some(alias) => format!("use {} as {};", self.module_name(), alias),
+            None => format!("use {};", self.module_name()),
+        }
+    }
+}
+
+// the builtin module that the module path (eg: `s` or `s::sub`) stands for, through the name
+// of a use statement (eg: `string` for `s`, with `use std::string as s;`). the path as it is
+// where it does not stand for one.
+pub fn unalias(imports: &[Import], module_path: &str, builtin_modules: &[String]) -> String {
+    let parts: Vec<&str> = module_path.split("::").collect();
+    let import = imports
+        .iter()
+        .find(|import| Some(&import.name()) == parts.first());
+    if let Some(import) = import {
+        let full: Vec<String> = import
+            .module_path
+            .iter()
+            .cloned()
+            .chain(parts.iter().skip(1).map(|part| part.to_string()))
+            .collect();
+        // builtin modules can be used with or without the `std` prefix
+        let without_std = match full.split_first() {
+            Some((first, rest)) if first == "std" => Some(rest.join("::")),
+            _ => None,
+        };
+        if let Some(name) = std::iter::once(full.join("::"))
+            .chain(without_std)
+            .find(|name| builtin_modules.contains(name))
+        {
+            return name;
+        }
+    }
+    module_path.to_string()
+}
+
+pub fn parse(tokens: &[TokenSpan]) -> Vec<Import> {
+    let tokens: Vec<&TokenSpan> = tokens.iter().filter(|t| !t.value.is_ignorable()).collect();
+
+    let mut imports = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if tokens[i].value != Token::Use {
+            i += 1;
+            continue;
+        }
+
+        let start = &tokens[i].span.start;
+        let mut module_path = Vec::new();
+        let mut alias = None;
+        let mut j = i + 1;
+        while let Some(t) = tokens.get(j) {
+            match &t.value {
+                Token::Ident(name, _) => {
+                    if alias.is_none() && tokens[j - 1].value == Token::As {
+                        alias = Some(name.to_string());
+                    } else {
+                        module_path.push(name.to_string());
+                    }
+                }
+                Token::ColonColon | Token::As => (),
+                _ => break,
+            }
+            j += 1;
+        }
+
+        // only complete statements are of interest here (partial ones are still being typed)
+        if let Some(t) = tokens.get(j) {
+            if t.value == Token::Semi && !module_path.is_empty() {
+                imports.push(Import {
+                    module_path,
+                    alias,
+                    range: Range {
+                        start: lsp_utils::to_lsp_position(start),
+                        end: lsp_utils::to_lsp_position(&t.span.end),
+                    },
+                });
+            }
+        }
+        i = j;
+    }
+
+    imports
+}
+
+// true if the imported module is referred to (as `name::...`) outside of the imports
+pub fn is_referenced(tokens: &[TokenSpan], import: &Import) -> bool {
+    let tokens: Vec<&TokenSpan> = tokens.iter().filter(|t| !t.value.is_ignorable()).collect();
+
+    tokens
+        .windows(2)
+        .any(|pair| match (&pair[0].value, &pair[1].value) {
+            (Token::Ident(name, _), Token::ColonColon) => {
+                name == import.name()
+                    && lsp_utils::to_lsp_position(&pair[0].span.start).line > import.range.end.line
+            }
+            _ => false,
+        })
+}
+
+// sorts the imports, removes duplicates and drops the ones not used in the document.
+// returns no edits if the imports are already organized.
+pub fn organize(tokens: &[TokenSpan]) -> Vec<TextEdit> {
+    let imports = parse(tokens);
+    let first = match imports.first() {
+        Some(import) => import.range.start,
+        None => return vec![],
+    };
+
+    let mut statements: Vec<String> = imports
+        .iter()
+        .filter(|import| is_referenced(tokens, import))
+        .map(Import::to_statement)
+        .collect();
+    statements.sort();
+    statements.dedup();
+
+    let existing: Vec<String> = imports.iter().map(Import::to_statement).collect();
+    if existing == statements {
+        return vec![];
+    }
+
+    // each import is removed along with its line, and the organized block goes in place of
+    // the first one. imports are expected to be on lines of their own.
+    let mut edits = Vec::new();
+    if !statements.is_empty() {
+        edits.push(TextEdit {
+            range: Range {
+                start: Position::new(first.line, 0),
+                end: Position::new(first.line, 0),
+            },
+            new_text: format!("{}\n", statements.join("\n")),
+        });
+    }
+    edits.extend(imports.iter().map(|import| TextEdit {
+        range: Range {
+            start: Position::new(import.range.start.line, 0),
+            end: Position::new(import.range.end.line + 1, 0),
+        },
+        new_text: String::new(),
+    }));
+
+    edits
+}
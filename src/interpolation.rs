@@ -0,0 +1,330 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// rewriting string concatenations (`"a" + x + "b"`) into interpolated strings (`"a#{x}b"`)
+
+use crate::balance;
+use crate::blocks;
+use crate::language::{Token, TokenSpan};
+use crate::lsp_utils;
+use tower_lsp::lsp_types::*;
+
+// keywords that are values of their own, and so can be operands
+const VALUE_KEYWORDS: &[&str] = &[
+    "event", "state", "args", "group", "window", "true", "false", "null",
+];
+
+// a token, or a whole string (with any interpolations in it)
+enum Unit<'a> {
+    Token(&'a TokenSpan),
+    Str { range: Range, heredoc: bool },
+}
+
+impl Unit<'_> {
+    fn range(&self, text: &str) -> Range {
+        match self {
+            Unit::Token(t) => lsp_utils::token_range(text, t),
+            Unit::Str { range, .. } => *range,
+        }
+    }
+}
+
+enum Part {
+    // as written between the quotes, escapes included
+    Literal(String),
+    Expression(String),
+}
+
+// the edit turning the concatenation around the position into an interpolated string, if
+// it's one of strings (ie. one of its first two operands is a string literal)
+pub fn from_concatenation(
+    text: &str,
+    tokens: &[TokenSpan],
+    position: Position,
+) -> Option<TextEdit> {
+    let units = units(text, tokens);
+    let mut at = units.iter().position(|u| {
+        let range = u.range(text);
+        range.start <= position && position <= range.end
+    })?;
+    // from within the parentheses of an operand (eg: a call), up to the concatenation
+    for _ in 0..units.len() {
+        let (start, end) = chain(text, &units, at);
+        // `a - "b" + c` subtracts before concatenating
+        let after_minus =
+            start > 0 && matches!(&units[start - 1], Unit::Token(t) if t.value == Token::Sub);
+        if !after_minus {
+            if let Some(edit) = rewrite(text, &units[start..end]) {
+                return Some(edit);
+            }
+        }
+        match start.checked_sub(1).map(|i| &units[i]) {
+            Some(Unit::Token(t))
+                if matches!(t.value, Token::LParen | Token::LBracket | Token::LBrace) =>
+            {
+                at = start - 1
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+fn units<'a>(text: &str, tokens: &'a [TokenSpan]) -> Vec<Unit<'a>> {
+    let chars = balance::positioned_chars(text);
+    let mut units = Vec::new();
+    let mut string_end: Option<Position> = None;
+    for t in blocks::significant(tokens) {
+        let range = lsp_utils::token_range(text, t);
+        if string_end.map_or(false, |end| range.start < end) {
+            continue;
+        }
+        string_end = None;
+        if t.value != Token::DQuote && !lsp_utils::text_in_range(text, range).starts_with('"') {
+            units.push(Unit::Token(t));
+            continue;
+        }
+        let start = match chars.iter().position(|(_, p)| *p == range.start) {
+            Some(start) => start,
+            None => continue,
+        };
+        let heredoc = chars
+            .get(start..start + 3)
+            .map_or(false, |quotes| quotes.iter().all(|(c, _)| *c == '"'));
+        let close = if heredoc {
+            (start + 3..chars.len())
+                .find(|i| {
+                    chars
+                        .get(*i..*i + 3)
+                        .map_or(false, |quotes| quotes.iter().all(|(c, _)| *c == '"'))
+                })
+                .map(|i| i + 2)
+        } else {
+            balance::string_end(&chars, start + 1)
+        };
+        // unterminated strings run to the end
+        let end = match close.and_then(|close| chars.get(close)) {
+            Some((c, p)) => Position::new(p.line, p.character + c.len_utf16() as u64),
+            None => return units,
+        };
+        units.push(Unit::Str {
+            range: Range::new(range.start, end),
+            heredoc,
+        });
+        string_end = Some(end);
+    }
+    units
+}
+
+// bounds of the operands of `+` around the unit (with whatever binds tighter in them), as
+// far as its parentheses go
+fn chain(text: &str, units: &[Unit], at: usize) -> (usize, usize) {
+    let is_operand = |u: &Unit| match u {
+        Unit::Str { .. } => true,
+        Unit::Token(t) => match &t.value {
+            Token::Ident(..)
+            | Token::IntLiteral(_)
+            | Token::FloatLiteral(..)
+            | Token::BoolLiteral(_)
+            | Token::Nil
+            | Token::Dot
+            | Token::ColonColon
+            | Token::Dollar
+            | Token::Mul
+            | Token::Div
+            | Token::Mod
+            | Token::Add => true,
+            _ => VALUE_KEYWORDS.contains(&lsp_utils::text_in_range(text, u.range(text)).as_str()),
+        },
+    };
+    let opens = |u: &Unit| matches!(u, Unit::Token(t) if matches!(t.value, Token::LParen | Token::LBracket | Token::LBrace));
+    let closes = |u: &Unit| matches!(u, Unit::Token(t) if matches!(t.value, Token::RParen | Token::RBracket | Token::RBrace));
+
+    let mut end = at;
+    let mut depth = 0;
+    while end < units.len() {
+        let unit = &units[end];
+        if opens(unit) {
+            depth += 1;
+        } else if closes(unit) {
+            if depth == 0 {
+                break;
+            }
+            depth -= 1;
+        } else if depth == 0 && !is_operand(unit) {
+            break;
+        }
+        end += 1;
+    }
+    let mut start = at;
+    let mut depth = 0;
+    while start > 0 {
+        let unit = &units[start - 1];
+        if closes(unit) {
+            depth += 1;
+        } else if opens(unit) {
+            if depth == 0 {
+                break;
+            }
+            depth -= 1;
+        } else if depth == 0 && !is_operand(unit) {
+            break;
+        }
+        start -= 1;
+    }
+    (start, end)
+}
+
+fn rewrite(text: &str, units: &[Unit]) -> Option<TextEdit> {
+    let span = |units: &[Unit]| match (units.first(), units.last()) {
+        (Some(first), Some(last)) => {
+            Some(Range::new(first.range(text).start, last.range(text).end))
+        }
+        _ => None,
+    };
+
+    // the operands, split at the `+` outside of brackets
+    let mut operands: Vec<&[Unit]> = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, unit) in units.iter().enumerate() {
+        if let Unit::Token(t) = unit {
+            match t.value {
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+                Token::Add if depth == 0 => {
+                    operands.push(&units[start..i]);
+                    start = i + 1;
+                }
+                _ => (),
+            }
+        }
+    }
+    operands.push(&units[start..]);
+    if operands.len() < 2 || operands.iter().any(|operand| operand.is_empty()) {
+        return None;
+    }
+
+    let mut parts = Vec::new();
+    for operand in &operands {
+        let written = lsp_utils::text_in_range(text, span(operand)?);
+        parts.push(match operand {
+            [Unit::Str { heredoc: false, .. }] => {
+                Part::Literal(written[1..written.len() - 1].to_string())
+            }
+            [Unit::Str { heredoc: true, .. }] => return None,
+            _ if written.contains('\n') => return None,
+            _ => Part::Expression(written.trim().to_string()),
+        });
+    }
+    // the rest are strings too when the first addition is of strings
+    if !parts.iter().take(2).any(|p| matches!(p, Part::Literal(_))) {
+        return None;
+    }
+
+    let mut content = String::new();
+    for part in &parts {
+        match part {
+            Part::Literal(literal) => {
+                // `"#" + "{a}"` is no interpolation, but `"#{a}"` would be
+                if content.ends_with('#') && literal.starts_with('{') {
+                    return None;
+                }
+                content.push_str(literal);
+            }
+            Part::Expression(expression) => {
+                content.push_str(&format!("#{{{}}}", expression));
+            }
+        }
+    }
+    Some(TextEdit {
+        range: span(units)?,
+        new_text: format!("\"{}\"", content),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::language::{self, Tokenizer};
+
+    // the script with `x` bound to "b", and the expression on the second line
+    fn script(expression: &str) -> String {
+        format!("let x = \"b\";\n{}\n", expression)
+    }
+
+    // the expression as rewritten from the start of the second line
+    fn rewritten(expression: &str) -> Option<String> {
+        let text = script(expression);
+        let tokens = Tokenizer::new(&text).collect::<Result<Vec<_>, _>>().ok()?;
+        let edit = from_concatenation(&text, &tokens, Position::new(1, 0))?;
+        let start = lsp_utils::position_to_offset(&text, edit.range.start);
+        let end = lsp_utils::position_to_offset(&text, edit.range.end);
+        let expression = format!("{}{}{}", &text[..start], edit.new_text, &text[end..]);
+        expression.lines().nth(1).map(String::from)
+    }
+
+    fn eval(expression: &str) -> Option<Result<String, String>> {
+        let uri = Url::parse("file:///interpolation.tremor").ok()?;
+        language::lookup("tremor")?.eval(&uri, &script(expression))
+    }
+
+    // the rewrite is as expected, and evaluates to what the concatenation does
+    fn assert_rewrite(expression: &str, expected: &str) {
+        let interpolated = rewritten(expression);
+        assert_eq!(interpolated.as_deref(), Some(expected));
+        let value = eval(expression);
+        assert!(matches!(value, Some(Ok(_))), "{:?}", value);
+        assert_eq!(eval(expected), value);
+    }
+
+    #[test]
+    fn braces_stay_literal() {
+        assert_rewrite(r##""a{" + x + "}c""##, r##""a{#{x}}c""##);
+        assert_rewrite(r##""{}" + x"##, r##""{}#{x}""##);
+    }
+
+    #[test]
+    fn escapes_are_kept() {
+        assert_rewrite(r##""say \"" + x + "\"""##, r##""say \"#{x}\"""##);
+        assert_rewrite(r##""C:\\" + x + "\\d""##, r##""C:\\#{x}\\d""##);
+    }
+
+    #[test]
+    fn no_interpolation_out_of_a_hash_and_a_brace() {
+        assert_eq!(rewritten(r##""#" + "{x}""##), None);
+    }
+
+    #[test]
+    fn non_string_operands() {
+        // interpolation formats the number, where `+` would fail on it
+        assert_eq!(
+            rewritten(r##""n=" + 1 + x"##).as_deref(),
+            Some(r##""n=#{1}#{x}""##)
+        );
+        assert!(matches!(eval(r##""n=#{1}#{x}""##), Some(Ok(value)) if value == r##""n=1b""##));
+        // no string in the first two operands, so it's an addition first
+        assert_eq!(rewritten("1 + 2 + x"), None);
+    }
+
+    #[test]
+    fn nested_concatenation() {
+        assert_rewrite(r##""a" + ("-" + x)"##, r##""a#{("-" + x)}""##);
+    }
+
+    #[test]
+    fn already_interpolated_operand() {
+        assert_rewrite(r##""a#{x}" + "c" + x"##, r##""a#{x}c#{x}""##);
+    }
+}
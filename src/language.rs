@@ -21,7 +21,7 @@ pub use tremor_script::highlighter::ErrorLevel;
 pub use tremor_script::pos::Location;
 
 // common language trait
-pub use prelude::{Language, Token, TokenSpan};
+pub use prelude::{Language, RunOutput, Token, TokenSpan, Tokenizer};
 
 pub const LANGUAGE_NAMES: &[&str] = &[
     script::LANGUAGE_NAME,
@@ -33,6 +33,18 @@ pub const LANGUAGE_NAMES: &[&str] = &[
 
 pub const DEFAULT_LANGUAGE_NAME: &str = script::LANGUAGE_NAME;
 
+// language of the scripts embedded in queries
+pub const EMBEDDED_LANGUAGE_NAME: &str = script::LANGUAGE_NAME;
+
+// extension of the files of the language (by name or extension)
+pub fn file_extension(language_name: &str) -> Option<&'static str> {
+    match language_name {
+        script::LANGUAGE_NAME | script::FILE_EXTENSION => Some(script::FILE_EXTENSION),
+        query::LANGUAGE_NAME | query::FILE_EXTENSION => Some(query::FILE_EXTENSION),
+        _ => None,
+    }
+}
+
 pub fn lookup(language_name: &str) -> Option<Box<dyn Language>> {
     match language_name {
         script::LANGUAGE_NAME | script::FILE_EXTENSION => {
@@ -14,6 +14,8 @@
 
 pub use std::collections::HashMap;
 pub use std::path::Path;
+pub use std::sync::Arc;
+pub use std::time::Duration;
 pub use tower_lsp::lsp_types::Url;
 pub use tremor_script::docs::FunctionDoc;
 pub use tremor_script::highlighter::Error;
@@ -21,14 +23,99 @@ pub use tremor_script::registry;
 
 pub use tremor_script::lexer::{Token, TokenSpan, Tokenizer};
 
+// outcome of running a script against an event, with the values as json
+#[derive(Debug, Clone)]
+pub struct RunOutput {
+    // None if the event was dropped
+    pub value: Option<String>,
+    pub port: Option<String>,
+    pub state: String,
+}
+
 pub trait Language: Send + Sync {
+    fn name(&self) -> &'static str;
+
     fn parse_errors(&self, uri: &Url, text: &str) -> Option<Vec<Error>>;
 
+    // keywords that can start a top-level statement
+    fn statement_keywords(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    // names of the (builtin) modules, sorted
+    fn modules(&self, _uri: &Url) -> Vec<String> {
+        vec![]
+    }
+
     fn functions(&self, _uri: &Url, _module_name: &str) -> Vec<String> {
         vec![]
     }
 
-    fn function_doc(&self, _uri: &Url, _full_function_name: &str) -> Option<&FunctionDoc> {
+    // shared, as the docs can be reloaded while they are in use (see function_docs.rs)
+    fn function_doc(&self, _uri: &Url, _full_function_name: &str) -> Option<Arc<FunctionDoc>> {
+        None
+    }
+
+    fn is_aggregate_module(&self, _module_name: &str) -> bool {
+        false
+    }
+
+    // runs the text as a script against the event and state (both as json), returning
+    // the error message if it failed to run
+    fn run(
+        &self,
+        _uri: &Url,
+        _text: &str,
+        _event: &str,
+        _state: &str,
+    ) -> Option<Result<RunOutput, String>> {
+        None
+    }
+
+    // evaluates the text as a script (against a null event), returning the emitted value
+    // as json, or the error message if it failed to run
+    fn eval(&self, uri: &Url, text: &str) -> Option<Result<String, String>> {
+        self.run(uri, text, "null", "null").map(|result| {
+            result.and_then(|output| output.value.ok_or_else(|| "Event was dropped".to_string()))
+        })
+    }
+
+    // runs the text as a script against the event (json) the given number of times,
+    // returning how long each run took (not counting the parsing)
+    fn benchmark(
+        &self,
+        _uri: &Url,
+        _text: &str,
+        _event: &str,
+        _iterations: usize,
+    ) -> Option<Result<Vec<Duration>, String>> {
+        None
+    }
+
+    // runs the events (json) through the text as a pipeline, one after another, returning
+    // the outcome for each event. by default, the pipeline is the text as a single script,
+    // with the state carried over between events.
+    fn run_pipeline(
+        &self,
+        uri: &Url,
+        text: &str,
+        events: &[String],
+    ) -> Option<Result<Vec<Result<RunOutput, String>>, String>> {
+        let mut state = "null".to_string();
+        let mut outputs = Vec::new();
+        for event in events {
+            let output = self.run(uri, text, event, &state)?;
+            if let Ok(output) = &output {
+                state = output.state.clone();
+            }
+            outputs.push(output);
+        }
+        Some(Ok(outputs))
+    }
+
+    // the syntax tree of the text, as json (with the spans of the nodes in its node metadata),
+    // or the error message if it does not parse
+    fn ast(&self, _uri: &Url, _text: &str) -> Option<Result<serde_json::Value, String>> {
         None
     }
 
@@ -51,7 +138,10 @@ macro_rules! load_function_docs {
         ));
 
         match bincode::deserialize::<HashMap<String, FunctionDoc>>(bytes) {
-            Ok(function_docs) => function_docs,
+            Ok(function_docs) => function_docs
+                .into_iter()
+                .map(|(name, doc)| (name, Arc::new(doc)))
+                .collect(),
             Err(e) => {
                 eprintln!("Error: {}", e);
                 HashMap::new()
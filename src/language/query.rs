@@ -12,8 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::function_docs;
 use crate::language::prelude::*;
-use crate::language::script::TremorScript;
+use crate::language::script::{self, TremorScript};
+use crate::modules;
 use tremor_script::path::ModulePath;
 use tremor_script::query::Query;
 
@@ -24,7 +26,7 @@ pub const FILE_EXTENSION: &str = "trickle";
 pub struct TremorQuery {
     registry: registry::Registry,
     aggr_registry: registry::Aggr,
-    all_function_docs: HashMap<String, FunctionDoc>,
+    all_function_docs: HashMap<String, Arc<FunctionDoc>>,
     // tremor-query is built on top of tremor-script
     tremor_script: TremorScript,
 }
@@ -42,12 +44,15 @@ impl Default for TremorQuery {
 }
 
 impl Language for TremorQuery {
+    fn name(&self) -> &'static str {
+        LANGUAGE_NAME
+    }
+
     fn parse_errors(&self, uri: &Url, text: &str) -> Option<Vec<Error>> {
-        // FIXME .unwrap() should we path in something here?
         let mut m = ModulePath::load();
-        let file = uri.as_str().replace("file://", "");
-        let p = Path::new(&file);
-        m.add(p.ancestors().nth(2).unwrap().to_str().unwrap().to_string());
+        for path in modules::document_search_paths(uri) {
+            m.add(path.display().to_string());
+        }
         let cus = vec![];
         match Query::parse(&m, "<file>", text, cus, &self.registry, &self.aggr_registry) {
             Ok(query) => Some(query.warnings.iter().map(|w| w.into()).collect()),
@@ -55,6 +60,35 @@ impl Language for TremorQuery {
         }
     }
 
+    fn ast(&self, uri: &Url, text: &str) -> Option<Result<serde_json::Value, String>> {
+        let mut m = ModulePath::load();
+        for path in modules::document_search_paths(uri) {
+            m.add(path.display().to_string());
+        }
+        let cus = vec![];
+        Some(
+            match Query::parse(&m, "<file>", text, cus, &self.registry, &self.aggr_registry) {
+                Ok(query) => serde_json::to_value(query.query.suffix()).map_err(|e| e.to_string()),
+                Err(ref e) => {
+                    let e: Error = e.into();
+                    Err(e.callout().to_string())
+                }
+            },
+        )
+    }
+
+    fn statement_keywords(&self) -> &'static [&'static str] {
+        &["select", "create", "define", "use", "const"]
+    }
+
+    fn modules(&self, uri: &Url) -> Vec<String> {
+        let mut names = script::module_names(self.all_function_docs.keys());
+        names.extend(self.tremor_script.modules(uri));
+        names.sort();
+        names.dedup();
+        names
+    }
+
     fn functions(&self, uri: &Url, module_name: &str) -> Vec<String> {
         if let Some(module) = self.aggr_registry.find_module(module_name) {
             let mut vec: Vec<String> = module.keys().cloned().collect();
@@ -66,9 +100,126 @@ impl Language for TremorQuery {
         }
     }
 
-    fn function_doc(&self, uri: &Url, full_function_name: &str) -> Option<&FunctionDoc> {
-        self.all_function_docs
-            .get(full_function_name)
+    fn is_aggregate_module(&self, module_name: &str) -> bool {
+        self.aggr_registry.find_module(module_name).is_some()
+    }
+
+    fn function_doc(&self, uri: &Url, full_function_name: &str) -> Option<Arc<FunctionDoc>> {
+        function_docs::get(full_function_name)
+            .or_else(|| self.all_function_docs.get(full_function_name).cloned())
             .or_else(|| self.tremor_script.function_doc(uri, full_function_name))
     }
+
+    fn run(
+        &self,
+        uri: &Url,
+        text: &str,
+        event: &str,
+        state: &str,
+    ) -> Option<Result<RunOutput, String>> {
+        // scripts embedded in queries are plain tremor-script
+        self.tremor_script.run(uri, text, event, state)
+    }
+
+    // the events go into the `in` stream of the query, compiled to a pipeline as `tremor run`
+    // does. an event with several outputs (eg. on more than one port) gets them as an array.
+    #[cfg(feature = "runtime")]
+    fn run_pipeline(
+        &self,
+        uri: &Url,
+        text: &str,
+        events: &[String],
+    ) -> Option<Result<Vec<Result<RunOutput, String>>, String>> {
+        let mut m = ModulePath::load();
+        for path in modules::document_search_paths(uri) {
+            m.add(path.display().to_string());
+        }
+        let query = match Query::parse(
+            &m,
+            "<file>",
+            text,
+            vec![],
+            &self.registry,
+            &self.aggr_registry,
+        ) {
+            Ok(query) => query,
+            Err(ref e) => {
+                let e: Error = e.into();
+                return Some(Err(e.callout().to_string()));
+            }
+        };
+        let mut uid = 0;
+        let mut pipeline = match tremor_pipeline::query::Query(query).to_pipe(&mut uid) {
+            Ok(pipeline) => pipeline,
+            Err(e) => return Some(Err(e.to_string())),
+        };
+
+        let mut outputs = Vec::with_capacity(events.len());
+        for event in events {
+            let mut event_bytes = event.as_bytes().to_vec();
+            let value = match simd_json::to_borrowed_value(&mut event_bytes) {
+                Ok(value) => value.into_static(),
+                Err(e) => {
+                    outputs.push(Err(format!("Invalid event: {}", e)));
+                    continue;
+                }
+            };
+            let event = tremor_pipeline::Event {
+                data: tremor_script::LineValue::new(vec![], |_| {
+                    tremor_script::ValueAndMeta::from(value)
+                }),
+                ingest_ns: now_ns(),
+                ..tremor_pipeline::Event::default()
+            };
+            let mut returns = Vec::new();
+            outputs.push(match pipeline.enqueue("in", event, &mut returns) {
+                Ok(()) => Ok(run_output(&returns)),
+                Err(e) => Err(e.to_string()),
+            });
+        }
+        Some(Ok(outputs))
+    }
+
+    // without the runtime feature, there's no tremor-pipeline for the operators and windows
+    #[cfg(not(feature = "runtime"))]
+    fn run_pipeline(
+        &self,
+        _uri: &Url,
+        _text: &str,
+        _events: &[String],
+    ) -> Option<Result<Vec<Result<RunOutput, String>>, String>> {
+        None
+    }
+}
+
+// what the pipeline sent out for an event, on whichever ports
+#[cfg(feature = "runtime")]
+fn run_output(returns: &[(std::borrow::Cow<'static, str>, tremor_pipeline::Event)]) -> RunOutput {
+    use simd_json::prelude::*;
+
+    let mut values: Vec<String> = returns
+        .iter()
+        .map(|(_, event)| event.data.suffix().value().encode())
+        .collect();
+    let mut ports: Vec<&str> = returns.iter().map(|(port, _)| port.as_ref()).collect();
+    ports.dedup();
+    let value = match values.len() {
+        0 => None,
+        1 => values.pop(),
+        _ => Some(format!("[{}]", values.join(","))),
+    };
+    RunOutput {
+        value,
+        port: Some(ports.join(",")).filter(|ports| !ports.is_empty()),
+        // queries keep their state within the pipeline
+        state: "null".to_string(),
+    }
+}
+
+// event timestamps, which time based windows go by
+#[cfg(feature = "runtime")]
+fn now_ns() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos() as u64)
 }
@@ -12,9 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::function_docs;
 use crate::language::prelude::*;
+use crate::modules;
+use simd_json::prelude::*;
+use std::time::Instant;
 use tremor_script::path::ModulePath;
-use tremor_script::Script;
+use tremor_script::{AggrType, EventContext, Return, Script, Value};
 
 pub const LANGUAGE_NAME: &str = "tremor-script";
 pub const FILE_EXTENSION: &str = "tremor";
@@ -22,7 +26,7 @@ pub const FILE_EXTENSION: &str = "tremor";
 #[derive(Debug)]
 pub struct TremorScript {
     registry: registry::Registry,
-    all_function_docs: HashMap<String, FunctionDoc>,
+    all_function_docs: HashMap<String, Arc<FunctionDoc>>,
 }
 
 impl Default for TremorScript {
@@ -34,13 +38,41 @@ impl Default for TremorScript {
     }
 }
 
+impl TremorScript {
+    fn parse(&self, uri: &Url, text: &str) -> Result<Script, String> {
+        let mut m = ModulePath::load();
+        for path in modules::document_search_paths(uri) {
+            m.add(path.display().to_string());
+        }
+
+        Script::parse(&m, "<eval>", text.to_string(), &self.registry).map_err(|ref e| {
+            let e: Error = e.into();
+            e.callout().to_string()
+        })
+    }
+}
+
+// modules that the (fully qualified) function names are from, sorted and deduplicated
+pub fn module_names<'a>(function_names: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let mut names: Vec<String> = function_names
+        .filter_map(|name| name.rsplitn(2, "::").nth(1))
+        .map(String::from)
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
 impl Language for TremorScript {
+    fn name(&self) -> &'static str {
+        LANGUAGE_NAME
+    }
+
     fn parse_errors(&self, uri: &Url, text: &str) -> Option<Vec<Error>> {
-        // FIXME .unwrap() should we path in something here?
         let mut m = ModulePath::load();
-        let file = uri.as_str().replace("file://", "");
-        let p = Path::new(&file);
-        m.add(p.ancestors().nth(2).unwrap().to_str().unwrap().to_string());
+        for path in modules::document_search_paths(uri) {
+            m.add(path.display().to_string());
+        }
         let text = text.to_string();
         match Script::parse(&m, "<file>", text, &self.registry) {
             Ok(script) => Some(script.warnings().iter().map(|w| w.into()).collect()),
@@ -48,6 +80,22 @@ impl Language for TremorScript {
         }
     }
 
+    fn ast(&self, uri: &Url, text: &str) -> Option<Result<serde_json::Value, String>> {
+        Some(self.parse(uri, text).and_then(|script| {
+            serde_json::to_value(script.script.suffix()).map_err(|e| e.to_string())
+        }))
+    }
+
+    fn statement_keywords(&self) -> &'static [&'static str] {
+        &[
+            "use", "const", "fn", "let", "match", "for", "patch", "merge", "emit", "drop",
+        ]
+    }
+
+    fn modules(&self, _uri: &Url) -> Vec<String> {
+        module_names(self.all_function_docs.keys())
+    }
+
     fn functions(&self, _uri: &Url, module_name: &str) -> Vec<String> {
         if let Some(module) = self.registry.find_module(module_name) {
             let mut vec: Vec<String> = module.keys().cloned().collect();
@@ -58,7 +106,92 @@ impl Language for TremorScript {
         }
     }
 
-    fn function_doc(&self, _uri: &Url, full_function_name: &str) -> Option<&FunctionDoc> {
-        self.all_function_docs.get(full_function_name)
+    fn function_doc(&self, _uri: &Url, full_function_name: &str) -> Option<Arc<FunctionDoc>> {
+        function_docs::get(full_function_name)
+            .or_else(|| self.all_function_docs.get(full_function_name).cloned())
+    }
+
+    fn run(
+        &self,
+        uri: &Url,
+        text: &str,
+        event: &str,
+        state: &str,
+    ) -> Option<Result<RunOutput, String>> {
+        let script = match self.parse(uri, text) {
+            Ok(script) => script,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let mut event_bytes = event.as_bytes().to_vec();
+        let mut event = match simd_json::to_borrowed_value(&mut event_bytes) {
+            Ok(event) => event,
+            Err(e) => return Some(Err(format!("Invalid event: {}", e))),
+        };
+        let mut state_bytes = state.as_bytes().to_vec();
+        let mut state = match simd_json::to_borrowed_value(&mut state_bytes) {
+            Ok(state) => state.into_static(),
+            Err(e) => return Some(Err(format!("Invalid state: {}", e))),
+        };
+        let mut meta = Value::object();
+
+        let result = match script.run(
+            &EventContext::new(0, None),
+            AggrType::Emit,
+            &mut event,
+            &mut state,
+            &mut meta,
+        ) {
+            Ok(Return::Emit { value, port }) => Ok((Some(value.encode()), port)),
+            Ok(Return::EmitEvent { port }) => Ok((Some(event.encode()), port)),
+            Ok(Return::Drop) => Ok((None, None)),
+            Err(e) => Err(e.to_string()),
+        };
+
+        Some(result.map(|(value, port)| RunOutput {
+            value,
+            port,
+            state: state.encode(),
+        }))
+    }
+
+    fn benchmark(
+        &self,
+        uri: &Url,
+        text: &str,
+        event: &str,
+        iterations: usize,
+    ) -> Option<Result<Vec<Duration>, String>> {
+        let script = match self.parse(uri, text) {
+            Ok(script) => script,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let mut timings = Vec::with_capacity(iterations);
+        for _ in 0..iterations {
+            // scripts can modify the event, so every run gets a fresh one
+            let mut event_bytes = event.as_bytes().to_vec();
+            let mut event = match simd_json::to_borrowed_value(&mut event_bytes) {
+                Ok(event) => event,
+                Err(e) => return Some(Err(format!("Invalid event: {}", e))),
+            };
+            let mut state = Value::null();
+            let mut meta = Value::object();
+
+            let start = Instant::now();
+            let result = script.run(
+                &EventContext::new(0, None),
+                AggrType::Emit,
+                &mut event,
+                &mut state,
+                &mut meta,
+            );
+            timings.push(start.elapsed());
+
+            if let Err(e) = result {
+                return Some(Err(e.to_string()));
+            }
+        }
+        Some(Ok(timings))
     }
 }
@@ -0,0 +1,890 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// semantic checks that go beyond what the tremor parser reports
+
+use crate::blocks;
+use crate::embedded;
+use crate::flow::Flow;
+use crate::fuzzy;
+use crate::imports;
+use crate::language::{self, Language, Token, TokenSpan};
+use crate::literals;
+use crate::lsp_utils;
+use crate::modules;
+use crate::params;
+use crate::patch;
+use crate::pipeline;
+use crate::shapes::{Shape, Shapes};
+use std::collections::HashMap;
+use tower_lsp::lsp_types::*;
+
+pub const UNREACHABLE_CASE: &str = "unreachable-case";
+pub const UNDEFINED_STREAM: &str = "undefined-stream";
+pub const UNDEFINED_WINDOW: &str = "undefined-window";
+pub const UNCONNECTED_STREAM: &str = "unconnected-stream";
+pub const PIPELINE_CYCLE: &str = "pipeline-cycle";
+pub const TYPE_MISMATCH: &str = "type-mismatch";
+pub const ARGUMENT_COUNT: &str = "argument-count";
+pub const UNKNOWN_FUNCTION: &str = "unknown-function";
+pub const UNBALANCED_BLOCK: &str = "unbalanced-block";
+pub const UNTERMINATED_STRING: &str = "unterminated-string";
+pub const MALFORMED_PATCH: &str = "malformed-patch";
+pub const UNREACHABLE_STATEMENT: &str = "unreachable-statement";
+pub const IMPLICIT_EMIT: &str = "implicit-emit";
+pub const INVALID_TEST_FILE: &str = "invalid-test-file";
+pub const UNKNOWN_PARAMETER: &str = "unknown-parameter";
+pub const EXPENSIVE_IN_GROUP_BY: &str = "expensive-in-group-by";
+pub const INVALID_EMBEDDED_JSON: &str = "invalid-embedded-json";
+
+// functions too costly to run for every event of every group
+const EXPENSIVE_FUNCTIONS: &[&str] = &[
+    "json::decode",
+    "json::encode",
+    "json::encode_pretty",
+    "re::replace",
+    "re::replace_all",
+    "re::is_match",
+    "re::split",
+    "datetime::parse",
+];
+
+// record literals with at least this many fields are worth building once, upstream
+const LARGE_RECORD_FIELDS: usize = 8;
+
+// an edit that resolves a lint
+#[derive(Debug, Clone)]
+pub struct Fix {
+    pub title: String,
+    pub edits: Vec<TextEdit>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Lint {
+    pub code: &'static str,
+    pub range: Range,
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+    pub fixes: Vec<Fix>,
+}
+
+impl Lint {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            range: self.range,
+            message: self.message.clone(),
+            severity: Some(self.severity),
+            source: Some("tremor-language-server".to_string()),
+            code: Some(NumberOrString::String(self.code.to_string())),
+            related_information: None,
+            tags: None,
+        }
+    }
+
+    // true if the diagnostic (as sent back by the client) was produced from this lint
+    pub fn matches(&self, diagnostic: &Diagnostic) -> bool {
+        diagnostic.range == self.range
+            && diagnostic.code == Some(NumberOrString::String(self.code.to_string()))
+    }
+}
+
+pub fn check(language: &dyn Language, uri: &Url, text: &str, tokens: &[TokenSpan]) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    lints.extend(unreachable_cases(text, tokens));
+    lints.extend(undefined_select_references(text, tokens));
+    lints.extend(unconnected_streams(text, tokens));
+    lints.extend(pipeline_cycles(text, tokens));
+    lints.extend(type_mismatches(language, uri, text, tokens));
+    lints.extend(argument_counts(language, uri, text, tokens));
+    lints.extend(unknown_functions(language, uri, text, tokens));
+    lints.extend(malformed_patch_operations(text, tokens));
+    lints.extend(unknown_parameters(text, tokens));
+    lints.extend(expensive_group_by_work(text, tokens));
+    lints.extend(flow_lints(language, text, tokens));
+    lints.extend(invalid_embedded_json(text));
+    lints
+}
+
+// case arms that can never match because an earlier arm always matches before them
+fn unreachable_cases(text: &str, tokens: &[TokenSpan]) -> Vec<Lint> {
+    let mut lints = Vec::new();
+
+    for block in blocks::match_blocks(text, tokens) {
+        for (i, arm) in block.arms.iter().enumerate() {
+            let earlier = &block.arms[..i];
+            let remove = Fix {
+                title: "Remove unreachable case".to_string(),
+                edits: vec![TextEdit::new(arm.range, String::new())],
+            };
+
+            if let Some(default_arm) = earlier.iter().find(|a| a.is_default) {
+                let mut fixes = vec![remove];
+                if let Some(end_range) = block.end_range {
+                    fixes.push(Fix {
+                        title: "Move default case to the end".to_string(),
+                        edits: vec![
+                            TextEdit::new(default_arm.range, String::new()),
+                            TextEdit::new(
+                                Range::new(end_range.start, end_range.start),
+                                lsp_utils::text_in_range(text, default_arm.range),
+                            ),
+                        ],
+                    });
+                }
+                lints.push(Lint {
+                    code: UNREACHABLE_CASE,
+                    range: arm.keyword_range,
+                    severity: DiagnosticSeverity::Warning,
+                    message: format!(
+                        "Unreachable case: the default case on line {} always matches first",
+                        default_arm.keyword_range.start.line + 1
+                    ),
+                    fixes,
+                });
+            } else if let Some(same) = earlier
+                .iter()
+                .find(|a| !a.has_guard && !arm.pattern.is_empty() && a.pattern == arm.pattern)
+            {
+                lints.push(Lint {
+                    code: UNREACHABLE_CASE,
+                    range: arm.keyword_range,
+                    severity: DiagnosticSeverity::Warning,
+                    message: format!(
+                        "Unreachable case: the case on line {} has the same pattern",
+                        same.keyword_range.start.line + 1
+                    ),
+                    fixes: vec![remove],
+                });
+            }
+        }
+    }
+
+    lints
+}
+
+// streams (or other nodes) and windows used in select statements, without being created
+// or defined in the query
+fn undefined_select_references(text: &str, tokens: &[TokenSpan]) -> Vec<Lint> {
+    let nodes = pipeline::nodes(text, tokens);
+    let windows = pipeline::windows(text, tokens);
+
+    let selects = pipeline::selects(text, tokens);
+    // streams get created above the first select, so that all of them can use the stream
+    let first_select = match selects.first() {
+        Some(select) => Position::new(select.range.start.line, 0),
+        None => return vec![],
+    };
+
+    let mut lints = Vec::new();
+    for select in &selects {
+        for name in select.from.iter().chain(select.into.iter()) {
+            if !pipeline::is_node(&name.value, &nodes) {
+                lints.push(Lint {
+                    code: UNDEFINED_STREAM,
+                    range: name.range,
+                    severity: DiagnosticSeverity::Warning,
+                    message: format!("Undefined stream: {}", name.value),
+                    fixes: vec![Fix {
+                        title: format!("Create stream {}", name.value),
+                        edits: vec![TextEdit::new(
+                            Range::new(first_select, first_select),
+                            format!("create stream {};\n", name.value),
+                        )],
+                    }],
+                });
+            }
+        }
+        for name in &select.windows {
+            // windows from other modules are not checked here
+            if !name.value.contains("::") && !windows.iter().any(|w| w.name.value == name.value) {
+                lints.push(Lint {
+                    code: UNDEFINED_WINDOW,
+                    range: name.range,
+                    severity: DiagnosticSeverity::Warning,
+                    message: format!("Undefined window: {}", name.value),
+                    fixes: vec![],
+                });
+            }
+        }
+    }
+
+    lints
+}
+
+// created streams that events never get into, or never get out of
+fn unconnected_streams(text: &str, tokens: &[TokenSpan]) -> Vec<Lint> {
+    let selects = pipeline::selects(text, tokens);
+    let is_used = |name: &str, node: fn(&pipeline::Select) -> Option<&pipeline::Name>| {
+        selects
+            .iter()
+            .any(|select| node(select).map_or(false, |n| n.value == name))
+    };
+
+    pipeline::nodes(text, tokens)
+        .iter()
+        .filter(|node| node.kind == pipeline::NodeKind::Stream)
+        .filter_map(|node| {
+            let name = &node.name.value;
+            let message = match (
+                is_used(name, |s| s.into.as_ref()),
+                is_used(name, |s| s.from.as_ref()),
+            ) {
+                (true, true) => return None,
+                (false, false) => format!("Stream {} is never selected into or from", name),
+                (false, true) => format!("Stream {} is never selected into", name),
+                (true, false) => format!("Stream {} is never selected from", name),
+            };
+            Some(Lint {
+                code: UNCONNECTED_STREAM,
+                range: node.name.range,
+                severity: DiagnosticSeverity::Warning,
+                message,
+                fixes: vec![],
+            })
+        })
+        .collect()
+}
+
+// selects that feed events back into the node they read from
+fn pipeline_cycles(text: &str, tokens: &[TokenSpan]) -> Vec<Lint> {
+    let selects = pipeline::selects(text, tokens);
+
+    selects
+        .iter()
+        .filter_map(|select| {
+            let chain = pipeline::cycle(select, &selects)?;
+            Some(Lint {
+                code: PIPELINE_CYCLE,
+                range: select.range,
+                severity: DiagnosticSeverity::Warning,
+                message: format!("Select is part of a cycle: {}", chain.join(" -> ")),
+                fixes: vec![],
+            })
+        })
+        .collect()
+}
+
+// operations on values that can never work with their (obvious) types, like arithmetic on
+// records or calling a string function on an integer. warnings, as the types are guessed from
+// the text.
+fn type_mismatches(
+    language: &dyn Language,
+    uri: &Url,
+    text: &str,
+    tokens: &[TokenSpan],
+) -> Vec<Lint> {
+    let tokens = blocks::significant(tokens);
+    let shapes = Shapes::new(&tokens);
+    let range = |start: usize, end: usize| Range {
+        start: lsp_utils::to_lsp_position_in(text, &tokens[start].span.start),
+        end: lsp_utils::to_lsp_position_in(text, &tokens[end].span.end),
+    };
+    let lint = |range: Range, message: String| Lint {
+        code: TYPE_MISMATCH,
+        range,
+        severity: DiagnosticSeverity::Warning,
+        message,
+        fixes: vec![],
+    };
+
+    let mut lints = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        let before = i.checked_sub(1).and_then(|j| shapes.operand_ending_at(j));
+        match &t.value {
+            // `%{` and `%[` start patterns, rather than being the modulo operator
+            Token::Mod
+                if matches!(
+                    tokens.get(i + 1).map(|t| &t.value),
+                    Some(Token::LBrace) | Some(Token::LBracket) | Some(Token::LParen)
+                ) => {}
+            Token::Add | Token::Sub | Token::Mul | Token::Div | Token::Mod => {
+                let after = shapes.operand(i + 1).map(|(shape, _)| shape);
+                let is_add = t.value == Token::Add;
+                let invalid = before.iter().chain(after.iter()).find(|shape| match shape {
+                    // `+` also concatenates strings and arrays
+                    Shape::Array | Shape::String => !is_add,
+                    Shape::Record | Shape::Bool | Shape::Null => true,
+                    Shape::Int | Shape::Float => false,
+                });
+                if let Some(shape) = invalid {
+                    lints.push(lint(
+                        range(i, i),
+                        format!("Arithmetic on {} is not supported", shape.description()),
+                    ));
+                }
+            }
+            Token::LBracket | Token::Dot => {
+                if let Some(shape) = before.filter(|shape| shape.is_scalar()) {
+                    lints.push(lint(
+                        range(i, i),
+                        format!("Cannot index into {}", shape.description()),
+                    ));
+                }
+            }
+            Token::LParen => lints.extend(
+                call_argument_mismatches(language, uri, &tokens, &shapes, i)
+                    .into_iter()
+                    .map(|(start, end, message)| lint(range(start, end), message)),
+            ),
+            _ => (),
+        }
+    }
+
+    lints
+}
+
+// arguments of the call at the `(` index, that can't be of the type the function signature
+// asks for. as token ranges and messages.
+fn call_argument_mismatches(
+    language: &dyn Language,
+    uri: &Url,
+    tokens: &[&TokenSpan],
+    shapes: &Shapes,
+    paren: usize,
+) -> Vec<(usize, usize, String)> {
+    let call = match Call::at(tokens, paren) {
+        Some(call) => call,
+        None => return vec![],
+    };
+    let doc = match language.function_doc(uri, &call.function_name) {
+        Some(doc) => doc,
+        None => return vec![],
+    };
+
+    let mut mismatches = Vec::new();
+    for (arg, (start, end)) in call.args.iter().copied().enumerate() {
+        // signatures mostly name their arguments rather than typing them, but the first
+        // argument of eg. `string::` functions is always a string
+        let expected = doc
+            .signature
+            .args
+            .get(arg)
+            .map(ToString::to_string)
+            .into_iter()
+            .chain(Some(call.module_name.clone()).filter(|_| arg == 0))
+            .find_map(|type_name| Some((Shape::accepted_by(&type_name)?, type_name)));
+        let actual = shapes
+            .operand(start)
+            .filter(|(_, operand_end)| *operand_end == end)
+            .map(|(shape, _)| shape);
+        if let (Some((accepted, type_name)), Some(shape)) = (expected, actual) {
+            if !accepted.contains(&shape) {
+                mismatches.push((
+                    start,
+                    end,
+                    format!(
+                        "{} expects a {} as argument {}, got {}",
+                        call.function_name,
+                        type_name,
+                        arg + 1,
+                        shape.description()
+                    ),
+                ));
+            }
+        }
+    }
+    mismatches
+}
+
+// calls of known functions (including aggregate functions in queries) with more or fewer
+// arguments than their signature has
+fn argument_counts(
+    language: &dyn Language,
+    uri: &Url,
+    text: &str,
+    tokens: &[TokenSpan],
+) -> Vec<Lint> {
+    let tokens = blocks::significant(tokens);
+
+    let mut lints = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        if t.value != Token::LParen {
+            continue;
+        }
+        let call = match Call::at(&tokens, i) {
+            Some(call) => call,
+            None => continue,
+        };
+        let doc = match language.function_doc(uri, &call.function_name) {
+            Some(doc) => doc,
+            None => continue,
+        };
+
+        // eg: `string::format(format, ...)`
+        let args = &doc.signature.args;
+        let is_variadic = args.iter().any(|arg| arg.contains("..."));
+        let required = args.iter().filter(|arg| !arg.contains("...")).count();
+        let got = call.args.len();
+        let expected = match (is_variadic, got.cmp(&required)) {
+            (_, std::cmp::Ordering::Equal) | (true, std::cmp::Ordering::Greater) => continue,
+            (true, _) => format!("at least {}", required),
+            (false, _) => required.to_string(),
+        };
+        lints.push(Lint {
+            code: ARGUMENT_COUNT,
+            range: Range {
+                start: lsp_utils::to_lsp_position_in(text, &tokens[call.start].span.start),
+                end: lsp_utils::to_lsp_position_in(text, &tokens[call.close].span.end),
+            },
+            severity: DiagnosticSeverity::Error,
+            message: format!(
+                "{} expects {} argument{}, got {}",
+                call.function_name,
+                expected,
+                if required == 1 { "" } else { "s" },
+                got
+            ),
+            fixes: vec![],
+        });
+    }
+
+    lints
+}
+
+// calls of functions that their (builtin) module does not have, with a suggestion for the
+// closest name if it looks like a typo
+fn unknown_functions(
+    language: &dyn Language,
+    uri: &Url,
+    text: &str,
+    tokens: &[TokenSpan],
+) -> Vec<Lint> {
+    let document_imports = imports::parse(tokens);
+    let builtin_modules = language.modules(uri);
+    let tokens = blocks::significant(tokens);
+
+    // whether a module path (as written) is a user module, by module path
+    let mut user_modules: HashMap<String, bool> = HashMap::new();
+    let mut lints = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        if t.value != Token::LParen {
+            continue;
+        }
+        let call = match Call::at(&tokens, i) {
+            Some(call) if !call.module_name.is_empty() => call,
+            _ => continue,
+        };
+        let (module_path, function_name) =
+            match call.function_name.rsplitn(2, "::").collect::<Vec<_>>()[..] {
+                [function_name, module_path] => (module_path, function_name),
+                _ => continue,
+            };
+        // user modules are not checked, even where they have the name of a builtin module
+        let is_user_module = *user_modules
+            .entry(module_path.to_string())
+            .or_insert_with(|| {
+                let parts: Vec<String> = module_path.split("::").map(String::from).collect();
+                let full_path = match document_imports
+                    .iter()
+                    .find(|import| Some(import.name()) == parts.first().map(String::as_str))
+                {
+                    Some(import) => import
+                        .module_path
+                        .iter()
+                        .chain(parts.iter().skip(1))
+                        .cloned()
+                        .collect(),
+                    None => parts,
+                };
+                modules::resolve(uri, &full_path).is_some()
+            });
+        if is_user_module {
+            continue;
+        }
+        // nor are modules we don't know the functions of
+        let builtin_module = imports::unalias(&document_imports, module_path, &builtin_modules);
+        let functions = language.functions(uri, &builtin_module);
+        if functions.is_empty() || functions.iter().any(|f| f == function_name) {
+            continue;
+        }
+
+        let range = Range {
+            start: lsp_utils::to_lsp_position_in(text, &tokens[call.start].span.start),
+            end: lsp_utils::to_lsp_position_in(text, &tokens[i - 1].span.end),
+        };
+        // at most a third of the name can be off
+        let suggestion = functions
+            .iter()
+            .map(|f| (fuzzy::edit_distance(function_name, f), f))
+            .filter(|(distance, f)| *distance <= 2.max(f.chars().count() / 3))
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, f)| format!("{}::{}", module_path, f));
+
+        lints.push(Lint {
+            code: UNKNOWN_FUNCTION,
+            range,
+            severity: DiagnosticSeverity::Error,
+            message: match &suggestion {
+                Some(suggestion) => format!(
+                    "Unknown function {}, did you mean `{}`?",
+                    call.function_name, suggestion
+                ),
+                None => format!("Unknown function {}", call.function_name),
+            },
+            fixes: suggestion
+                .map(|suggestion| Fix {
+                    title: format!("Change to {}", suggestion),
+                    edits: vec![TextEdit::new(range, suggestion)],
+                })
+                .into_iter()
+                .collect(),
+        });
+    }
+
+    lints
+}
+
+// costly calls and large record literals in the target of select statements with a
+// `group by`, which runs for each group an event falls in (and more so with `each`)
+fn expensive_group_by_work(text: &str, tokens: &[TokenSpan]) -> Vec<Lint> {
+    let tokens = blocks::significant(tokens);
+    let hint = "consider doing it once per event in a script ahead of the select \
+                (`define script ...`, `create script ...`)";
+
+    let mut lints = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        if t.value != Token::Select {
+            continue;
+        }
+        // where the target ends, and whether the statement groups
+        let mut from = None;
+        let mut grouped = false;
+        let mut depth = 0;
+        for (j, t) in tokens.iter().enumerate().skip(i + 1) {
+            match &t.value {
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+                Token::From if depth == 0 && from.is_none() => from = Some(j),
+                Token::Group if depth == 0 => {
+                    grouped = tokens.get(j + 1).map(|t| &t.value) == Some(&Token::By);
+                }
+                Token::Semi if depth <= 0 => break,
+                _ => (),
+            }
+            if grouped {
+                break;
+            }
+        }
+        let from = match from {
+            Some(from) if grouped => from,
+            _ => continue,
+        };
+
+        for k in i + 1..from {
+            match tokens[k].value {
+                Token::LParen => {
+                    let call = match Call::at(&tokens, k) {
+                        Some(call)
+                            if EXPENSIVE_FUNCTIONS.contains(&call.function_name.as_str()) =>
+                        {
+                            call
+                        }
+                        _ => continue,
+                    };
+                    lints.push(Lint {
+                        code: EXPENSIVE_IN_GROUP_BY,
+                        range: Range::new(
+                            lsp_utils::token_range(text, tokens[call.start]).start,
+                            lsp_utils::token_range(text, tokens[k - 1]).end,
+                        ),
+                        severity: DiagnosticSeverity::Information,
+                        message: format!(
+                            "`{}` runs for every event of every group here, {}",
+                            call.function_name, hint
+                        ),
+                        fixes: vec![],
+                    });
+                }
+                Token::LBrace => {
+                    let mut fields = 0;
+                    let mut depth = 0;
+                    let mut close = None;
+                    for (m, t) in tokens.iter().enumerate().take(from).skip(k + 1) {
+                        match &t.value {
+                            Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                            Token::RBrace if depth == 0 => {
+                                close = Some(m);
+                                break;
+                            }
+                            Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+                            Token::Comma if depth == 0 => fields += 1,
+                            _ if fields == 0 => fields = 1,
+                            _ => (),
+                        }
+                    }
+                    // a trailing comma does not make a field
+                    if close.map_or(false, |close| tokens[close - 1].value == Token::Comma) {
+                        fields -= 1;
+                    }
+                    if fields < LARGE_RECORD_FIELDS {
+                        continue;
+                    }
+                    lints.push(Lint {
+                        code: EXPENSIVE_IN_GROUP_BY,
+                        range: lsp_utils::token_range(text, tokens[k]),
+                        severity: DiagnosticSeverity::Information,
+                        message: format!(
+                            "A record of {} fields is built for every event of every group here, {}",
+                            fields, hint
+                        ),
+                        fixes: vec![],
+                    });
+                }
+                _ => (),
+            }
+        }
+    }
+
+    lints
+}
+
+// parameters that the window or operator kind of a define statement does not have
+fn unknown_parameters(text: &str, tokens: &[TokenSpan]) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    for definition in params::definitions(text, tokens) {
+        let known: Vec<&str> = definition
+            .kind
+            .params
+            .iter()
+            .map(|(name, _)| *name)
+            .collect();
+        for param in &definition.params {
+            if known.contains(&param.value.as_str()) {
+                continue;
+            }
+            let suggestion = known
+                .iter()
+                .map(|name| (fuzzy::edit_distance(&param.value, name), name))
+                .filter(|(distance, name)| *distance <= 2.max(name.chars().count() / 3))
+                .min_by_key(|(distance, _)| *distance)
+                .map(|(_, name)| name.to_string());
+            let message = match (&suggestion, known.is_empty()) {
+                (Some(suggestion), _) => format!(
+                    "Unknown parameter `{}` for {}, did you mean `{}`?",
+                    param.value, definition.kind.name, suggestion
+                ),
+                (None, true) => format!(
+                    "Unknown parameter `{}`, {} takes no parameters",
+                    param.value, definition.kind.name
+                ),
+                (None, false) => format!(
+                    "Unknown parameter `{}` for {}, expected one of: {}",
+                    param.value,
+                    definition.kind.name,
+                    known.join(", ")
+                ),
+            };
+            lints.push(Lint {
+                code: UNKNOWN_PARAMETER,
+                range: param.range,
+                severity: DiagnosticSeverity::Warning,
+                message,
+                fixes: suggestion
+                    .map(|suggestion| Fix {
+                        title: format!("Change to {}", suggestion),
+                        edits: vec![TextEdit::new(param.range, suggestion)],
+                    })
+                    .into_iter()
+                    .collect(),
+            });
+        }
+    }
+    lints
+}
+
+// patch operations that are unknown, or miss their key or value
+fn malformed_patch_operations(text: &str, tokens: &[TokenSpan]) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    for operation in patch::patches(text, tokens)
+        .iter()
+        .flat_map(|p| p.operations.iter())
+    {
+        let keyword = operation.keyword.as_str();
+        let message = if !patch::OPERATIONS.iter().any(|(k, _)| *k == keyword) {
+            let keywords: Vec<&str> = patch::OPERATIONS.iter().map(|(k, _)| *k).collect();
+            format!(
+                "Unknown patch operation `{}`, expected one of: {}",
+                keyword,
+                keywords.join(", ")
+            )
+        } else if keyword == "erase" && !operation.has_key {
+            "`erase` needs the key of the field to remove".to_string()
+        } else if keyword == "erase" && operation.has_arrow {
+            "`erase` takes no value, only the key of the field to remove".to_string()
+        } else if keyword == "erase" {
+            continue;
+        } else if !operation.has_key && keyword != "merge" && keyword != "default" {
+            format!("`{}` needs the key of the field", keyword)
+        } else if !operation.has_arrow || !operation.has_value {
+            format!("`{}` needs a value, as in `=> value`", keyword)
+        } else {
+            continue;
+        };
+
+        lints.push(Lint {
+            code: MALFORMED_PATCH,
+            range: operation.keyword_range,
+            severity: DiagnosticSeverity::Error,
+            message,
+            fixes: vec![],
+        });
+    }
+    lints
+}
+
+// statements after `emit` or `drop` (that never run), and scripts that can reach their end
+// without either (emitting the last value implicitly). match expressions without a default
+// case are left to the parser, which warns about those itself.
+// queries don't emit or drop events themselves, so the flow is only followed within the
+// scripts embedded in them
+fn flow_lints(language: &dyn Language, text: &str, tokens: &[TokenSpan]) -> Vec<Lint> {
+    if language.name() == language::EMBEDDED_LANGUAGE_NAME {
+        return emit_and_drop_flow(text, tokens);
+    }
+    embedded::scripts(text, tokens)
+        .iter()
+        .flat_map(|script| emit_and_drop_flow(text, script.tokens))
+        .collect()
+}
+
+fn emit_and_drop_flow(text: &str, tokens: &[TokenSpan]) -> Vec<Lint> {
+    let tokens = blocks::significant(tokens);
+    let flow = Flow::new(&tokens);
+    let range = |start: usize, end: usize| Range {
+        start: lsp_utils::to_lsp_position_in(text, &tokens[start].span.start),
+        end: lsp_utils::to_lsp_position_in(text, &tokens[end.max(start + 1) - 1].span.end),
+    };
+
+    let mut lints = Vec::new();
+    for sequence in flow.sequences() {
+        let unreachable = sequence
+            .windows(2)
+            .find(|pair| flow.terminates(pair[0]))
+            .map(|pair| pair[1]);
+        if let Some((start, end)) = unreachable {
+            lints.push(Lint {
+                code: UNREACHABLE_STATEMENT,
+                range: range(start, end),
+                severity: DiagnosticSeverity::Warning,
+                message: "Unreachable: the event is emitted or dropped before this".to_string(),
+                fixes: vec![],
+            });
+        }
+    }
+
+    // queries only have scripts embedded, and those get their own emits in `define script`
+    let is_script = !tokens
+        .iter()
+        .any(|t| matches!(t.value, Token::Select | Token::Create | Token::Define));
+    let is_explicit = tokens
+        .iter()
+        .any(|t| matches!(t.value, Token::Emit | Token::Drop));
+    if let (true, true, Some(last)) = (is_script, is_explicit, flow.top_level().last()) {
+        if !flow.terminates(*last) {
+            lints.push(Lint {
+                code: IMPLICIT_EMIT,
+                range: range(last.0, last.1),
+                severity: DiagnosticSeverity::Hint,
+                message: "The script can get here without `emit` or `drop`, emitting the value \
+                          of this statement implicitly"
+                    .to_string(),
+                fixes: vec![],
+            });
+        }
+    }
+
+    lints
+}
+
+// a `module::function(...)` call in the (significant) tokens
+struct Call {
+    function_name: String,
+    // empty for functions without a module
+    module_name: String,
+    // token indices of where the call starts, its closing `)` and the first and last tokens
+    // of each argument
+    start: usize,
+    close: usize,
+    args: Vec<(usize, usize)>,
+}
+
+impl Call {
+    // the call with its `(` at the index. None for unfinished calls.
+    fn at(tokens: &[&TokenSpan], paren: usize) -> Option<Self> {
+        let is_ident = |i: usize| matches!(tokens[i].value, Token::Ident(_, _));
+        if paren == 0 || !is_ident(paren - 1) {
+            return None;
+        }
+        let mut start = paren - 1;
+        while start >= 2 && tokens[start - 1].value == Token::ColonColon && is_ident(start - 2) {
+            start -= 2;
+        }
+        let function_name: String = tokens[start..paren]
+            .iter()
+            .map(|t| match &t.value {
+                Token::Ident(name, _) => name.to_string(),
+                other => other.to_string(),
+            })
+            .collect();
+        let module_name = match &tokens[start].value {
+            Token::Ident(name, _) if start + 1 < paren - 1 => name.to_string(),
+            _ => String::new(),
+        };
+
+        let mut args = Vec::new();
+        let mut arg_start = paren + 1;
+        let mut depth = 0;
+        for (j, t) in tokens.iter().enumerate().skip(paren + 1) {
+            match &t.value {
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RParen if depth == 0 => {
+                    // `f()` has no arguments
+                    if arg_start < j {
+                        args.push((arg_start, j - 1));
+                    }
+                    return Some(Self {
+                        function_name,
+                        module_name,
+                        start,
+                        close: j,
+                        args,
+                    });
+                }
+                Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+                Token::Comma if depth == 0 => {
+                    args.push((arg_start, j.saturating_sub(1)));
+                    arg_start = j + 1;
+                }
+                // the call was not closed before the statement ended
+                Token::Semi if depth <= 0 => return None,
+                _ => (),
+            }
+        }
+        None
+    }
+}
+
+// heredocs holding json (eg: a constant to decode) that doesn't parse
+fn invalid_embedded_json(text: &str) -> Vec<Lint> {
+    literals::invalid_json(text)
+        .into_iter()
+        .map(|(range, message)| Lint {
+            code: INVALID_EMBEDDED_JSON,
+            range,
+            severity: DiagnosticSeverity::Warning,
+            message: format!("Invalid JSON in heredoc: {}", message),
+            fixes: vec![],
+        })
+        .collect()
+}
@@ -0,0 +1,266 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// hover for literals whose value differs from how they are written: hex and binary
+// numbers, strings with escapes and heredocs (with json in them checked)
+
+use crate::balance;
+use crate::blocks;
+use crate::language::{Token, TokenSpan};
+use crate::lsp_utils;
+use tower_lsp::lsp_types::*;
+
+pub fn hover(text: &str, tokens: &[TokenSpan], position: Position) -> Option<(String, Range)> {
+    number_hover(text, tokens, position).or_else(|| string_hover(text, position))
+}
+
+fn number_hover(text: &str, tokens: &[TokenSpan], position: Position) -> Option<(String, Range)> {
+    let token = blocks::significant(tokens).into_iter().find(|t| {
+        let range = lsp_utils::token_range(text, t);
+        range.start <= position && position < range.end
+    })?;
+    let range = lsp_utils::token_range(text, token);
+    let written = lsp_utils::text_in_range(text, range);
+
+    let (kind, value) = match token.value {
+        Token::IntLiteral(value) => (
+            "Integer",
+            format!("`{}` (hex `{:#x}`, binary `{:#b}`)", value, value, value),
+        ),
+        Token::FloatLiteral(value, _) => ("Float", format!("`{:?}`", value)),
+        _ => return None,
+    };
+    // nothing to tell for the likes of `42`
+    if value.starts_with(&format!("`{}`", written)) {
+        return None;
+    }
+    Some((
+        format!("{} literal `{}`\n\n= {}", kind, written, value),
+        range,
+    ))
+}
+
+// a string or heredoc, as written
+pub struct StringLiteral {
+    pub range: Range,
+    // between the quotes
+    pub raw: String,
+    pub heredoc: bool,
+}
+
+// the strings and heredocs of the text, up to the first unterminated one
+pub fn string_literals(text: &str) -> Vec<StringLiteral> {
+    let chars = balance::positioned_chars(text);
+    let starts_with = |i: usize, pattern: &str| {
+        pattern
+            .chars()
+            .enumerate()
+            .all(|(k, p)| chars.get(i + k).map(|(c, _)| *c) == Some(p))
+    };
+    let content = |start: usize, end: usize| -> String {
+        chars[start..end].iter().map(|(c, _)| *c).collect()
+    };
+    // every line ends in a `\n`, so there is a char after any closing quote
+    let range = |start: usize, end: usize| Some(Range::new(chars[start].1, chars.get(end)?.1));
+
+    let mut literals = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i].0 {
+            '#' => {
+                while i < chars.len() && chars[i].0 != '\n' {
+                    i += 1;
+                }
+            }
+            '`' => {
+                i += 1;
+                while i < chars.len() && chars[i].0 != '`' {
+                    i += 1;
+                }
+            }
+            '"' if starts_with(i, "\"\"\"") => {
+                let close = match (i + 3..chars.len()).find(|k| starts_with(*k, "\"\"\"")) {
+                    Some(close) => close,
+                    None => break,
+                };
+                if let Some(range) = range(i, close + 3) {
+                    literals.push(StringLiteral {
+                        range,
+                        raw: content(i + 3, close),
+                        heredoc: true,
+                    });
+                }
+                i = close + 2;
+            }
+            '"' => {
+                let close = match balance::string_end(&chars, i + 1) {
+                    Some(close) => close,
+                    None => break,
+                };
+                if let Some(range) = range(i, close + 1) {
+                    literals.push(StringLiteral {
+                        range,
+                        raw: content(i + 1, close),
+                        heredoc: false,
+                    });
+                }
+                i = close;
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    literals
+}
+
+fn string_hover(text: &str, position: Position) -> Option<(String, Range)> {
+    let literal = string_literals(text)
+        .into_iter()
+        .find(|l| l.range.start <= position && position < l.range.end)?;
+    if literal.heredoc {
+        let (content, _) = normalize_heredoc(&literal.raw);
+        return Some((heredoc_hover(&unescape(&content)), literal.range));
+    }
+    // plain strings read as they are
+    if !literal.raw.contains('\\') {
+        return None;
+    }
+    Some((
+        format!(
+            "String literal, as parsed\n\n```\n{}\n```",
+            unescape(&literal.raw)
+        ),
+        literal.range,
+    ))
+}
+
+// json content is shown pretty-printed, or with what's wrong with it
+fn heredoc_hover(value: &str) -> String {
+    if !is_json_like(value) {
+        return format!("Heredoc content\n\n```\n{}\n```", value);
+    }
+    match serde_json::from_str::<serde_json::Value>(value) {
+        Ok(json) => format!(
+            "Heredoc content (JSON)\n\n```json\n{}\n```",
+            serde_json::to_string_pretty(&json).unwrap_or_else(|_| value.to_string())
+        ),
+        Err(e) => format!(
+            "Heredoc content (invalid JSON: {})\n\n```\n{}\n```",
+            e, value
+        ),
+    }
+}
+
+// heredocs that start out as an object or array, without interpolations
+fn is_json_like(value: &str) -> bool {
+    let value = value.trim();
+    (value.starts_with('{') && value.ends_with('}')
+        || value.starts_with('[') && value.ends_with(']'))
+        && !value.contains("#{")
+}
+
+// heredocs with json content that doesn't parse, as the range of the error and its message
+pub fn invalid_json(text: &str) -> Vec<(Range, String)> {
+    let lines: Vec<&str> = text.lines().collect();
+    string_literals(text)
+        .into_iter()
+        .filter(|literal| literal.heredoc)
+        .filter_map(|literal| {
+            let (content, indentation) = normalize_heredoc(&literal.raw);
+            let value = unescape(&content);
+            if !is_json_like(&value) {
+                return None;
+            }
+            let e = serde_json::from_str::<serde_json::Value>(&value).err()?;
+            // serde's message ends with the position in the content, which we point at instead
+            let message = e.to_string();
+            let message = message
+                .split(" at line ")
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            // content lines start on the line after the opening quotes
+            let line = literal.range.start.line + e.line() as u64;
+            let range = match lines.get(line as usize) {
+                Some(line_text) if line < literal.range.end.line => {
+                    let column = indentation + e.column().saturating_sub(1);
+                    let start = lsp_utils::to_utf16_character(line_text, column);
+                    let end = lsp_utils::to_utf16_character(line_text, column + 1);
+                    Range::new(Position::new(line, start), Position::new(line, end))
+                }
+                _ => literal.range,
+            };
+            Some((range, message))
+        })
+        .collect()
+}
+
+// string with its escape sequences replaced by what they stand for
+fn unescape(raw: &str) -> String {
+    let mut value = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('t') => value.push('\t'),
+            Some('r') => value.push('\r'),
+            Some('b') => value.push('\u{8}'),
+            Some('f') => value.push('\u{c}'),
+            Some('u') => {
+                let code: String = chars.by_ref().take(4).collect();
+                match u32::from_str_radix(&code, 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+                {
+                    Some(c) => value.push(c),
+                    // left as written
+                    None => {
+                        value.push_str("\\u");
+                        value.push_str(&code);
+                    }
+                }
+            }
+            // `\\`, `\"`, `\/` and the like
+            Some(c) => value.push(c),
+            None => value.push('\\'),
+        }
+    }
+    value
+}
+
+// heredoc content without the line break after the opening quotes, the line of the closing
+// quotes, and the indentation common to its lines (which it returns as well, in chars)
+fn normalize_heredoc(content: &str) -> (String, usize) {
+    let content = content.splitn(2, '\n').nth(1).unwrap_or_default();
+    let mut lines: Vec<&str> = content.split('\n').collect();
+    if lines.last().map_or(false, |line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    let indentation = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let content = lines
+        .iter()
+        .map(|line| line.get(indentation..).unwrap_or_else(|| line.trim_start()))
+        .collect::<Vec<&str>>()
+        .join("\n");
+    (content, indentation)
+}
@@ -15,78 +15,437 @@
 use crate::language;
 use tower_lsp::lsp_types::*;
 
-use crate::backend::file_dbg;
-
 pub fn to_lsp_position(location: &language::Location) -> Position {
     // position in language server protocol is zero-based
     Position::new((location.line() - 1) as u64, (location.column() - 1) as u64)
 }
 
-pub fn to_language_location(position: &Position) -> language::Location {
+pub fn to_lsp_severity(error_level: &language::ErrorLevel) -> DiagnosticSeverity {
+    match error_level {
+        language::ErrorLevel::Error => DiagnosticSeverity::Error,
+        language::ErrorLevel::Warning => DiagnosticSeverity::Warning,
+        language::ErrorLevel::Hint => DiagnosticSeverity::Hint,
+    }
+}
+
+// edit of a single document
+pub fn workspace_edit(uri: &Url, edits: Vec<TextEdit>) -> WorkspaceEdit {
+    let mut changes = std::collections::HashMap::new();
+    changes.insert(uri.clone(), edits);
+    WorkspaceEdit::new(changes)
+}
+
+// workspace edit (as json) that clients preview and ask the user to confirm before applying,
+// via a change annotation on each of its edits. annotations are newer than the protocol
+// version we implement, hence no types for them.
+pub fn annotated_workspace_edit(
+    changes: std::collections::HashMap<Url, Vec<TextEdit>>,
+    label: &str,
+    description: &str,
+) -> serde_json::Value {
+    let id = "confirm";
+    let mut changes: Vec<(Url, Vec<TextEdit>)> = changes.into_iter().collect();
+    changes.sort_by(|a, b| a.0.cmp(&b.0));
+    let document_changes: Vec<serde_json::Value> = changes
+        .iter()
+        .map(|(uri, edits)| {
+            let edits: Vec<serde_json::Value> = edits
+                .iter()
+                .map(|edit| {
+                    serde_json::json!({
+                        "range": edit.range,
+                        "newText": edit.new_text,
+                        "annotationId": id,
+                    })
+                })
+                .collect();
+            serde_json::json!({
+                "textDocument": { "uri": uri, "version": null },
+                "edits": edits,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "documentChanges": document_changes,
+        "changeAnnotations": {
+            id: {
+                "label": label,
+                "needsConfirmation": true,
+                "description": description,
+            }
+        }
+    })
+}
+
+// text as it is inserted from a snippet: `\\`, `$` and `}` escaped
+pub fn escape_snippet(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('$', "\\$")
+        .replace('}', "\\}")
+}
+
+// text a snippet inserts, for clients that can't expand snippets: placeholders replaced by
+// their default text (if any), and escapes resolved
+pub fn snippet_to_plain_text(snippet: &str) -> String {
+    let mut text = String::with_capacity(snippet.len());
+    let mut chars = snippet.chars().peekable();
+    // placeholders opened with `${n:` whose `}` is still to come
+    let mut open = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                while chars.peek().map_or(false, char::is_ascii_digit) {
+                    chars.next();
+                }
+                if chars.next() == Some(':') {
+                    open += 1;
+                }
+            }
+            '$' if chars.peek().map_or(false, char::is_ascii_digit) => {
+                while chars.peek().map_or(false, char::is_ascii_digit) {
+                    chars.next();
+                }
+            }
+            '}' if open > 0 => open -= 1,
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+// readable version of markdown, for clients that can't render it: without code fences, and
+// with links as `text (url)`
+pub fn to_plain_text(markdown: &str) -> String {
+    let mut lines = Vec::new();
+    for line in markdown.lines() {
+        if line.trim_start().starts_with("```") {
+            continue;
+        }
+        let mut plain = String::new();
+        let mut rest = line;
+        // `[text](url)`
+        while let Some(open) = rest.find('[') {
+            let link = rest[open..].find(']').and_then(|middle| {
+                let middle = open + middle;
+                if !rest[middle..].starts_with("](") {
+                    return None;
+                }
+                Some((middle, rest[middle..].find(')')? + middle))
+            });
+            match link {
+                Some((middle, close)) => {
+                    plain.push_str(&rest[..open]);
+                    plain.push_str(&format!(
+                        "{} ({})",
+                        &rest[open + 1..middle],
+                        &rest[middle + 2..close]
+                    ));
+                    rest = &rest[close + 1..];
+                }
+                None => {
+                    plain.push_str(&rest[..=open]);
+                    rest = &rest[open + 1..];
+                }
+            }
+        }
+        plain.push_str(rest);
+        lines.push(plain);
+    }
+    lines.join("\n")
+}
+
+// a (possibly module qualified) identifier at a position, eg: `string::format`
+#[derive(Debug, Clone)]
+pub struct PathToken {
+    pub value: String,
+    pub range: Range,
+}
+
+// LSP positions count utf-16 code units in a line, while our locations count chars
+pub fn to_char_column(line: &str, character: u64) -> usize {
+    let mut units = 0;
+    let mut column = 0;
+    for c in line.chars() {
+        if units >= character as usize {
+            break;
+        }
+        units += c.len_utf16();
+        column += 1;
+    }
+    column
+}
+
+pub fn to_utf16_character(line: &str, column: usize) -> u64 {
+    line.chars()
+        .take(column)
+        .map(char::len_utf16)
+        .sum::<usize>() as u64
+}
+
+// encoding-aware variant of to_lsp_position(), for when the document text is around
+pub fn to_lsp_position_in(text: &str, location: &language::Location) -> Position {
+    let line = text.lines().nth(location.line() - 1).unwrap_or("");
+    Position::new(
+        (location.line() - 1) as u64,
+        to_utf16_character(line, location.column() - 1),
+    )
+}
+
+pub fn to_language_location_in(text: &str, position: &Position) -> language::Location {
+    let line = text.lines().nth(position.line as usize).unwrap_or("");
     // location numbers in our languages starts from one
     language::Location::new(
         (position.line + 1) as usize,
-        (position.character + 1) as usize,
+        to_char_column(line, position.character) + 1,
         0,
         0, // absolute byte offset -- we don't use it here so setting to 0
     )
 }
 
-pub fn to_lsp_severity(error_level: &language::ErrorLevel) -> DiagnosticSeverity {
-    match error_level {
-        language::ErrorLevel::Error => DiagnosticSeverity::Error,
-        language::ErrorLevel::Warning => DiagnosticSeverity::Warning,
-        language::ErrorLevel::Hint => DiagnosticSeverity::Hint,
+pub fn token_range(text: &str, token: &language::TokenSpan) -> Range {
+    Range {
+        start: to_lsp_position_in(text, &token.span.start),
+        end: to_lsp_position_in(text, &token.span.end),
     }
 }
 
-pub fn get_token(tokens: Vec<language::TokenSpan>, position: Position) -> Option<String> {
-    let location = to_language_location(&position);
-
-    //file_dbg("get_token_location_line", &location.line.to_string());
-    //file_dbg("get_token_location_column", &location.column.to_string());
-
-    let mut token = None;
-    for (i, t) in tokens.iter().enumerate() {
-        if t.span.end.line() == location.line() && t.span.end.column() > location.column() {
-            //file_dbg("get_token_span_end", &token.span.end.line.to_string());
-            //file_dbg("get_token_location_end", &location.line.to_string());
-            file_dbg("get_token_t_value", &t.value.to_string());
-
-            token = match t.value {
-                language::Token::Ident(_, _) => {
-                    if language::Token::ColonColon == tokens[i - 1].value {
-                        Some(format!(
-                            "{}{}{}",
-                            tokens[i - 2].value,
-                            tokens[i - 1].value,
-                            tokens[i].value
-                        ))
-                    } else if language::Token::ColonColon == tokens[i + 1].value {
-                        Some(format!(
-                            "{}{}{}",
-                            tokens[i].value,
-                            tokens[i + 1].value,
-                            tokens[i + 2].value,
-                        ))
-                    } else {
-                        None
-                    }
-                }
+// markdown link to where something is defined, eg: `Defined at [foo.tremor:3](...)`
+pub fn definition_link(uri: &Url, range: Range) -> String {
+    format!("Defined at {}", location_link(uri, range))
+}
 
-                language::Token::ColonColon => Some(format!(
-                    "{}{}{}",
-                    tokens[i - 1].value,
-                    //t.value,
-                    tokens[i].value,
-                    tokens[i + 1].value
-                )),
-                _ => None,
-            };
+// markdown link to the line of the range, eg: `[foo.tremor:3](...)`
+pub fn location_link(uri: &Url, range: Range) -> String {
+    let line = range.start.line + 1;
+    let file_name = uri
+        .path_segments()
+        .and_then(Iterator::last)
+        .unwrap_or_default();
+    format!("[{}:{}]({}#L{})", file_name, line, uri, line)
+}
 
-            break;
+// byte offset in the text for the location
+pub fn to_offset(text: &str, location: &language::Location) -> usize {
+    let line_start: usize = text
+        .split_inclusive('\n')
+        .take(location.line() - 1)
+        .map(str::len)
+        .sum();
+    let column_offset: usize = text[line_start..]
+        .chars()
+        .take(location.column() - 1)
+        .map(char::len_utf8)
+        .sum();
+    line_start + column_offset
+}
+
+// byte offset in the text for the (lsp) position
+pub fn position_to_offset(text: &str, position: Position) -> usize {
+    let line_start: usize = text
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum();
+    let line = text[line_start..].lines().next().unwrap_or("");
+    let column = to_char_column(line, position.character);
+    line_start + line.chars().take(column).map(char::len_utf8).sum::<usize>()
+}
+
+pub fn text_in_range(text: &str, range: Range) -> String {
+    text.get(position_to_offset(text, range.start)..position_to_offset(text, range.end))
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn contains(token: &language::TokenSpan, location: &language::Location) -> bool {
+    let start = (token.span.start.line(), token.span.start.column());
+    let end = (token.span.end.line(), token.span.end.column());
+    let location = (location.line(), location.column());
+    start <= location && location < end
+}
+
+pub fn get_token(
+    text: &str,
+    tokens: &[language::TokenSpan],
+    position: Position,
+) -> Option<PathToken> {
+    let location = to_language_location_in(text, &position);
+    let index = tokens.iter().position(|t| contains(t, &location))?;
+
+    match &tokens[index].value {
+        language::Token::Ident(_, _) | language::Token::ColonColon => {
+            let (start, end) = get_path_bounds(tokens, index);
+            let value = tokens[start..=end]
+                .iter()
+                .map(|t| match &t.value {
+                    language::Token::Ident(name, _) => name.to_string(),
+                    other => other.to_string(),
+                })
+                .collect();
+            Some(PathToken {
+                value,
+                range: Range {
+                    start: to_lsp_position_in(text, &tokens[start].span.start),
+                    end: to_lsp_position_in(text, &tokens[end].span.end),
+                },
+            })
         }
+        // lexer does not look into string interpolation, so we do it ourselves
+        language::Token::StringLiteral(_) => get_interpolated_token(text, position),
+        _ => None,
+    }
+}
+
+// expands the token at index to the full `module::name` path it is part of
+fn get_path_bounds(tokens: &[language::TokenSpan], index: usize) -> (usize, usize) {
+    let is_ident = |i: usize| {
+        matches!(
+            tokens.get(i).map(|t| &t.value),
+            Some(language::Token::Ident(_, _))
+        )
+    };
+    let is_colons = |i: usize| {
+        matches!(
+            tokens.get(i).map(|t| &t.value),
+            Some(language::Token::ColonColon)
+        )
+    };
+
+    let mut start = index;
+    if is_colons(start) && start > 0 && is_ident(start - 1) {
+        start -= 1;
+    }
+    while start >= 2 && is_colons(start - 1) && is_ident(start - 2) {
+        start -= 2;
+    }
+
+    let mut end = index;
+    if is_colons(end) && is_ident(end + 1) {
+        end += 1;
+    }
+    while is_ident(end) && is_colons(end + 1) {
+        // trailing `::` is kept, since that's what we see during completion
+        end += if is_ident(end + 2) { 2 } else { 1 };
+    }
+
+    (start, end)
+}
+
+// finds the token inside a `#{...}` interpolation around the position, if any
+fn get_interpolated_token(text: &str, position: Position) -> Option<PathToken> {
+    let line = text.lines().nth(position.line as usize)?;
+    let chars: Vec<char> = line.chars().collect();
+    let column = to_char_column(line, position.character);
+    let (open_column, close_column) = interpolation_bounds(&chars, column)?;
+
+    let expression: String = chars.get(open_column..close_column)?.iter().collect();
+    let tokens: Vec<language::TokenSpan> = language::Tokenizer::new(&expression)
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let offset = to_utf16_character(line, open_column);
+
+    get_token(
+        &expression,
+        &tokens,
+        Position::new(0, position.character - offset),
+    )
+    .map(|token| PathToken {
+        value: token.value,
+        range: Range {
+            start: Position::new(position.line, token.range.start.character + offset),
+            end: Position::new(position.line, token.range.end.character + offset),
+        },
+    })
+}
+
+// columns where the expression of the innermost `#{...}` interpolation around the column
+// starts and ends
+fn interpolation_bounds(chars: &[char], column: usize) -> Option<(usize, usize)> {
+    let opens = (1..column.min(chars.len()))
+        .rev()
+        .filter(|i| chars[i - 1] == '#' && chars[*i] == '{')
+        .map(|i| i + 1);
+
+    for open in opens {
+        // the expression can have its own braces and strings, eg: `#{ {"a": "}"}.a }`
+        let mut depth = 0;
+        let mut in_string = false;
+        // up to the position only, if it's not closed yet (the rest is not part of it)
+        let mut close = column;
+        let mut i = open;
+        while i < chars.len() {
+            match chars[i] {
+                '\\' if in_string => i += 1,
+                '"' => in_string = !in_string,
+                '{' if !in_string => depth += 1,
+                '}' if !in_string && depth == 0 => {
+                    close = i;
+                    break;
+                }
+                '}' if !in_string => depth -= 1,
+                _ => (),
+            }
+            i += 1;
+        }
+        if column <= close {
+            return Some((open, close));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippet_placeholders() {
+        assert_eq!(snippet_to_plain_text("let ${1:x} = $0;"), "let x = ;");
+        assert_eq!(snippet_to_plain_text("f(${1}, $2)$0"), "f(, )");
+        assert_eq!(
+            snippet_to_plain_text("match ${1:event} of\n  case ${2:pattern} => $0\nend"),
+            "match event of\n  case pattern => \nend"
+        );
+    }
+
+    #[test]
+    fn snippet_escapes() {
+        assert_eq!(snippet_to_plain_text("\"\\$\\{a\\}\\\\\""), "\"${a}\\\"");
+        assert_eq!(snippet_to_plain_text("${1:{\"a\": 1\\}}"), "{\"a\": 1}");
+        assert_eq!(
+            snippet_to_plain_text(&escape_snippet("\"#{ $x }\\\"")),
+            "\"#{ $x }\\\""
+        );
+    }
+
+    #[test]
+    fn snippet_nested_placeholders() {
+        assert_eq!(
+            snippet_to_plain_text("${1:a, ${2:b, ${3:c}}} }"),
+            "a, b, c }"
+        );
+        assert_eq!(snippet_to_plain_text("${1:f(${2})}$0"), "f()");
+    }
+
+    #[test]
+    fn markdown_code_fences() {
+        let markdown = "Formats a string.\n\n```tremor\nstring::format(\"{}\", 1)\n```\n";
+        assert_eq!(
+            to_plain_text(markdown),
+            "Formats a string.\n\nstring::format(\"{}\", 1)"
+        );
+        assert_eq!(to_plain_text("  ```\nx\n  ```"), "x");
+    }
+
+    #[test]
+    fn markdown_links() {
+        assert_eq!(
+            to_plain_text("See [the docs](https://docs.tremor.rs) and [this]."),
+            "See the docs (https://docs.tremor.rs) and [this]."
+        );
+        assert_eq!(to_plain_text("a [b] (c)"), "a [b] (c)");
+        assert_eq!(to_plain_text("a[0] and [x](y)"), "a[0] and x (y)");
     }
-    //file_dbg("get_token_return", &token.clone().unwrap_or_default());
-    token
 }
@@ -12,20 +12,78 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod aggregates;
 mod backend;
+mod balance;
+mod bench;
+mod binary;
+mod bindings;
+mod blocks;
+mod cache;
+mod cases;
+mod check;
+mod commands;
+mod completion;
+mod config;
+mod consts;
+#[cfg(feature = "dap")]
+mod dap;
+mod embedded;
+mod extract;
+mod flow;
+mod function_docs;
+mod fuzzy;
+mod imports;
+mod interpolation;
 mod language;
+mod lints;
+mod literals;
 mod lsp_utils;
+mod metadata;
+mod metrics;
+mod modules;
+mod params;
+mod parse_stats;
+mod patch;
+mod paths;
+mod pipeline;
+mod progress;
+mod project;
+mod regions;
+mod repl;
+#[cfg(feature = "runtime")]
+mod runtime;
+mod scratch;
+mod shapes;
+mod signatures;
+mod stdlib;
+mod symbols;
+#[cfg(feature = "otlp")]
+mod telemetry;
+mod test_files;
+mod watchdog;
+mod workspace;
 
 use backend::Backend;
-use clap::{App, Arg};
+use clap::{App, Arg, SubCommand};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use tower_lsp::{LspService, Server};
 
 #[tokio::main]
 async fn main() {
     backend::file_dbg("main", "main");
 
-    let matches = App::new(env!("CARGO_PKG_NAME"))
+    let long_version = format!(
+        "{} (tremor-script {})",
+        env!("CARGO_PKG_VERSION"),
+        backend::TREMOR_SCRIPT_VERSION
+    );
+
+    let app = App::new(env!("CARGO_PKG_NAME"))
         .version(env!("CARGO_PKG_VERSION"))
+        .long_version(long_version.as_str())
         .about(env!("CARGO_PKG_DESCRIPTION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .arg(
@@ -45,7 +103,65 @@ async fn main() {
                 .takes_value(true)
                 .default_value(""),
         )
-        .get_matches();
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Prints the diagnostics of tremor files, failing if there are errors")
+                .arg(
+                    Arg::with_name("files")
+                        .help("Files to check, or directories to check the tremor files in")
+                        .multiple(true)
+                        .required_unless("stdin"),
+                )
+                .arg(
+                    Arg::with_name("strict")
+                        .help("Report warnings as errors")
+                        .long("strict"),
+                )
+                .arg(
+                    Arg::with_name("stdin")
+                        .help("Check the source read from stdin, printing the diagnostics as json")
+                        .long("stdin")
+                        .conflicts_with("files"),
+                )
+                .arg(
+                    Arg::with_name("language")
+                        .help("Tremor language of the source read from stdin")
+                        .long("language")
+                        .takes_value(true)
+                        .requires("stdin")
+                        .possible_values(language::LANGUAGE_NAMES),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stdlib")
+                .about("Manages the local copy of the tremor stdlib, that goes on the TREMOR_PATH")
+                .subcommand(
+                    SubCommand::with_name("install").about("Downloads the stdlib, if it's missing"),
+                )
+                .subcommand(SubCommand::with_name("path").about("Prints where the stdlib goes"))
+                .subcommand(SubCommand::with_name("update").about("Downloads the stdlib again")),
+        )
+        .subcommand(
+            SubCommand::with_name("dump-docs")
+                .about("Prints the documentation of the language's modules and functions")
+                .arg(
+                    Arg::with_name("format")
+                        .help("Output format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["json"])
+                        .default_value("json"),
+                ),
+        );
+
+    #[cfg(feature = "dap")]
+    let app = app.arg(
+        Arg::with_name("dap")
+            .help("Serve the Debug Adapter Protocol instead of the Language Server Protocol")
+            .long("dap"),
+    );
+
+    let matches = app.get_matches();
 
     let language_name = matches
         .value_of("language")
@@ -58,26 +174,111 @@ async fn main() {
         .unwrap_or_else(|| unreachable!());
 
     if !path.is_empty() {
-        std::env::set_var(
-            "TREMOR_PATH",
-            match std::env::var("TREMOR_PATH") {
-                // append to existing path if it's already set
-                Ok(p) => format!("{}:{}", p, path),
-                Err(_) => path.to_string(),
+        // separated as in PATH, ie. by `:` (or `;` on windows)
+        for path in std::env::split_paths(path) {
+            append_tremor_path(&path);
+        }
+    }
+    // the stdlib goes last, so that the modules of the user can shadow it
+    if let Some(stdlib) = stdlib::installed() {
+        append_tremor_path(&stdlib);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("stdlib") {
+        match matches.subcommand_name() {
+            Some("path") => match stdlib::path() {
+                Some(path) => println!("{}", path.display()),
+                None => {
+                    eprintln!("Error: failed to find a directory for the stdlib");
+                    std::process::exit(1)
+                }
             },
-        );
+            #[cfg(feature = "stdlib")]
+            Some(command) => match stdlib::install(command == "update").await {
+                Ok(path) => println!("{}", path.display()),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1)
+                }
+            },
+            #[cfg(not(feature = "stdlib"))]
+            Some(_) => {
+                eprintln!("Error: built without the stdlib feature, so can't download it");
+                std::process::exit(1)
+            }
+            None => {
+                eprintln!("{}", matches.usage());
+                std::process::exit(1)
+            }
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("check") {
+        if matches.is_present("stdin") {
+            let language_name = matches
+                .value_of("language")
+                .unwrap_or(language::DEFAULT_LANGUAGE_NAME);
+            if !check::run_stdin(language_name, matches.is_present("strict")) {
+                std::process::exit(1)
+            }
+            return;
+        }
+        let files: Vec<std::path::PathBuf> = matches
+            .values_of("files")
+            .map(|files| files.map(std::path::PathBuf::from).collect())
+            .unwrap_or_default();
+        if !check::run(&files, matches.is_present("strict")) {
+            std::process::exit(1)
+        }
+        return;
     }
 
     match language::lookup(language_name) {
+        // json is the only format so far, so there is nothing to pick based on it
+        Some(language) if matches.subcommand_matches("dump-docs").is_some() => {
+            let uri = match std::env::current_dir()
+                .ok()
+                .and_then(|dir| tower_lsp::lsp_types::Url::from_directory_path(dir).ok())
+            {
+                Some(uri) => uri,
+                None => {
+                    eprintln!("Error: failed to get the current directory");
+                    std::process::exit(1)
+                }
+            };
+            let docs = modules::builtin_docs_json(language.as_ref(), &uri);
+            match serde_json::to_string_pretty(&docs) {
+                Ok(docs) => println!("{}", docs),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1)
+                }
+            }
+        }
+        #[cfg(feature = "dap")]
+        Some(language) if matches.is_present("dap") => {
+            if let Err(e) = dap::serve(language) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1)
+            }
+        }
         Some(language) => {
             let stdin = tokio::io::stdin();
             let stdout = tokio::io::stdout();
 
-            let (service, messages) = LspService::new(|client| Backend::new(client, language));
+            let shut_down = Arc::new(AtomicBool::new(false));
+            let backend_shut_down = shut_down.clone();
+            let (service, messages) =
+                LspService::new(|client| Backend::new(client, language, backend_shut_down));
             Server::new(stdin, stdout)
                 .interleave(messages)
                 .serve(service)
                 .await;
+            // exiting without a shutdown request first is an error, as per the protocol
+            if !shut_down.load(Ordering::SeqCst) {
+                std::process::exit(1)
+            }
         }
         None => {
             eprintln!("Error: unknown tremor language {}", language_name);
@@ -85,3 +286,10 @@ async fn main() {
         }
     }
 }
+
+// appends to TREMOR_PATH, keeping what is set already
+fn append_tremor_path(path: &Path) {
+    if !paths::append_env("TREMOR_PATH", path) {
+        eprintln!("Warning: failed to add {} to TREMOR_PATH", path.display());
+    }
+}
@@ -0,0 +1,198 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// documentation for the well-known event metadata (`$name`) fields
+
+use crate::blocks;
+use crate::language::{Token, TokenSpan};
+use crate::lsp_utils;
+use tower_lsp::lsp_types::*;
+
+pub struct Field {
+    pub name: &'static str,
+    pub description: &'static str,
+    // nested fields, with their descriptions
+    pub fields: &'static [(&'static str, &'static str)],
+}
+
+pub const FIELDS: &[Field] = &[
+    Field {
+        name: "correlation",
+        description: "Correlation id of the event. Carried over to the events that linked \
+                      transports produce in response, so they can be matched up.",
+        fields: &[],
+    },
+    Field {
+        name: "class",
+        description: "Class of the event, for the `grouper::bucket` operator to apply rate \
+                      limits by.",
+        fields: &[],
+    },
+    Field {
+        name: "rate",
+        description: "Events per second allowed for the class of the event, for the \
+                      `grouper::bucket` operator.",
+        fields: &[],
+    },
+    Field {
+        name: "dimensions",
+        description: "Further grouping of events within their class, for the \
+                      `grouper::bucket` operator.",
+        fields: &[],
+    },
+    Field {
+        name: "kafka",
+        description: "Set by the kafka onramp for events it receives, and read by the kafka \
+                      offramp for the messages it sends.",
+        fields: &[
+            ("key", "message key"),
+            ("headers", "message headers, as a record"),
+            ("topic", "topic the message was received from"),
+            ("partition", "partition the message was received from"),
+            ("offset", "offset of the message in the partition"),
+        ],
+    },
+    Field {
+        name: "elastic",
+        description: "Read by the elastic offramp, for where and how to index the event.",
+        fields: &[
+            ("_index", "index to write into"),
+            ("_type", "document type"),
+            ("_id", "document id"),
+            ("pipeline", "ingest pipeline to run the document through"),
+            ("action", "bulk action, eg: `index`, `update` or `delete`"),
+        ],
+    },
+    Field {
+        name: "request",
+        description: "Set by the rest onramp, for the http request the event came from.",
+        fields: &[
+            ("method", "http method"),
+            (
+                "url",
+                "request url, as a record of `scheme`, `host`, `port`, `path` and `query`",
+            ),
+            ("headers", "request headers, as a record"),
+        ],
+    },
+    Field {
+        name: "response",
+        description: "Read by the rest onramp (in linked mode) for the http response to send, \
+                      and set by the rest offramp for the responses it receives.",
+        fields: &[
+            ("status", "http status code"),
+            ("headers", "response headers, as a record"),
+        ],
+    },
+];
+
+fn find(name: &str) -> Option<&'static Field> {
+    FIELDS.iter().find(|field| field.name == name)
+}
+
+pub fn document(field: &Field) -> String {
+    let mut doc = format!("`${}`\n\n{}", field.name, field.description);
+    if !field.fields.is_empty() {
+        let fields: Vec<String> = field
+            .fields
+            .iter()
+            .map(|(name, description)| format!("* `{}`: {}", name, description))
+            .collect();
+        doc.push_str(&format!("\n\n{}", fields.join("\n")));
+    }
+    doc
+}
+
+// markdown documentation for the metadata name at the position (eg: `$kafka`), or a field
+// of it (eg: `$kafka.topic`), along with its range
+pub fn hover(text: &str, tokens: &[TokenSpan], position: Position) -> Option<(String, Range)> {
+    let tokens = blocks::significant(tokens);
+    let index = tokens.iter().position(|t| {
+        let range = lsp_utils::token_range(text, t);
+        range.start <= position && position < range.end
+    })?;
+    let name = |i: usize| match &tokens.get(i)?.value {
+        Token::Ident(name, _) => Some(name.to_string()),
+        _ => None,
+    };
+    let range = lsp_utils::token_range(text, tokens[index]);
+
+    match tokens[index].value {
+        // all of it, for the bare `$`
+        Token::Dollar if name(index + 1).is_none() => {
+            let doc: Vec<String> = FIELDS.iter().map(document).collect();
+            Some((
+                format!("Event metadata\n\n---\n\n{}", doc.join("\n\n---\n\n")),
+                range,
+            ))
+        }
+        Token::Ident(_, _) if index >= 1 && tokens[index - 1].value == Token::Dollar => {
+            let field = find(&name(index)?)?;
+            Some((document(field), range))
+        }
+        // `$name.field`
+        Token::Ident(_, _)
+            if index >= 3
+                && tokens[index - 1].value == Token::Dot
+                && tokens[index - 3].value == Token::Dollar =>
+        {
+            let field = find(&name(index - 2)?)?;
+            let field_name = name(index)?;
+            let (_, description) = field.fields.iter().find(|(f, _)| *f == field_name)?;
+            Some((
+                format!("`${}.{}`: {}", field.name, field_name, description),
+                range,
+            ))
+        }
+        _ => None,
+    }
+}
+
+// what is being typed at the completion position: a metadata name (after `$`), or a field of
+// one (after `$kafka.`)
+pub enum Slot {
+    Name,
+    Field(&'static Field),
+}
+
+// the metadata slot the position is in, if any, along with what's typed of the name so far
+pub fn slot(text: &str, tokens: &[TokenSpan], position: Position) -> Option<(Slot, String)> {
+    let tokens = blocks::significant(tokens);
+    let location = lsp_utils::to_language_location_in(text, &position);
+    let location = (location.line(), location.column());
+    let before: Vec<&Token> = tokens
+        .iter()
+        .filter(|t| (t.span.end.line(), t.span.end.column()) <= location)
+        .map(|t| &t.value)
+        .collect();
+    // the name being typed ends at the position
+    let (prefix, before) = match tokens
+        .iter()
+        .rfind(|t| (t.span.end.line(), t.span.end.column()) <= location)
+    {
+        Some(t) if (t.span.end.line(), t.span.end.column()) == location => match &t.value {
+            Token::Ident(name, _) => (name.to_string(), &before[..before.len() - 1]),
+            _ => (String::new(), &before[..]),
+        },
+        _ => (String::new(), &before[..]),
+    };
+
+    match before {
+        [.., Token::Dollar] => Some((Slot::Name, prefix)),
+        [.., Token::Dollar, Token::Ident(name, _), Token::Dot] => {
+            Some((Slot::Field(find(name)?), prefix))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// request counts and durations of the server, for the `tremor.metrics` command (and the
+// request traces, with the otlp feature)
+
+#[cfg(feature = "otlp")]
+use crate::telemetry;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+use tower_lsp::lsp_types::Url;
+
+// upper bounds of the duration buckets, in milliseconds
+const BUCKETS: &[f64] = &[1.0, 5.0, 10.0, 50.0, 100.0, 500.0, 1000.0, 5000.0];
+
+const PREFIX: &str = "tremor_language_server";
+
+#[derive(Default)]
+struct Histogram {
+    // per bucket, with the last one for durations above all bounds
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+    errors: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration, failed: bool) {
+        let millis = duration.as_secs_f64() * 1000.0;
+        if self.counts.is_empty() {
+            self.counts = vec![0; BUCKETS.len() + 1];
+        }
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| millis <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.sum += millis;
+        self.count += 1;
+        if failed {
+            self.errors += 1;
+        }
+    }
+
+    // (bound, count of durations up to it), as prometheus has them
+    fn cumulative(&self) -> Vec<(String, u64)> {
+        let bounds = BUCKETS
+            .iter()
+            .map(|bound| bound.to_string())
+            .chain(std::iter::once("+Inf".to_string()));
+        let mut total = 0;
+        bounds
+            .zip(self.counts.iter().chain(std::iter::repeat(&0)))
+            .map(|(bound, count)| {
+                total += count;
+                (bound, total)
+            })
+            .collect()
+    }
+
+    fn to_json(&self) -> Value {
+        let buckets: Map<String, Value> = self
+            .cumulative()
+            .into_iter()
+            .map(|(bound, count)| (bound, json!(count)))
+            .collect();
+        json!({
+            "count": self.count,
+            "errors": self.errors,
+            "sumMs": self.sum,
+            "meanMs": if self.count > 0 { self.sum / self.count as f64 } else { 0.0 },
+            "buckets": buckets,
+        })
+    }
+
+    fn to_prometheus(&self, name: &str, labels: &str, lines: &mut Vec<String>) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        for (bound, count) in self.cumulative() {
+            lines.push(format!(
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, separator, bound, count
+            ));
+        }
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels)
+        };
+        lines.push(format!("{}_sum{} {}", name, labels, self.sum));
+        lines.push(format!("{}_count{} {}", name, labels, self.count));
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    // per request method (or command)
+    requests: Mutex<BTreeMap<String, Histogram>>,
+    // of a document, parsing included
+    diagnostics: Mutex<Histogram>,
+    #[cfg(feature = "otlp")]
+    exporter: Option<telemetry::Exporter>,
+}
+
+// records the duration of a request once dropped, ie. however the handler returns
+pub struct Timer<'a> {
+    metrics: &'a Metrics,
+    method: String,
+    // the request is about, for the trace
+    #[cfg_attr(not(feature = "otlp"), allow(dead_code))]
+    document: Option<Url>,
+    failed: bool,
+    start: Instant,
+    #[cfg_attr(not(feature = "otlp"), allow(dead_code))]
+    started: SystemTime,
+}
+
+impl Timer<'_> {
+    pub fn document(mut self, uri: &Url) -> Self {
+        self.document = Some(uri.clone());
+        self
+    }
+
+    // marks the request as answered with an error
+    pub fn fail(&mut self) {
+        self.failed = true;
+    }
+
+    // the outcome of work done in catch_panic, marking the request as failed if it panicked
+    pub fn caught<T>(&mut self, outcome: Option<T>) -> Option<T> {
+        if outcome.is_none() {
+            self.fail();
+        }
+        outcome
+    }
+}
+
+impl Drop for Timer<'_> {
+    fn drop(&mut self) {
+        let duration = self.start.elapsed();
+        #[cfg(feature = "otlp")]
+        {
+            if let Some(exporter) = &self.metrics.exporter {
+                exporter.record(telemetry::Span {
+                    method: self.method.clone(),
+                    document: self.document.as_ref().map(Url::to_string),
+                    start: self.started,
+                    duration,
+                    failed: self.failed,
+                });
+            }
+        }
+        lock(&self.metrics.requests)
+            .entry(std::mem::take(&mut self.method))
+            .or_default()
+            .observe(duration, self.failed);
+    }
+}
+
+impl Metrics {
+    // with request traces going to the collector configured in the environment, if any
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "otlp")]
+            exporter: telemetry::Exporter::from_env(),
+            ..Self::default()
+        }
+    }
+
+    pub fn request(&self, method: &str) -> Timer {
+        Timer {
+            metrics: self,
+            method: method.to_string(),
+            document: None,
+            failed: false,
+            start: Instant::now(),
+            started: SystemTime::now(),
+        }
+    }
+
+    pub fn diagnostics_took(&self, duration: Duration) {
+        lock(&self.diagnostics).observe(duration, false);
+    }
+
+    // sends the traces not sent yet
+    pub async fn flush(&self) {
+        #[cfg(feature = "otlp")]
+        {
+            if let Some(exporter) = &self.exporter {
+                exporter.flush().await;
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let requests: Map<String, Value> = lock(&self.requests)
+            .iter()
+            .map(|(method, histogram)| (method.clone(), histogram.to_json()))
+            .collect();
+        let diagnostics = lock(&self.diagnostics).to_json();
+        json!({ "requests": requests, "diagnostics": diagnostics })
+    }
+
+    // in the prometheus text format, with the given counters (name, help, value) added
+    pub fn to_prometheus(&self, counters: &[(&str, &str, u64)]) -> String {
+        let mut lines = Vec::new();
+
+        let name = format!("{}_request_duration_milliseconds", PREFIX);
+        lines.push(format!("# HELP {} Duration of LSP requests", name));
+        lines.push(format!("# TYPE {} histogram", name));
+        for (method, histogram) in lock(&self.requests).iter() {
+            let labels = format!("method=\"{}\"", method.replace('"', "\\\""));
+            histogram.to_prometheus(&name, &labels, &mut lines);
+        }
+
+        let name = format!("{}_diagnostics_duration_milliseconds", PREFIX);
+        lines.push(format!(
+            "# HELP {} Duration of computing the diagnostics of a document",
+            name
+        ));
+        lines.push(format!("# TYPE {} histogram", name));
+        lock(&self.diagnostics).to_prometheus(&name, "", &mut lines);
+
+        for (counter, help, value) in counters {
+            let name = format!("{}_{}", PREFIX, counter);
+            lines.push(format!("# HELP {} {}", name, help));
+            lines.push(format!("# TYPE {} counter", name));
+            lines.push(format!("{} {}", name, value));
+        }
+
+        lines.push(String::new());
+        lines.join("\n")
+    }
+}
+
+// the guarded value, even if a panic while holding the lock poisoned it: a half-recorded
+// request is better than no metrics (or traces) for the rest of the session
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
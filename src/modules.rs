@@ -0,0 +1,251 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::language::{Language, TokenSpan};
+use crate::paths;
+use crate::scratch;
+use crate::symbols;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tower_lsp::lsp_types::{SymbolKind, Url};
+
+pub const MODULE_FILE_EXTENSION: &str = "tremor";
+
+// module directories configured for each workspace root (in its project manifest).
+// global, since the languages need them when parsing and only get the document uri.
+static ROOT_PATHS: RwLock<Vec<(PathBuf, Vec<PathBuf>)>> = RwLock::new(Vec::new());
+
+pub fn set_root_paths(root_paths: Vec<(PathBuf, Vec<PathBuf>)>) {
+    let root_paths: Vec<(PathBuf, Vec<PathBuf>)> = root_paths
+        .into_iter()
+        .map(|(root, dirs)| {
+            let dirs = dirs.iter().map(|dir| paths::normalize(dir)).collect();
+            (paths::normalize(&root), dirs)
+        })
+        .collect();
+    match ROOT_PATHS.write() {
+        Ok(mut guard) => *guard = root_paths,
+        Err(poisoned) => *poisoned.into_inner() = root_paths,
+    }
+}
+
+// module directories of the (innermost) workspace root that the document is in. scratchpads
+// are in none, so they get the ones of the first root.
+fn root_paths(uri: &Url) -> Vec<PathBuf> {
+    let root_paths = match ROOT_PATHS.read() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    if scratch::is_scratch(uri) {
+        return root_paths
+            .first()
+            .map(|(_, paths)| paths.clone())
+            .unwrap_or_default();
+    }
+    let path = match paths::to_path(uri) {
+        Some(path) => path,
+        None => return vec![],
+    };
+    root_paths
+        .iter()
+        .filter(|(root, _)| path.starts_with(root))
+        .max_by_key(|(root, _)| root.components().count())
+        .map(|(_, paths)| paths.clone())
+        .unwrap_or_default()
+}
+
+// module directory relative to the document, as the languages use it during parsing
+pub fn document_search_path(uri: &Url) -> Option<PathBuf> {
+    paths::to_path(uri).and_then(|path| path.ancestors().nth(2).map(Path::to_path_buf))
+}
+
+// directories to look up modules in on top of TREMOR_PATH (which the languages already
+// take from the environment), in order of precedence
+pub fn document_search_paths(uri: &Url) -> Vec<PathBuf> {
+    let mut paths = root_paths(uri);
+    paths.extend(document_search_path(uri));
+    paths
+}
+
+// directories where modules are looked up, in order of precedence
+pub fn search_paths(uri: &Url) -> Vec<PathBuf> {
+    labeled_search_paths(uri)
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect()
+}
+
+// search paths along with where they come from, eg: `TREMOR_PATH`
+pub fn labeled_search_paths(uri: &Url) -> Vec<(PathBuf, &'static str)> {
+    let mut labeled: Vec<(PathBuf, &str)> = paths::split_env("TREMOR_PATH")
+        .into_iter()
+        .map(|path| (path, "TREMOR_PATH"))
+        .collect();
+    labeled.extend(
+        root_paths(uri)
+            .into_iter()
+            .map(|path| (path, "project path")),
+    );
+    labeled.extend(document_search_path(uri).map(|path| (path, "relative to the document")));
+    labeled
+}
+
+// file for a module path like `foo::bar`, relative to the search paths
+pub fn module_file(module_path: &[String]) -> PathBuf {
+    module_path
+        .iter()
+        .collect::<PathBuf>()
+        .with_extension(MODULE_FILE_EXTENSION)
+}
+
+// file for a module path like `foo::bar` (ie. `foo/bar.tremor` under one of the search paths)
+pub fn resolve(uri: &Url, module_path: &[String]) -> Option<PathBuf> {
+    let relative = module_file(module_path);
+
+    search_paths(uri)
+        .into_iter()
+        .map(|dir| dir.join(&relative))
+        .find(|path| path.is_file())
+}
+
+// module path by which the document can use the module file (the reverse of resolve()).
+// None if the file is not under any of the document's search paths.
+pub fn module_path(uri: &Url, module_file: &Path) -> Option<Vec<String>> {
+    if module_file.extension().and_then(|e| e.to_str()) != Some(MODULE_FILE_EXTENSION) {
+        return None;
+    }
+    let module_file = paths::normalize(module_file);
+    let relative = search_paths(uri)
+        .into_iter()
+        .find_map(|dir| module_file.strip_prefix(dir).ok().map(Path::to_path_buf))?;
+    relative
+        .with_extension("")
+        .iter()
+        .map(|part| part.to_str().map(String::from))
+        .collect()
+}
+
+// full markdown documentation for a builtin module (all of its functions) or a single
+// function. with no name, lists the modules. None if there's no such module or function.
+pub fn builtin_docs(language: &dyn Language, uri: &Url, name: &str) -> Option<String> {
+    if name.is_empty() {
+        let modules: Vec<String> = language
+            .modules(uri)
+            .iter()
+            .map(|module_name| format!("* `{}`", module_name))
+            .collect();
+        return Some(format!("# Modules\n\n{}", modules.join("\n")));
+    }
+    if let Some(doc) = language.function_doc(uri, name) {
+        return Some(doc.to_string());
+    }
+
+    let functions: Vec<String> = language
+        .functions(uri, name)
+        .iter()
+        .filter_map(|function_name| {
+            language
+                .function_doc(uri, &format!("{}::{}", name, function_name))
+                .map(ToString::to_string)
+        })
+        .collect();
+    if functions.is_empty() {
+        return None;
+    }
+    Some(format!("# {}\n\n{}", name, functions.join("\n\n---\n\n")))
+}
+
+// all the builtin modules and their functions, for other tools to use
+pub fn builtin_docs_json(language: &dyn Language, uri: &Url) -> Value {
+    let modules: Vec<Value> = language
+        .modules(uri)
+        .iter()
+        .map(|module_name| {
+            let functions: Vec<Value> = language
+                .functions(uri, module_name)
+                .iter()
+                .map(|function_name| {
+                    let full_name = format!("{}::{}", module_name, function_name);
+                    match language.function_doc(uri, &full_name) {
+                        Some(doc) => json!({
+                            "name": function_name,
+                            "fullName": full_name,
+                            "signature": doc.signature.to_string(),
+                            "args": doc.signature.args,
+                            "result": doc.signature.result,
+                            "description": doc.description,
+                        }),
+                        None => json!({ "name": function_name, "fullName": full_name }),
+                    }
+                })
+                .collect();
+            json!({ "name": module_name, "functions": functions })
+        })
+        .collect();
+
+    json!({ "language": language.name(), "modules": modules })
+}
+
+// markdown summary of a builtin module, listing its functions. None if it's not one.
+pub fn builtin_summary(language: &dyn Language, uri: &Url, module_name: &str) -> Option<String> {
+    let functions: Vec<String> = language
+        .functions(uri, module_name)
+        .iter()
+        .map(|function_name| {
+            let full_name = format!("{}::{}", module_name, function_name);
+            match language.function_doc(uri, &full_name) {
+                Some(doc) => format!("* `{}`", doc.signature),
+                None => format!("* `{}`", full_name),
+            }
+        })
+        .collect();
+    if functions.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "Module `{}`, with {} functions:\n\n{}",
+        module_name,
+        functions.len(),
+        functions.join("\n")
+    ))
+}
+
+// markdown summary of a module file: its leading comment, and the functions and constants
+// it defines
+pub fn file_summary(module_name: &str, text: &str, tokens: &[TokenSpan]) -> String {
+    let header: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .take_while(|line| line.starts_with('#'))
+        .map(|line| line.trim_start_matches('#').trim())
+        .collect();
+
+    let definitions: Vec<String> = symbols::collect(text, tokens)
+        .iter()
+        .filter(|symbol| matches!(symbol.kind, SymbolKind::Function | SymbolKind::Constant))
+        .filter_map(|symbol| text.lines().nth(symbol.range.start.line as usize))
+        .map(|line| format!("* `{}`", line.trim().trim_end_matches(';')))
+        .collect();
+
+    let mut summary = format!("Module `{}`", module_name);
+    if !header.is_empty() {
+        summary.push_str(&format!("\n\n{}", header.join("\n")));
+    }
+    if !definitions.is_empty() {
+        summary.push_str(&format!("\n\nDefines:\n\n{}", definitions.join("\n")));
+    }
+    summary
+}
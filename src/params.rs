@@ -0,0 +1,201 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// parameters of `define <kind> window|operator <name> with <params> end` statements (trickle)
+
+use crate::blocks;
+use crate::language::{Token, TokenSpan};
+use crate::lsp_utils;
+use crate::pipeline::Name;
+use tower_lsp::lsp_types::*;
+
+// a window or operator kind, with its parameters and what they do
+pub struct Kind {
+    pub name: &'static str,
+    pub params: &'static [(&'static str, &'static str)],
+}
+
+pub const WINDOWS: &[Kind] = &[Kind {
+    name: "tumbling",
+    params: &[
+        (
+            "interval",
+            "Length of the window in nanoseconds, for time based windows",
+        ),
+        (
+            "size",
+            "Number of events in the window, for size based windows",
+        ),
+        (
+            "eviction_period",
+            "Nanoseconds after which groups without events are evicted",
+        ),
+        ("max_groups", "Maximum number of groups kept for the window"),
+    ],
+}];
+
+pub const OPERATORS: &[Kind] = &[
+    Kind {
+        name: "generic::batch",
+        params: &[
+            ("count", "Number of events to put into a batch"),
+            (
+                "timeout",
+                "Nanoseconds after which an incomplete batch is sent anyway",
+            ),
+        ],
+    },
+    Kind {
+        name: "qos::backpressure",
+        params: &[
+            (
+                "timeout",
+                "Milliseconds an event can take downstream before backing off",
+            ),
+            (
+                "steps",
+                "Backoff steps in milliseconds, eg: `[50, 100, 250, 500]`",
+            ),
+        ],
+    },
+    Kind {
+        name: "qos::percentile",
+        params: &[
+            (
+                "timeout",
+                "Milliseconds an event can take downstream before the percentile drops",
+            ),
+            (
+                "step",
+                "How much the percentile changes on timeouts and successes",
+            ),
+        ],
+    },
+    Kind {
+        name: "qos::roundrobin",
+        params: &[("outputs", "Output ports to distribute the events over")],
+    },
+    Kind {
+        name: "debug::history",
+        params: &[
+            ("op", "Name of the operation recorded in the history"),
+            ("name", "Metadata field to record the history in"),
+        ],
+    },
+    Kind {
+        name: "generic::counter",
+        params: &[],
+    },
+    Kind {
+        name: "grouper::bucket",
+        params: &[],
+    },
+    Kind {
+        name: "passthrough",
+        params: &[],
+    },
+];
+
+// a define statement for a window or operator kind that we know the parameters of
+pub struct Definition {
+    pub kind: &'static Kind,
+    // parameter names as written
+    pub params: Vec<Name>,
+    // indices of the `with` and the `,`s that parameter names follow
+    separators: Vec<usize>,
+}
+
+fn definition(text: &str, tokens: &[&TokenSpan], define: usize) -> Option<Definition> {
+    // the kind is a (module) path, eg: `qos::backpressure`
+    let keyword = (define + 1..tokens.len())
+        .take_while(|k| matches!(tokens[*k].value, Token::Ident(_, _) | Token::ColonColon))
+        .last()?
+        + 1;
+    let kinds = match tokens.get(keyword)?.value {
+        Token::Window => WINDOWS,
+        Token::Operator => OPERATORS,
+        _ => return None,
+    };
+    let kind_name: String = tokens[define + 1..keyword]
+        .iter()
+        .map(|t| t.value.to_string())
+        .collect();
+    let kind = kinds.iter().find(|kind| kind.name == kind_name)?;
+    let with = keyword + 2;
+    if tokens.get(with)?.value != Token::With {
+        return None;
+    }
+
+    let mut params = Vec::new();
+    let mut separators = vec![with];
+    let mut depth = 0;
+    for i in with + 1..tokens.len() {
+        match &tokens[i].value {
+            v if blocks::opens_expression(v) => depth += 1,
+            Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+            Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+            Token::End if depth > 0 => depth -= 1,
+            // the window script, or the end of the statement
+            Token::End | Token::Script | Token::Semi if depth == 0 => break,
+            Token::Comma if depth == 0 => separators.push(i),
+            Token::Ident(value, _)
+                if depth == 0
+                    && separators.last() == Some(&(i - 1))
+                    && tokens.get(i + 1).map(|t| &t.value) == Some(&Token::Equal) =>
+            {
+                params.push(Name {
+                    value: value.to_string(),
+                    range: lsp_utils::token_range(text, tokens[i]),
+                });
+            }
+            _ => (),
+        }
+    }
+
+    Some(Definition {
+        kind,
+        params,
+        separators,
+    })
+}
+
+pub fn definitions(text: &str, tokens: &[TokenSpan]) -> Vec<Definition> {
+    let tokens = blocks::significant(tokens);
+    tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| t.value == Token::Define)
+        .filter_map(|(i, _)| definition(text, &tokens, i))
+        .collect()
+}
+
+// the definition that a parameter name is expected at the position of, if any
+pub fn slot(text: &str, tokens: &[TokenSpan], position: Position) -> Option<Definition> {
+    let significant = blocks::significant(tokens);
+    let location = lsp_utils::to_language_location_in(text, &position);
+    let location = (location.line(), location.column());
+    // the last token before the position, not counting the name being typed
+    let previous = significant.iter().rposition(|t| {
+        (t.span.end.line(), t.span.end.column()) <= location
+            && !(matches!(t.value, Token::Ident(_, _))
+                && (t.span.end.line(), t.span.end.column()) == location)
+    })?;
+
+    significant
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| t.value == Token::Define)
+        .filter_map(|(i, _)| definition(text, &significant, i))
+        .find(|definition| definition.separators.contains(&previous))
+}
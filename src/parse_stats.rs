@@ -0,0 +1,72 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// the `tremor/parseStats` notification, sent along with the diagnostics of a document (with
+// the parseStats setting on), for telling whether slow feedback comes from parsing or from
+// getting the diagnostics to the editor
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tower_lsp::lsp_types::notification::Notification;
+use tower_lsp::lsp_types::Url;
+
+pub enum ParseStats {}
+
+impl Notification for ParseStats {
+    type Params = ParseStatsParams;
+    const METHOD: &'static str = "tremor/parseStats";
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParseStatsParams {
+    pub uri: Url,
+    pub version: Option<i64>,
+    // computing all the diagnostics, parsing included
+    pub diagnostics_ms: f64,
+    // parsing alone, None if the language has no syntax tree to give
+    pub parse_ms: Option<f64>,
+    pub ast_nodes: Option<usize>,
+    pub tokens: Option<usize>,
+    // when the diagnostics were published, in milliseconds since the unix epoch, for the
+    // client to compare with when they arrived
+    pub published_at: u64,
+}
+
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// nodes of the syntax tree (as json), ie. the objects in it but for the node metadata
+pub fn node_count(ast: &Value) -> usize {
+    match ast {
+        Value::Object(fields) => {
+            1 + fields
+                .iter()
+                .filter(|(key, _)| key.as_str() != "node_meta")
+                .map(|(_, value)| node_count(value))
+                .sum::<usize>()
+        }
+        Value::Array(values) => values.iter().map(node_count).sum(),
+        _ => 0,
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// `patch <target> of <operation>; ... end` expressions
+
+use crate::blocks;
+use crate::language::{Token, TokenSpan};
+use crate::lsp_utils;
+use tower_lsp::lsp_types::*;
+
+// operation keywords, with what they do
+pub const OPERATIONS: &[(&str, &str)] = &[
+    (
+        "insert",
+        "`insert \"key\" => value`: adds the field, failing if it exists",
+    ),
+    (
+        "upsert",
+        "`upsert \"key\" => value`: adds or replaces the field",
+    ),
+    (
+        "update",
+        "`update \"key\" => value`: replaces the field, failing if it's missing",
+    ),
+    ("erase", "`erase \"key\"`: removes the field"),
+    (
+        "merge",
+        "`merge => value` or `merge \"key\" => value`: merges the record in",
+    ),
+    (
+        "default",
+        "`default => value` or `default \"key\" => value`: adds missing fields",
+    ),
+    ("copy", "`copy \"from\" => \"to\"`: copies a field"),
+    ("move", "`move \"from\" => \"to\"`: renames a field"),
+];
+
+// a single operation of a patch, eg: `insert "key" => value`
+#[derive(Debug, Clone)]
+pub struct Operation {
+    // covers the first token
+    pub keyword_range: Range,
+    pub keyword: String,
+    pub has_key: bool,
+    pub has_arrow: bool,
+    pub has_value: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Patch {
+    // target expression, as written
+    pub target: String,
+    pub operations: Vec<Operation>,
+}
+
+// what the grammar expects at a position within a patch
+#[derive(Debug, Clone, PartialEq)]
+pub enum Slot {
+    Operation,
+    // a field of the patch target (as written)
+    Key(String),
+}
+
+// index of the `of` and index ranges of the operations (without the separating `;`) of the
+// patch starting at the index
+fn parts(tokens: &[&TokenSpan], patch: usize) -> (usize, Vec<(usize, usize)>) {
+    let mut depth = 0;
+    let mut of = None;
+    let mut operations = Vec::new();
+    let mut start = patch + 1;
+    for (i, t) in tokens.iter().enumerate().skip(patch + 1) {
+        match &t.value {
+            // `merge` within the patch is an operation, rather than an expression
+            Token::Merge if depth == 0 && of.is_some() => (),
+            v if blocks::opens_expression(v) => depth += 1,
+            Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+            Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+            Token::End if depth > 0 => depth -= 1,
+            Token::Of if depth == 0 && of.is_none() => {
+                of = Some(i);
+                start = i + 1;
+            }
+            Token::Semi if depth == 0 && of.is_some() => {
+                operations.push((start, i));
+                start = i + 1;
+            }
+            Token::End if depth == 0 => {
+                operations.push((start, i));
+                return (of.unwrap_or(i), operations);
+            }
+            _ => (),
+        }
+    }
+    operations.push((start, tokens.len()));
+    (of.unwrap_or_else(|| tokens.len()), operations)
+}
+
+pub fn patches(text: &str, tokens: &[TokenSpan]) -> Vec<Patch> {
+    let tokens = blocks::significant(tokens);
+    let token_text = |from: usize, to: usize| {
+        if from >= to {
+            return String::new();
+        }
+        let from = lsp_utils::to_offset(text, &tokens[from].span.start);
+        let to = lsp_utils::to_offset(text, &tokens[to - 1].span.end);
+        text.get(from..to).unwrap_or_default().trim().to_string()
+    };
+
+    let mut patches = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        if t.value != Token::Patch {
+            continue;
+        }
+        let (of, operations) = parts(&tokens, i);
+        let operations = operations
+            .into_iter()
+            // eg: a trailing `;` before the `end`
+            .filter(|(start, end)| start < end)
+            .map(|(start, end)| {
+                let arrow = (start..end).find(|k| tokens[*k].value == Token::EqArrow);
+                Operation {
+                    keyword_range: lsp_utils::token_range(text, tokens[start]),
+                    keyword: tokens[start].value.to_string(),
+                    has_key: arrow.unwrap_or(end) > start + 1,
+                    has_arrow: arrow.is_some(),
+                    has_value: arrow.map_or(false, |arrow| arrow + 1 < end),
+                }
+            })
+            .collect();
+        patches.push(Patch {
+            target: token_text(i + 1, of),
+            operations,
+        });
+    }
+    patches
+}
+
+// what is expected at the position, if it's within the operations of a patch
+pub fn slot(text: &str, tokens: &[TokenSpan], position: Position) -> Option<Slot> {
+    let tokens = blocks::significant(tokens);
+    let location = lsp_utils::to_language_location_in(text, &position);
+    let location = (location.line(), location.column());
+    let is_before = |t: &TokenSpan| (t.span.end.line(), t.span.end.column()) <= location;
+    // the name being typed is not part of the context
+    let is_typed = |t: &TokenSpan| {
+        matches!(t.value, Token::Ident(_, _))
+            && (t.span.end.line(), t.span.end.column()) == location
+    };
+
+    // the operation around the position, in the innermost patch
+    let (target, start, end) = tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| t.value == Token::Patch && is_before(t))
+        .filter_map(|(i, _)| {
+            let (of, operations) = parts(&tokens, i);
+            let (start, end) = operations.into_iter().find(|(start, end)| {
+                *start > of
+                    && is_before(tokens[start - 1])
+                    && tokens.get(*end).map_or(true, |t| !is_before(t))
+            })?;
+            let from = lsp_utils::to_offset(text, &tokens[i + 1].span.start);
+            let to = lsp_utils::to_offset(text, &tokens[of - 1].span.end);
+            let target = text.get(from..to).unwrap_or_default().trim().to_string();
+            Some((target, start, end))
+        })
+        .last()?;
+
+    let written: Vec<String> = tokens[start..end]
+        .iter()
+        .filter(|t| is_before(t) && !is_typed(t))
+        .map(|t| t.value.to_string())
+        .collect();
+    match written.as_slice() {
+        [] => Some(Slot::Operation),
+        [keyword] if OPERATIONS.iter().any(|(k, _)| *k == *keyword) => Some(Slot::Key(target)),
+        _ => None,
+    }
+}
+
+// names of the fields of the target that the document refers to, eg: `event.field`, or
+// keys in other patches of it
+pub fn known_fields(tokens: &[TokenSpan], target: &str) -> Vec<String> {
+    let significant = blocks::significant(tokens);
+
+    let mut fields: Vec<String> = significant
+        .windows(3)
+        .filter_map(|w| match (&w[0].value, &w[1].value, &w[2].value) {
+            (Token::Ident(name, _), Token::Dot, Token::Ident(field, _)) if name == target => {
+                Some(field.to_string())
+            }
+            _ => None,
+        })
+        .collect();
+
+    for (i, t) in significant.iter().enumerate() {
+        if t.value != Token::Patch {
+            continue;
+        }
+        let (of, operations) = parts(&significant, i);
+        if i + 2 != of
+            || !matches!(&significant[i + 1].value, Token::Ident(name, _) if name == target)
+        {
+            continue;
+        }
+        for (start, _) in operations {
+            // `insert "key"`
+            if let (Some(Token::DQuote), Some(Token::StringLiteral(key))) = (
+                significant.get(start + 1).map(|t| &t.value),
+                significant.get(start + 2).map(|t| &t.value),
+            ) {
+                fields.push(key.to_string());
+            }
+        }
+    }
+
+    fields.sort();
+    fields.dedup();
+    fields
+}
@@ -0,0 +1,214 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// conversions between file uris and paths, normalized so that paths of the same file compare
+// equal however they were written (eg. `file:///c%3A/...` from clients vs `C:\...` in
+// TREMOR_PATH, or `\\?\C:\...` from canonicalize on windows). paths stay as the os has them
+// (no lossy conversion to strings), so non-utf8 ones work too.
+
+use std::env;
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf, Prefix};
+use tower_lsp::lsp_types::Url;
+
+// upper case drive letters, and verbatim prefixes (`\\?\C:`, `\\?\UNC\server\share`) in
+// their usual form. a no-op on unix.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => match prefix.kind() {
+                Prefix::Disk(drive) | Prefix::VerbatimDisk(drive) => {
+                    normalized.push(format!("{}:", (drive as char).to_ascii_uppercase()))
+                }
+                Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => {
+                    let mut unc = OsString::from(r"\\");
+                    unc.push(server);
+                    unc.push(r"\");
+                    unc.push(share);
+                    normalized.push(unc);
+                }
+                _ => normalized.push(component.as_os_str()),
+            },
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+// path of a file uri, with percent-encoded characters (eg: spaces) decoded
+pub fn to_path(uri: &Url) -> Option<PathBuf> {
+    uri.to_file_path().ok().map(|path| normalize(&path))
+}
+
+pub fn to_uri(path: &Path) -> Option<Url> {
+    Url::from_file_path(normalize(path)).ok()
+}
+
+// the paths of the variable (eg: TREMOR_PATH), with the os separator (`;` on windows)
+pub fn split_env(name: &str) -> Vec<PathBuf> {
+    match env::var_os(name) {
+        Some(value) => env::split_paths(&value)
+            .filter(|path| !path.as_os_str().is_empty())
+            .map(|path| normalize(&path))
+            .collect(),
+        None => vec![],
+    }
+}
+
+// adds the path at the end of the variable. false if it can't be added (eg: for paths with
+// the separator in them).
+pub fn append_env(name: &str, path: &Path) -> bool {
+    let mut paths = split_env(name);
+    paths.push(normalize(path));
+    match env::join_paths(paths) {
+        Ok(value) => {
+            env::set_var(name, value);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn to_path_decodes_spaces_and_non_ascii() {
+        let uri = Url::parse("file:///tmp/a%20b/%C3%A9t%C3%A9.tremor").ok();
+        let path = uri.as_ref().and_then(to_path);
+        assert_eq!(path, Some(PathBuf::from("/tmp/a b/été.tremor")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn to_uri_encodes_spaces_and_non_ascii() {
+        let uri = to_uri(Path::new("/tmp/a b/été.tremor"));
+        assert_eq!(
+            uri.as_ref().map(Url::as_str),
+            Some("file:///tmp/a%20b/%C3%A9t%C3%A9.tremor")
+        );
+        assert_eq!(
+            uri.as_ref().and_then(to_path),
+            Some(PathBuf::from("/tmp/a b/été.tremor"))
+        );
+    }
+
+    #[test]
+    fn to_path_of_other_schemes() {
+        let uri = Url::parse("untitled:Untitled-1").ok();
+        assert_eq!(uri.as_ref().and_then(to_path), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn normalize_keeps_unix_paths() {
+        let path = Path::new("/tmp/c:/x.tremor");
+        assert_eq!(normalize(path), path);
+    }
+
+    #[test]
+    fn split_env_skips_empty_paths() {
+        let name = "TREMOR_LANGUAGE_SERVER_TEST_SPLIT_ENV";
+        assert!(split_env(name).is_empty());
+
+        let paths = vec![PathBuf::from("/a"), PathBuf::new(), PathBuf::from("/b c")];
+        if let Ok(value) = env::join_paths(&paths) {
+            env::set_var(name, value);
+        }
+        assert_eq!(
+            split_env(name),
+            vec![PathBuf::from("/a"), PathBuf::from("/b c")]
+        );
+        env::remove_var(name);
+    }
+
+    #[test]
+    fn append_env_adds_at_the_end() {
+        let name = "TREMOR_LANGUAGE_SERVER_TEST_APPEND_ENV";
+        env::set_var(name, "/a");
+        assert!(append_env(name, Path::new("/b")));
+        assert_eq!(
+            split_env(name),
+            vec![PathBuf::from("/a"), PathBuf::from("/b")]
+        );
+        env::remove_var(name);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn normalize_drive_letters_and_prefixes() {
+        assert_eq!(
+            normalize(Path::new(r"c:\lib\x.tremor")),
+            PathBuf::from(r"C:\lib\x.tremor")
+        );
+        assert_eq!(
+            normalize(Path::new(r"\\?\c:\lib\x.tremor")),
+            PathBuf::from(r"C:\lib\x.tremor")
+        );
+        assert_eq!(
+            normalize(Path::new(r"\\?\UNC\server\share\x.tremor")),
+            PathBuf::from(r"\\server\share\x.tremor")
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn to_path_of_encoded_drive_letters() {
+        let encoded = Url::parse("file:///c%3A/a%20b/%C3%A9t%C3%A9.tremor").ok();
+        let plain = Url::parse("file:///C:/a%20b/%C3%A9t%C3%A9.tremor").ok();
+        let expected = Some(PathBuf::from(r"C:\a b\été.tremor"));
+        assert_eq!(encoded.as_ref().and_then(to_path), expected);
+        assert_eq!(plain.as_ref().and_then(to_path), expected);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn to_path_and_to_uri_of_unc_paths() {
+        let uri = Url::parse("file://server/share/x.tremor").ok();
+        assert_eq!(
+            uri.as_ref().and_then(to_path),
+            Some(PathBuf::from(r"\\server\share\x.tremor"))
+        );
+        let uri = to_uri(Path::new(r"\\?\UNC\server\share\x.tremor"));
+        assert_eq!(
+            uri.as_ref().map(Url::as_str),
+            Some("file://server/share/x.tremor")
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn to_uri_of_verbatim_paths() {
+        let uri = to_uri(Path::new(r"\\?\c:\a b\x.tremor"));
+        assert_eq!(
+            uri.as_ref().map(Url::as_str),
+            Some("file:///C:/a%20b/x.tremor")
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn split_env_uses_semicolons() {
+        let name = "TREMOR_LANGUAGE_SERVER_TEST_SPLIT_ENV_WINDOWS";
+        env::set_var(name, r"c:\a;\\?\D:\b;");
+        assert_eq!(
+            split_env(name),
+            vec![PathBuf::from(r"C:\a"), PathBuf::from(r"D:\b")]
+        );
+        env::remove_var(name);
+    }
+}
@@ -0,0 +1,447 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// the pipeline graph of a trickle query: the nodes created in it and the select
+// statements wiring them together
+
+use crate::blocks;
+use crate::language::{Token, TokenSpan};
+use crate::lsp_utils;
+use serde_json::{json, Value};
+use tower_lsp::lsp_types::*;
+
+// streams that every query has, without needing to create them
+pub const DEFAULT_STREAMS: &[&str] = &["in", "out", "err"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeKind {
+    Stream,
+    Operator,
+    Script,
+}
+
+// a name in the document, along with where it is
+#[derive(Debug, Clone)]
+pub struct Name {
+    pub value: String,
+    pub range: Range,
+}
+
+// something created in the query, that select statements can read from or write into
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub name: Name,
+    pub kind: NodeKind,
+    // definition of an operator or script, as written after `from` (eg: `alias::name`).
+    // None if it has the name of the node.
+    pub definition: Option<Name>,
+}
+
+// a `select ... from node[windows] ... into node` statement
+#[derive(Debug, Clone)]
+pub struct Select {
+    // covers the select keyword
+    pub range: Range,
+    pub from: Option<Name>,
+    pub windows: Vec<Name>,
+    pub into: Option<Name>,
+}
+
+// a `define <kind> window <name> with ... end` statement
+#[derive(Debug, Clone)]
+pub struct Window {
+    pub name: Name,
+    pub kind: String,
+    // full statement text, as written
+    pub definition: String,
+}
+
+fn name(text: &str, token: &TokenSpan) -> Option<Name> {
+    match &token.value {
+        Token::Ident(value, _) => Some(Name {
+            value: value.to_string(),
+            range: lsp_utils::token_range(text, token),
+        }),
+        _ => None,
+    }
+}
+
+// nodes from `create stream|operator|script <name>`
+pub fn nodes(text: &str, tokens: &[TokenSpan]) -> Vec<Node> {
+    let tokens = blocks::significant(tokens);
+
+    let mut nodes = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        if t.value != Token::Create {
+            continue;
+        }
+        let kind = match tokens.get(i + 1).map(|t| &t.value) {
+            Some(Token::Stream) => NodeKind::Stream,
+            Some(Token::Operator) => NodeKind::Operator,
+            Some(Token::Script) => NodeKind::Script,
+            _ => continue,
+        };
+        if let Some(name) = tokens.get(i + 2).and_then(|t| name(text, t)) {
+            // `create operator name from module::definition`
+            let path: Vec<&&TokenSpan> = match tokens.get(i + 3).map(|t| &t.value) {
+                Some(Token::From) => tokens[i + 4..]
+                    .iter()
+                    .take_while(|t| matches!(t.value, Token::Ident(_, _) | Token::ColonColon))
+                    .collect(),
+                _ => vec![],
+            };
+            let definition = match (path.first(), path.last()) {
+                (Some(first), Some(last)) => Some(Name {
+                    value: path.iter().map(|t| t.value.to_string()).collect(),
+                    range: Range::new(
+                        lsp_utils::token_range(text, first).start,
+                        lsp_utils::token_range(text, last).end,
+                    ),
+                }),
+                _ => None,
+            };
+            nodes.push(Node {
+                name,
+                kind,
+                definition,
+            });
+        }
+    }
+    nodes
+}
+
+pub fn windows(text: &str, tokens: &[TokenSpan]) -> Vec<Window> {
+    let tokens = blocks::significant(tokens);
+
+    let mut windows = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        // `define tumbling window name`
+        if t.value != Token::Window || i < 2 || tokens[i - 2].value != Token::Define {
+            continue;
+        }
+        let window_name = match tokens.get(i + 1).and_then(|t| name(text, t)) {
+            Some(window_name) => window_name,
+            None => continue,
+        };
+        let end = match tokens[i..].iter().find(|t| t.value == Token::Semi) {
+            Some(semi) => semi,
+            None => tokens[tokens.len() - 1],
+        };
+        let from = lsp_utils::to_offset(text, &tokens[i - 2].span.start);
+        let to = lsp_utils::to_offset(text, &end.span.end);
+        windows.push(Window {
+            name: window_name,
+            kind: tokens[i - 1].value.to_string(),
+            definition: text.get(from..to).unwrap_or_default().to_string(),
+        });
+    }
+    windows
+}
+
+pub fn selects(text: &str, tokens: &[TokenSpan]) -> Vec<Select> {
+    let tokens = blocks::significant(tokens);
+
+    let mut selects = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        if t.value != Token::Select {
+            continue;
+        }
+        let mut select = Select {
+            range: lsp_utils::token_range(text, t),
+            from: None,
+            windows: vec![],
+            into: None,
+        };
+
+        let mut depth = 0;
+        let mut j = i + 1;
+        while let Some(t) = tokens.get(j) {
+            match &t.value {
+                Token::LParen | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBrace => depth -= 1,
+                Token::LBracket => depth += 1,
+                Token::RBracket => depth -= 1,
+                Token::From if depth == 0 => {
+                    select.from = tokens.get(j + 1).and_then(|t| name(text, t));
+                    // window list follows the node, or its port (`from node/port[...]`)
+                    let list_start = [j + 2, j + 4].iter().copied().find(|k| {
+                        tokens.get(*k).map(|t| &t.value) == Some(&Token::LBracket)
+                            && (*k == j + 2
+                                || matches!(
+                                    tokens.get(k - 1).map(|t| &t.value),
+                                    Some(Token::Ident(_, _))
+                                ))
+                    });
+                    if let Some(list_start) = list_start {
+                        j = list_start;
+                        // names can be module paths, eg: `from in[windows::hourly]`
+                        let mut path: Option<Name> = None;
+                        while let Some(t) = tokens.get(j + 1) {
+                            j += 1;
+                            match (&t.value, path.as_mut()) {
+                                (Token::RBracket, _) => break,
+                                (Token::Comma, _) => select.windows.extend(path.take()),
+                                (Token::Ident(_, _), None) => path = name(text, t),
+                                (Token::Ident(value, _), Some(p)) => {
+                                    p.value.push_str(value);
+                                    p.range.end = lsp_utils::token_range(text, t).end;
+                                }
+                                (Token::ColonColon, Some(p)) => p.value.push_str("::"),
+                                _ => (),
+                            }
+                        }
+                        select.windows.extend(path);
+                    }
+                }
+                Token::Into if depth == 0 => {
+                    select.into = tokens.get(j + 1).and_then(|t| name(text, t));
+                }
+                Token::Semi if depth <= 0 => break,
+                _ => (),
+            }
+            j += 1;
+        }
+        selects.push(select);
+    }
+    selects
+}
+
+// counts of what makes up the pipeline, eg: `2 streams, 3 selects, 1 window`. None if the
+// document does not define a pipeline (ie. has no select statements).
+pub fn summary(text: &str, tokens: &[TokenSpan]) -> Option<String> {
+    let selects = selects(text, tokens);
+    if selects.is_empty() {
+        return None;
+    }
+    let nodes = nodes(text, tokens);
+    let count = |kind: NodeKind| nodes.iter().filter(|node| node.kind == kind).count();
+    let counts = [
+        (count(NodeKind::Stream), "stream"),
+        (count(NodeKind::Operator), "operator"),
+        (count(NodeKind::Script), "script"),
+        (selects.len(), "select"),
+        (windows(text, tokens).len(), "window"),
+    ];
+    Some(
+        counts
+            .iter()
+            .filter(|(n, _)| *n > 0)
+            .map(|(n, name)| format!("{} {}{}", n, name, if *n == 1 { "" } else { "s" }))
+            .collect::<Vec<_>>()
+            .join(", "),
+    )
+}
+
+// the pipeline graph as json, with the nodes and the edges that the selects make, along with
+// a graphviz (dot) rendering of it for clients to show
+pub fn graph(text: &str, tokens: &[TokenSpan]) -> Value {
+    let nodes = nodes(text, tokens);
+    let selects = selects(text, tokens);
+    let kind_name = |kind: NodeKind| match kind {
+        NodeKind::Stream => "stream",
+        NodeKind::Operator => "operator",
+        NodeKind::Script => "script",
+    };
+
+    let mut node_values: Vec<Value> = DEFAULT_STREAMS
+        .iter()
+        .map(|name| json!({ "name": name, "kind": "stream" }))
+        .collect();
+    node_values.extend(
+        nodes
+            .iter()
+            .map(|node| json!({ "name": node.name.value, "kind": kind_name(node.kind) })),
+    );
+    let edges: Vec<(&str, &str, Vec<&str>)> = selects
+        .iter()
+        .filter_map(|select| {
+            Some((
+                select.from.as_ref()?.value.as_str(),
+                select.into.as_ref()?.value.as_str(),
+                select.windows.iter().map(|w| w.value.as_str()).collect(),
+            ))
+        })
+        .collect();
+
+    let mut dot = vec!["digraph pipeline {".to_string()];
+    for node in &nodes {
+        let shape = match node.kind {
+            NodeKind::Stream => "ellipse",
+            NodeKind::Operator | NodeKind::Script => "box",
+        };
+        dot.push(format!("  \"{}\" [shape={}];", node.name.value, shape));
+    }
+    for (from, into, windows) in &edges {
+        if windows.is_empty() {
+            dot.push(format!("  \"{}\" -> \"{}\";", from, into));
+        } else {
+            dot.push(format!(
+                "  \"{}\" -> \"{}\" [label=\"{}\"];",
+                from,
+                into,
+                windows.join(", ")
+            ));
+        }
+    }
+    dot.push("}".to_string());
+
+    json!({
+        "nodes": node_values,
+        "edges": edges
+            .iter()
+            .map(|(from, into, windows)| json!({ "from": from, "into": into, "windows": windows }))
+            .collect::<Vec<_>>(),
+        "dot": dot.join("\n"),
+    })
+}
+
+// the default streams that the pipeline reads from (eg: `in`) and the ones it writes into
+// (eg: `out`, `err`), which are its ports when deployed. created streams are internal.
+pub fn ports(text: &str, tokens: &[TokenSpan]) -> (Vec<String>, Vec<String>) {
+    let nodes = nodes(text, tokens);
+    let is_port = |name: &Name| {
+        DEFAULT_STREAMS.contains(&name.value.as_str())
+            && !nodes.iter().any(|n| n.name.value == name.value)
+    };
+    let (mut inputs, mut outputs): (Vec<String>, Vec<String>) = (vec![], vec![]);
+    for select in selects(text, tokens) {
+        if let Some(from) = select.from.filter(|from| is_port(from)) {
+            if !inputs.contains(&from.value) {
+                inputs.push(from.value);
+            }
+        }
+        if let Some(into) = select.into.filter(|into| is_port(into)) {
+            if !outputs.contains(&into.value) {
+                outputs.push(into.value);
+            }
+        }
+    }
+    (inputs, outputs)
+}
+
+// true if selects can refer to the name as a node
+pub fn is_node(name: &str, nodes: &[Node]) -> bool {
+    DEFAULT_STREAMS.contains(&name) || nodes.iter().any(|n| n.name.value == name)
+}
+
+// the chain of nodes (starting and ending with the select's from node) that makes the
+// select part of a cycle, if it is in one
+pub fn cycle(select: &Select, selects: &[Select]) -> Option<Vec<String>> {
+    let from = &select.from.as_ref()?.value;
+    let into = &select.into.as_ref()?.value;
+
+    // breadth-first search from the into node back to the from node
+    let mut parents: Vec<(String, Option<usize>)> = vec![(into.clone(), None)];
+    let mut next = 0;
+    while let Some((node, _)) = parents.get(next).cloned() {
+        if &node == from {
+            let mut chain = vec![from.clone()];
+            let mut current = Some(next);
+            while let Some(i) = current {
+                chain.push(parents[i].0.clone());
+                current = parents[i].1;
+            }
+            chain.reverse();
+            // starts at the into node at this point, so rotate the from node to the front
+            chain.rotate_right(1);
+            return Some(chain);
+        }
+        for s in selects {
+            if let (Some(f), Some(i)) = (&s.from, &s.into) {
+                if f.value == node && !parents.iter().any(|(n, _)| n == &i.value) {
+                    parents.push((i.value.clone(), Some(next)));
+                }
+            }
+        }
+        next += 1;
+    }
+    None
+}
+
+// the node named at the position (where it is created, or in a select statement), with
+// the select statements writing into it and the ones reading from it
+pub struct Connections {
+    pub name: String,
+    // None for the default streams
+    pub kind: Option<NodeKind>,
+    pub producers: Vec<Select>,
+    pub consumers: Vec<Select>,
+}
+
+pub fn connections(text: &str, tokens: &[TokenSpan], position: Position) -> Option<Connections> {
+    let selects = selects(text, tokens);
+    let nodes = nodes(text, tokens);
+    let contains = |name: &Name| name.range.start <= position && position <= name.range.end;
+
+    let name = nodes
+        .iter()
+        .map(|node| &node.name)
+        .chain(
+            selects
+                .iter()
+                .flat_map(|s| s.from.iter().chain(s.into.iter())),
+        )
+        .find(|name| contains(name))?
+        .value
+        .clone();
+    let kind = nodes
+        .iter()
+        .find(|node| node.name.value == name)
+        .map(|node| node.kind);
+    if kind.is_none() && !DEFAULT_STREAMS.contains(&name.as_str()) {
+        return None;
+    }
+    let is = |node: &Option<Name>| node.as_ref().map_or(false, |node| node.value == name);
+
+    Some(Connections {
+        producers: selects.iter().filter(|s| is(&s.into)).cloned().collect(),
+        consumers: selects.iter().filter(|s| is(&s.from)).cloned().collect(),
+        name,
+        kind,
+    })
+}
+
+// ranges of all the occurrences of the node or window name at the position (definition
+// and uses in selects), for editing them together. empty if there's no name there.
+pub fn linked_ranges(text: &str, tokens: &[TokenSpan], position: Position) -> Vec<Range> {
+    let selects = selects(text, tokens);
+    let contains = |name: &Name| name.range.start <= position && position <= name.range.end;
+
+    let node_names: Vec<Name> = nodes(text, tokens)
+        .into_iter()
+        .map(|node| node.name)
+        .chain(
+            selects
+                .iter()
+                .flat_map(|s| s.from.iter().chain(s.into.iter()))
+                .cloned(),
+        )
+        .collect();
+    let window_names: Vec<Name> = windows(text, tokens)
+        .into_iter()
+        .map(|window| window.name)
+        .chain(selects.iter().flat_map(|s| s.windows.iter()).cloned())
+        .collect();
+
+    for names in &[node_names, window_names] {
+        if let Some(name) = names.iter().find(|name| contains(name)) {
+            return names
+                .iter()
+                .filter(|n| n.value == name.value)
+                .map(|n| n.range)
+                .collect();
+        }
+    }
+    vec![]
+}
@@ -0,0 +1,96 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use tower_lsp::lsp_types::notification::Progress as ProgressNotification;
+use tower_lsp::lsp_types::request::WorkDoneProgressCreate;
+use tower_lsp::lsp_types::*;
+use tower_lsp::Client;
+
+// for generating unique progress tokens
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+// server-initiated progress reporting (window/workDoneProgress), for long operations
+pub struct Progress<'a> {
+    client: &'a Client,
+    token: NumberOrString,
+    title: String,
+    total: usize,
+}
+
+impl<'a> Progress<'a> {
+    // returns None if the client did not accept the progress token, in which case
+    // the operation should just carry on without reporting
+    pub async fn begin(client: &'a Client, title: &str, total: usize) -> Option<Progress<'a>> {
+        let token = NumberOrString::String(format!(
+            "tremor-language-server/{}",
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        client
+            .send_custom_request::<WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .await
+            .ok()?;
+
+        let progress = Self {
+            client,
+            token,
+            title: title.to_string(),
+            total,
+        };
+        progress
+            .notify(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: progress.title.clone(),
+                cancellable: Some(false),
+                message: Some(format!("0/{}", total)),
+                percentage: Some(0),
+            }))
+            .await;
+
+        Some(progress)
+    }
+
+    pub async fn report(&self, done: usize) {
+        let percentage = if self.total == 0 {
+            100
+        } else {
+            (done * 100 / self.total) as u32
+        };
+
+        self.notify(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: Some(false),
+            message: Some(format!("{} {}/{}", self.title, done, self.total)),
+            percentage: Some(percentage),
+        }))
+        .await;
+    }
+
+    pub async fn end(self, message: &str) {
+        self.notify(WorkDoneProgress::End(WorkDoneProgressEnd {
+            message: Some(message.to_string()),
+        }))
+        .await;
+    }
+
+    async fn notify(&self, value: WorkDoneProgress) {
+        self.client
+            .send_custom_notification::<ProgressNotification>(ProgressParams {
+                token: self.token.clone(),
+                value: ProgressParamsValue::WorkDone(value),
+            })
+            .await;
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// project manifest at a workspace root, in toml. eg:
+//
+// path = ["lib", "/opt/tremor/lib"]
+// entrypoints = ["main.trickle"]
+//
+// [lints]
+// unreachable-case = "error"
+//
+// [schemas]
+// event = "schemas/event.json"
+//
+// settings there (lints, path) take precedence over the ones from the client, and relative
+// paths are relative to the root.
+
+use crate::config::Config;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// manifest file names, in order of precedence
+pub const MANIFEST_FILES: &[&str] = &["tremor.toml", ".tremor-project"];
+
+#[derive(Debug, Default, Clone)]
+pub struct Project {
+    pub root: PathBuf,
+    // None if the root has no manifest
+    pub manifest: Option<PathBuf>,
+    // settings for the documents in the project
+    pub config: Config,
+    // main files of the project
+    pub entrypoints: Vec<PathBuf>,
+    // json schema file per (event) name
+    pub schemas: Vec<(String, PathBuf)>,
+}
+
+impl Project {
+    pub fn load(root: &Path) -> Result<Self, String> {
+        let mut project = Self {
+            root: root.to_path_buf(),
+            ..Self::default()
+        };
+
+        let (manifest, text) = match MANIFEST_FILES.iter().find_map(|name| {
+            let file = root.join(name);
+            fs::read_to_string(&file).ok().map(|text| (file, text))
+        }) {
+            Some(found) => found,
+            None => return Ok(project),
+        };
+        let value: toml::Value =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", manifest.display(), e))?;
+        let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+
+        project.config = Config::from_json(&value);
+        project.config.path = project.config.path.iter().map(|p| root.join(p)).collect();
+        project.entrypoints = value
+            .get("entrypoints")
+            .and_then(Value::as_array)
+            .map(|files| {
+                files
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(|file| root.join(file))
+                    .collect()
+            })
+            .unwrap_or_default();
+        project.schemas = value
+            .get("schemas")
+            .and_then(Value::as_object)
+            .map(|schemas| {
+                schemas
+                    .iter()
+                    .filter_map(|(name, file)| Some((name.clone(), root.join(file.as_str()?))))
+                    .collect()
+            })
+            .unwrap_or_default();
+        project.manifest = Some(manifest);
+
+        Ok(project)
+    }
+
+    pub fn is_manifest(path: &Path) -> bool {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| MANIFEST_FILES.contains(&name))
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "root": self.root,
+            "manifest": self.manifest,
+            "path": self.config.path,
+            "entrypoints": self.entrypoints,
+            "schemas": self
+                .schemas
+                .iter()
+                .map(|(name, file)| (name.clone(), json!(file)))
+                .collect::<serde_json::Map<String, Value>>(),
+        })
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// named regions of a document, marked in comments, and the folding ranges of a document.
+//
+// regions are either sections, from a `## Section` header (a doc comment line on its own, not
+// documenting a definition) up to the next one, or `# region name` ... `# endregion` blocks.
+
+use crate::language::TokenSpan;
+use crate::symbols;
+use tower_lsp::lsp_types::*;
+
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub name: String,
+    // from the marker to the end of the region
+    pub range: Range,
+    // the marker line
+    pub marker_range: Range,
+}
+
+fn line_range(line_number: usize, line: &str) -> Range {
+    Range::new(
+        Position::new(line_number as u64, 0),
+        Position::new(line_number as u64, line.encode_utf16().count() as u64),
+    )
+}
+
+// name of the region that the comment line starts (`#region name` or `# region name`), or
+// None. Some(None) for the line ending one.
+fn region_marker(line: &str) -> Option<Option<String>> {
+    let comment = line.trim().strip_prefix('#')?.trim_start();
+    if comment == "endregion" {
+        return Some(None);
+    }
+    let name = comment.strip_prefix("region")?;
+    if !(name.is_empty() || name.starts_with(char::is_whitespace)) {
+        return None;
+    }
+    Some(Some(name.trim().to_string()))
+}
+
+// title of the section that the line is the header of, if it is one
+fn section_header(lines: &[&str], i: usize) -> Option<String> {
+    let is_doc = |line: &str| {
+        let line = line.trim();
+        line.starts_with("##") && !line.starts_with("###")
+    };
+    let title = lines[i].trim().strip_prefix("##")?;
+    let standalone = is_doc(lines[i])
+        && (i == 0 || !is_doc(lines[i - 1]))
+        && lines.get(i + 1).map_or(true, |next| next.trim().is_empty());
+    // the title can be a markdown heading too, eg: `## # Strings`
+    let title = title.trim().trim_start_matches('#').trim();
+    Some(title.to_string()).filter(|title| standalone && !title.is_empty())
+}
+
+pub fn regions(text: &str) -> Vec<Region> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut regions = Vec::new();
+
+    // open `# region` markers
+    let mut open: Vec<(usize, String)> = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        match region_marker(line) {
+            Some(Some(name)) => open.push((i, name)),
+            Some(None) => {
+                if let Some((start, name)) = open.pop() {
+                    regions.push(Region {
+                        name: if name.is_empty() {
+                            "region".to_string()
+                        } else {
+                            name
+                        },
+                        range: Range::new(
+                            line_range(start, lines[start]).start,
+                            line_range(i, line).end,
+                        ),
+                        marker_range: line_range(start, lines[start]),
+                    });
+                }
+            }
+            None => (),
+        }
+    }
+
+    let headers: Vec<(usize, String)> = (0..lines.len())
+        .filter_map(|i| section_header(&lines, i).map(|title| (i, title)))
+        .collect();
+    // marked regions the sections are in, which end them too
+    let marked: Vec<(usize, usize)> = regions
+        .iter()
+        .map(|region| {
+            (
+                region.range.start.line as usize,
+                region.range.end.line as usize,
+            )
+        })
+        .collect();
+    for (k, (start, title)) in headers.iter().enumerate() {
+        let next = headers
+            .get(k + 1)
+            .map(|(next, _)| *next)
+            .into_iter()
+            .chain(
+                marked
+                    .iter()
+                    .filter(|(first, last)| first < start && start < last)
+                    .map(|(_, last)| *last),
+            )
+            .min()
+            .unwrap_or_else(|| lines.len());
+        // without the blank lines before the next one
+        let end = (*start..next)
+            .rev()
+            .find(|i| !lines[*i].trim().is_empty())
+            .unwrap_or(*start);
+        regions.push(Region {
+            name: title.clone(),
+            range: Range::new(
+                line_range(*start, lines[*start]).start,
+                line_range(end, lines[end]).end,
+            ),
+            marker_range: line_range(*start, lines[*start]),
+        });
+    }
+    regions
+}
+
+// the regions, the definitions with a body, match expressions and runs of comment lines
+pub fn folding_ranges(text: &str, tokens: &[TokenSpan]) -> Vec<FoldingRange> {
+    let folding = |range: Range, kind: Option<FoldingRangeKind>| FoldingRange {
+        start_line: range.start.line,
+        start_character: None,
+        end_line: range.end.line,
+        end_character: None,
+        kind,
+    };
+    let mut ranges: Vec<FoldingRange> = regions(text)
+        .into_iter()
+        .map(|region| folding(region.range, Some(FoldingRangeKind::Region)))
+        .collect();
+
+    let mut outline = symbols::outline(text, tokens);
+    while let Some(symbol) = outline.pop() {
+        ranges.push(folding(symbol.range, None));
+        outline.extend(symbol.children.into_iter().flatten());
+    }
+
+    let lines: Vec<&str> = text.lines().collect();
+    let mut start = None;
+    for (i, line) in lines.iter().chain(std::iter::once(&"")).enumerate() {
+        match (line.trim_start().starts_with('#'), start) {
+            (true, None) => start = Some(i),
+            (false, Some(first)) => {
+                ranges.push(folding(
+                    Range::new(
+                        Position::new(first as u64, 0),
+                        Position::new(i as u64 - 1, 0),
+                    ),
+                    Some(FoldingRangeKind::Comment),
+                ));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+
+    ranges.retain(|range| range.start_line < range.end_line);
+    ranges.sort_by_key(|range| (range.start_line, range.end_line));
+    ranges.dedup_by_key(|range| (range.start_line, range.end_line));
+    ranges
+}
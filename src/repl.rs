@@ -0,0 +1,87 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// interactive evaluation of tremor-script, for console panels in the editors
+
+use crate::language::Language;
+use serde_json::{json, Value};
+use tower_lsp::lsp_types::Url;
+
+// keywords for statements that define things for later inputs, instead of producing a value
+const DEFINITION_KEYWORDS: &[&str] = &["use", "const", "fn", "mod", "intrinsic"];
+
+// state carried over between evaluations
+#[derive(Debug)]
+pub struct Session {
+    definitions: Vec<String>,
+    // json
+    state: String,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            definitions: vec![],
+            state: "null".to_string(),
+        }
+    }
+}
+
+impl Session {
+    // evaluates the input against the event (json), returning the outcome as json. with no
+    // state given, the state from the previous evaluation is used.
+    pub fn eval(
+        &mut self,
+        language: &dyn Language,
+        uri: &Url,
+        input: &str,
+        event: &str,
+        state: Option<&str>,
+    ) -> Result<Value, String> {
+        let input = input.trim();
+        let is_definition = DEFINITION_KEYWORDS
+            .iter()
+            .any(|keyword| input.split_whitespace().next() == Some(keyword));
+
+        let mut script = self.definitions.join("\n");
+        script.push('\n');
+        script.push_str(input);
+        if is_definition {
+            // definitions on their own don't produce anything to emit
+            script.push_str("\nnull");
+        }
+
+        let state = state.unwrap_or(&self.state).to_string();
+        let output = language
+            .run(uri, &script, event, &state)
+            .ok_or("Evaluation is not supported for this language")??;
+
+        if is_definition {
+            self.definitions.push(input.to_string());
+        }
+        self.state = output.state.clone();
+
+        Ok(json!({
+            "value": parse_json(output.value.as_deref()),
+            "dropped": output.value.is_none(),
+            "port": output.port,
+            "state": parse_json(Some(&output.state)),
+        }))
+    }
+}
+
+fn parse_json(s: Option<&str>) -> Value {
+    s.and_then(|s| serde_json::from_str(s).ok())
+        .unwrap_or(Value::Null)
+}
@@ -0,0 +1,74 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// client for the REST API of a running tremor node, for listing what is deployed there and
+// publishing queries to it.
+//
+// https://docs.tremor.rs/api/
+
+use serde_json::{json, Value};
+
+// where the tremor API listens by default
+pub const DEFAULT_URL: &str = "http://localhost:9898";
+
+// artefact kinds, by their API path
+const ARTEFACTS: &[&str] = &["pipeline", "onramp", "offramp", "binding"];
+
+pub struct Api {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl Api {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<Value, String> {
+        let response = request
+            .header("Accept", "application/json")
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach tremor at {}: {}", self.url, e))?;
+        let status = response.status();
+        let body = response.text().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(format!("Tremor responded with {}: {}", status, body.trim()));
+        }
+        serde_json::from_str(&body).map_err(|e| format!("Unexpected response from tremor: {}", e))
+    }
+
+    // ids of the deployed artefacts, per kind, eg: `{"pipeline": ["main"], ...}`
+    pub async fn artefacts(&self) -> Result<Value, String> {
+        let mut artefacts = json!({});
+        for kind in ARTEFACTS {
+            let request = self.client.get(&format!("{}/{}", self.url, kind));
+            artefacts[kind] = self.send(request).await?;
+        }
+        Ok(artefacts)
+    }
+
+    // publishes the trickle query as a pipeline artefact, returning it as tremor has it
+    pub async fn publish_pipeline(&self, query: &str) -> Result<Value, String> {
+        let request = self
+            .client
+            .post(&format!("{}/pipeline", self.url))
+            .header("Content-Type", "application/vnd.trickle")
+            .body(query.to_string());
+        self.send(request).await
+    }
+}
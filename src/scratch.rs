@@ -0,0 +1,40 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// scratchpads: documents that only live in the editor (`untitled:Untitled-1` in vs code, or
+// `tremor-scratch:/pipeline.trickle` from the extension), with no file on disk. they get the
+// same features as files, minus the ones writing next to them.
+
+use crate::language;
+use crate::workspace;
+use std::path::Path;
+use tower_lsp::lsp_types::Url;
+
+pub const SCHEMES: &[&str] = &["untitled", "tremor-scratch"];
+
+pub fn is_scratch(uri: &Url) -> bool {
+    SCHEMES.contains(&uri.scheme())
+}
+
+// file extension of the language of the scratchpad: the one of its name (eg: for
+// `tremor-scratch:/pipeline.trickle`), or else the one for the language id the client
+// opened it with
+pub fn language(uri: &Url, language_id: Option<&str>) -> Option<&'static str> {
+    let extension = Path::new(uri.path()).extension().and_then(|e| e.to_str());
+    workspace::FILE_EXTENSIONS
+        .iter()
+        .copied()
+        .find(|e| Some(*e) == extension)
+        .or_else(|| language_id.and_then(language::file_extension))
+}
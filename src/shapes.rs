@@ -0,0 +1,221 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// lightweight shape (ie. value type) inference, for catching obvious type errors at
+// edit time. only literals and names bound to literals via `let` have a known shape.
+
+use crate::language::{Token, TokenSpan};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    Null,
+    Bool,
+    Int,
+    Float,
+    String,
+    Array,
+    Record,
+}
+
+impl Shape {
+    // for messages, eg: "got an integer"
+    pub fn description(self) -> &'static str {
+        match self {
+            Shape::Null => "null",
+            Shape::Bool => "a boolean",
+            Shape::Int => "an integer",
+            Shape::Float => "a float",
+            Shape::String => "a string",
+            Shape::Array => "an array",
+            Shape::Record => "a record",
+        }
+    }
+
+    pub fn is_scalar(self) -> bool {
+        !matches!(self, Shape::Array | Shape::Record | Shape::String)
+    }
+
+    // shapes accepted for a type name, as used in function signatures. None if it's not a
+    // type name we know (eg: a plain argument name).
+    pub fn accepted_by(type_name: &str) -> Option<&'static [Shape]> {
+        match type_name {
+            "string" | "str" => Some(&[Shape::String]),
+            "int" | "integer" => Some(&[Shape::Int]),
+            "float" | "number" | "num" => Some(&[Shape::Int, Shape::Float]),
+            "bool" | "boolean" => Some(&[Shape::Bool]),
+            "array" => Some(&[Shape::Array]),
+            "record" | "object" => Some(&[Shape::Record]),
+            _ => None,
+        }
+    }
+}
+
+// shapes known for the (significant) tokens of a document
+pub struct Shapes<'a, 'input> {
+    tokens: &'a [&'a TokenSpan<'input>],
+    // token index of the `let`, bound name and its shape
+    bindings: Vec<(usize, String, Shape)>,
+}
+
+impl<'a, 'input> Shapes<'a, 'input> {
+    pub fn new(tokens: &'a [&'a TokenSpan<'input>]) -> Self {
+        let mut shapes = Self {
+            tokens,
+            bindings: vec![],
+        };
+
+        // `let name = <literal>;`
+        for (i, t) in tokens.iter().enumerate() {
+            if t.value != Token::Let {
+                continue;
+            }
+            if let (Some(Token::Ident(name, _)), Some(Token::Equal)) = (
+                tokens.get(i + 1).map(|t| &t.value),
+                tokens.get(i + 2).map(|t| &t.value),
+            ) {
+                let shape = shapes.literal(i + 3).and_then(|(shape, end)| {
+                    // the literal should be all there is to the expression
+                    match tokens.get(end + 1).map(|t| &t.value) {
+                        Some(Token::Semi) | None => Some(shape),
+                        _ => None,
+                    }
+                });
+                if let Some(shape) = shape {
+                    shapes.bindings.push((i, name.to_string(), shape));
+                }
+            }
+        }
+
+        shapes
+    }
+
+    // shape of the literal starting at the index, along with the index where it ends
+    pub fn literal(&self, i: usize) -> Option<(Shape, usize)> {
+        let shape = match &self.tokens.get(i)?.value {
+            Token::Nil => Shape::Null,
+            Token::BoolLiteral(_) => Shape::Bool,
+            Token::IntLiteral(_) => Shape::Int,
+            Token::FloatLiteral(..) => Shape::Float,
+            Token::DQuote => Shape::String,
+            Token::LBracket => Shape::Array,
+            Token::LBrace => Shape::Record,
+            _ => return None,
+        };
+        let end = match shape {
+            Shape::String => self.closing(i, |t| *t == Token::DQuote, |_| false)?,
+            Shape::Array => {
+                self.closing(i, |t| *t == Token::RBracket, |t| *t == Token::LBracket)?
+            }
+            Shape::Record => self.closing(i, |t| *t == Token::RBrace, |t| *t == Token::LBrace)?,
+            _ => i,
+        };
+        Some((shape, end))
+    }
+
+    // shape of the operand starting at the index: a literal or a bound name (and not part
+    // of a longer expression like a path or a call)
+    pub fn operand(&self, i: usize) -> Option<(Shape, usize)> {
+        match &self.tokens.get(i)?.value {
+            Token::Ident(name, _) => {
+                let is_plain = !matches!(
+                    self.tokens.get(i + 1).map(|t| &t.value),
+                    Some(Token::ColonColon) | Some(Token::LParen) | Some(Token::Dot)
+                );
+                let shape = self.binding(name, i).filter(|_| is_plain)?;
+                Some((shape, i))
+            }
+            _ => self.literal(i),
+        }
+    }
+
+    // shape of the operand ending at the index
+    pub fn operand_ending_at(&self, i: usize) -> Option<Shape> {
+        let start = match &self.tokens.get(i)?.value {
+            Token::RBrace => self.opening(i, |t| *t == Token::LBrace, |t| *t == Token::RBrace)?,
+            Token::RBracket => {
+                self.opening(i, |t| *t == Token::LBracket, |t| *t == Token::RBracket)?
+            }
+            Token::DQuote => self.opening(i, |t| *t == Token::DQuote, |_| false)?,
+            Token::Ident(_, _) => {
+                // a path or a field access, rather than a plain name
+                if i > 0 && matches!(self.tokens[i - 1].value, Token::ColonColon | Token::Dot) {
+                    return None;
+                }
+                i
+            }
+            _ => i,
+        };
+        // `a[...]` is an index expression, not an array literal
+        if self.tokens[start].value == Token::LBracket && start > 0 {
+            if let Token::Ident(_, _) | Token::RBracket | Token::RParen | Token::RBrace =
+                self.tokens[start - 1].value
+            {
+                return None;
+            }
+        }
+        self.operand(start)
+            .filter(|(_, end)| *end == i)
+            .map(|(shape, _)| shape)
+    }
+
+    // shape of the latest binding for the name before the index
+    fn binding(&self, name: &str, i: usize) -> Option<Shape> {
+        self.bindings
+            .iter()
+            .rev()
+            .find(|(index, bound, _)| *index < i && bound == name)
+            .map(|(_, _, shape)| *shape)
+    }
+
+    fn closing(
+        &self,
+        i: usize,
+        is_close: impl Fn(&Token) -> bool,
+        is_open: impl Fn(&Token) -> bool,
+    ) -> Option<usize> {
+        let mut depth = 0;
+        for (j, t) in self.tokens.iter().enumerate().skip(i + 1) {
+            if is_close(&t.value) {
+                if depth == 0 {
+                    return Some(j);
+                }
+                depth -= 1;
+            } else if is_open(&t.value) {
+                depth += 1;
+            }
+        }
+        None
+    }
+
+    fn opening(
+        &self,
+        i: usize,
+        is_open: impl Fn(&Token) -> bool,
+        is_close: impl Fn(&Token) -> bool,
+    ) -> Option<usize> {
+        let mut depth = 0;
+        for j in (0..i).rev() {
+            let t = &self.tokens[j].value;
+            if is_open(t) {
+                if depth == 0 {
+                    return Some(j);
+                }
+                depth -= 1;
+            } else if is_close(t) {
+                depth += 1;
+            }
+        }
+        None
+    }
+}
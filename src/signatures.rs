@@ -0,0 +1,125 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// signature help: the function call around a position, and signatures to show for it
+
+use crate::blocks;
+use crate::language::{Token, TokenSpan};
+use crate::lsp_utils;
+use tower_lsp::lsp_types::*;
+
+// a call that the position is within the arguments of, eg: `string::format("{}", |)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallSite {
+    // function name along with the module path before it, as written
+    pub path: Vec<String>,
+    // index of the argument at the position
+    pub argument: usize,
+}
+
+// the innermost (possibly unfinished) call around the position
+pub fn call_site(text: &str, tokens: &[TokenSpan], position: Position) -> Option<CallSite> {
+    let tokens = blocks::significant(tokens);
+    let location = lsp_utils::to_language_location_in(text, &position);
+    let location = (location.line(), location.column());
+
+    // open brackets before the position: index of the opening token and commas since
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        if (t.span.start.line(), t.span.start.column()) >= location {
+            break;
+        }
+        match t.value {
+            Token::LParen | Token::LBracket | Token::LBrace => open.push((i, 0)),
+            Token::RParen | Token::RBracket | Token::RBrace => {
+                open.pop();
+            }
+            Token::Comma => {
+                if let Some((_, commas)) = open.last_mut() {
+                    *commas += 1;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // the innermost parenthesis, that follows a function name
+    let (paren, commas) = open
+        .into_iter()
+        .rev()
+        .find(|(i, _)| tokens[*i].value == Token::LParen)?;
+    let mut path = Vec::new();
+    let mut i = paren;
+    while let Some(Token::Ident(name, _)) = i.checked_sub(1).map(|j| &tokens[j].value) {
+        path.insert(0, name.to_string());
+        i -= 1;
+        match i.checked_sub(1).map(|j| &tokens[j].value) {
+            Some(Token::ColonColon) => i -= 1,
+            _ => break,
+        }
+    }
+    if path.is_empty() {
+        return None;
+    }
+
+    Some(CallSite {
+        path,
+        argument: commas,
+    })
+}
+
+// parameter names of a signature like `fn add(a, b)` or `string::format(format, ...)`
+pub fn parameters(signature: &str) -> Vec<String> {
+    let start = match signature.find('(') {
+        Some(start) => start + 1,
+        None => return vec![],
+    };
+    let end = signature.rfind(')').unwrap_or_else(|| signature.len());
+    signature
+        .get(start..end)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|parameter| !parameter.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// signature help for the call, given its signature (eg. `fn add(a, b)`) and documentation
+pub fn help(label: &str, documentation: Option<Documentation>, argument: usize) -> SignatureHelp {
+    let parameters = parameters(label);
+    // arguments past the last parameter belong to it, if it is variadic
+    let active = match parameters.last() {
+        Some(last) if argument >= parameters.len() && last.ends_with("...") => parameters.len() - 1,
+        _ => argument,
+    };
+
+    SignatureHelp {
+        signatures: vec![SignatureInformation {
+            label: label.to_string(),
+            documentation,
+            parameters: Some(
+                parameters
+                    .into_iter()
+                    .map(|parameter| ParameterInformation {
+                        label: ParameterLabel::Simple(parameter),
+                        documentation: None,
+                    })
+                    .collect(),
+            ),
+        }],
+        active_signature: Some(0),
+        active_parameter: Some(active as i64),
+    }
+}
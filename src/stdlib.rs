@@ -0,0 +1,96 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// local copy of the tremor stdlib (the `lib` directory of the tremor-script crate), for the
+// tremor-script version that the server is built with. once installed, it goes on the
+// TREMOR_PATH so that the stdlib modules resolve.
+
+use crate::backend::TREMOR_SCRIPT_VERSION;
+use std::env;
+use std::path::{Path, PathBuf};
+
+// where the stdlib goes, eg: `~/.local/share/tremor-language-server/stdlib/0.9.0`
+pub fn path() -> Option<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .map(|dir| {
+            dir.join(env!("CARGO_PKG_NAME"))
+                .join("stdlib")
+                .join(TREMOR_SCRIPT_VERSION)
+        })
+}
+
+pub fn installed() -> Option<PathBuf> {
+    path().filter(|path| path.is_dir())
+}
+
+// downloads the stdlib, replacing an existing copy if asked to
+#[cfg(feature = "stdlib")]
+pub async fn install(replace: bool) -> Result<PathBuf, String> {
+    use flate2::read::GzDecoder;
+    use std::fs;
+    use std::path::Component;
+    use tar::Archive;
+
+    let path = path().ok_or("Failed to find a directory for the stdlib (HOME is not set)")?;
+    if path.is_dir() && !replace {
+        return Ok(path);
+    }
+    if TREMOR_SCRIPT_VERSION == "unknown" {
+        return Err("The tremor-script version of the server is not known".to_string());
+    }
+
+    let url = format!(
+        "https://crates.io/api/v1/crates/tremor-script/{}/download",
+        TREMOR_SCRIPT_VERSION
+    );
+    let response = reqwest::get(&url)
+        .await
+        .map_err(|e| format!("Failed to download {}: {}", url, e))?;
+    if !response.status().is_success() {
+        return Err(format!("Failed to download {}: {}", url, response.status()));
+    }
+    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+
+    // extracted next to the final directory first, so that a failure keeps the old copy
+    let staging = path.with_extension("partial");
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir_all(&staging).map_err(|e| e.to_string())?;
+    let lib = Path::new(&format!("tremor-script-{}", TREMOR_SCRIPT_VERSION)).join("lib");
+    let mut archive = Archive::new(GzDecoder::new(&bytes[..]));
+    for entry in archive.entries().map_err(|e| e.to_string())? {
+        let mut entry = entry.map_err(|e| e.to_string())?;
+        let entry_path = entry.path().map_err(|e| e.to_string())?.into_owned();
+        let relative = match entry_path.strip_prefix(&lib) {
+            Ok(relative) => relative,
+            Err(_) => continue,
+        };
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            continue;
+        }
+        let destination = staging.join(relative);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        entry.unpack(&destination).map_err(|e| e.to_string())?;
+    }
+
+    let _ = fs::remove_dir_all(&path);
+    fs::rename(&staging, &path).map_err(|e| e.to_string())?;
+    Ok(path)
+}
@@ -0,0 +1,284 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::blocks;
+use crate::language::{Token, TokenSpan};
+use crate::lsp_utils;
+use crate::regions;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::*;
+
+// a named thing defined in a document (function, constant, window, stream etc.)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    // covers just the name
+    pub range: Range,
+    // from the `##` comment lines right above the definition
+    #[serde(default)]
+    pub doc: Option<String>,
+}
+
+// finds the definitions in the document, based on the keyword(s) preceding the name
+pub fn collect(text: &str, tokens: &[TokenSpan]) -> Vec<Symbol> {
+    let tokens: Vec<&TokenSpan> = tokens.iter().filter(|t| !t.value.is_ignorable()).collect();
+
+    let mut symbols = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        let name = match &t.value {
+            Token::Ident(name, _) => name,
+            _ => continue,
+        };
+
+        let kind = match i.checked_sub(1).map(|j| &tokens[j].value) {
+            Some(Token::Fn) => SymbolKind::Function,
+            Some(Token::Const) => SymbolKind::Constant,
+            Some(Token::Module) => SymbolKind::Module,
+            Some(Token::Window) => SymbolKind::Struct,
+            // `define some::kind operator name` (as opposed to `create operator name`)
+            Some(Token::Operator) if i >= 2 && tokens[i - 2].value != Token::Create => {
+                SymbolKind::Operator
+            }
+            // `create stream name` (as opposed to `select ... from stream`)
+            Some(Token::Stream) if i >= 2 && tokens[i - 2].value == Token::Create => {
+                SymbolKind::Event
+            }
+            // `define script name` (as opposed to `create script name`)
+            Some(Token::Script) if i >= 2 && tokens[i - 2].value == Token::Define => {
+                SymbolKind::Class
+            }
+            _ => continue,
+        };
+
+        let range = Range {
+            start: lsp_utils::to_lsp_position_in(text, &t.span.start),
+            end: lsp_utils::to_lsp_position_in(text, &t.span.end),
+        };
+        symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            range,
+            doc: doc_comment(text, range.start.line),
+        });
+    }
+
+    symbols
+}
+
+// the `##` doc comment lines right above the line (of a definition), as markdown
+pub fn doc_comment(text: &str, line: u64) -> Option<String> {
+    let lines: Vec<&str> = text.lines().take(line as usize).collect();
+    let mut doc: Vec<&str> = lines
+        .iter()
+        .rev()
+        .map(|line| line.trim())
+        // `###` is for module comments
+        .take_while(|line| line.starts_with("##") && !line.starts_with("###"))
+        .map(|line| line[2..].strip_prefix(' ').unwrap_or(&line[2..]))
+        .collect();
+    doc.reverse();
+
+    Some(doc.join("\n")).filter(|doc| !doc.trim().is_empty())
+}
+
+// the first line of the definition, eg: `fn add(a, b)` for a function
+pub fn signature(text: &str, symbol: &Symbol) -> String {
+    let line = text
+        .lines()
+        .nth(symbol.range.start.line as usize)
+        .unwrap_or("");
+    let line = line.trim().trim_end_matches(';');
+    line.strip_suffix(" with")
+        .unwrap_or(line)
+        .trim()
+        .to_string()
+}
+
+// markdown documentation for the symbol, as defined in the text
+pub fn documentation(text: &str, symbol: &Symbol) -> String {
+    let mut value = format!("```tremor\n{}\n```", signature(text, symbol));
+    if let Some(doc) = &symbol.doc {
+        value.push_str(&format!("\n\n{}", doc));
+    }
+    value
+}
+
+// the symbols as a tree for outlines, where definitions with a body (functions, modules,
+// scripts) contain what is defined in them, and match expressions show their arms
+pub fn outline(text: &str, tokens: &[TokenSpan]) -> Vec<DocumentSymbol> {
+    let significant = blocks::significant(tokens);
+    let line_range = |line: u64| {
+        let length = text
+            .lines()
+            .nth(line as usize)
+            .map_or(0, |l| lsp_utils::to_utf16_character(l, l.chars().count()));
+        Range::new(Position::new(line, 0), Position::new(line, length))
+    };
+    // end of the body of the definition with the name at the position, if it has one
+    let body_end = |name: Position| {
+        let i = significant
+            .iter()
+            .position(|t| lsp_utils::to_lsp_position_in(text, &t.span.start) == name)?;
+        let opener = significant[..i]
+            .iter()
+            .rposition(|t| matches!(t.value, Token::Fn | Token::Module | Token::Define))?;
+        let end = blocks::block_end(&significant, opener)?;
+        if end < i {
+            return None;
+        }
+        Some(lsp_utils::to_lsp_position_in(
+            text,
+            &significant[end].span.end,
+        ))
+    };
+
+    let mut nodes: Vec<DocumentSymbol> = collect(text, tokens)
+        .into_iter()
+        .map(|symbol| {
+            // the whole definition line, or the whole definition if it has a body
+            let mut range = line_range(symbol.range.start.line);
+            if let Some(end) = body_end(symbol.range.start) {
+                range.end = end;
+            }
+            #[allow(deprecated)]
+            DocumentSymbol {
+                name: symbol.name.clone(),
+                // first line of the doc comment, if any
+                detail: symbol
+                    .doc
+                    .as_ref()
+                    .and_then(|doc| doc.lines().next())
+                    .map(String::from),
+                kind: symbol.kind,
+                deprecated: None,
+                range,
+                selection_range: symbol.range,
+                children: None,
+            }
+        })
+        .collect();
+
+    for region in regions::regions(text) {
+        #[allow(deprecated)]
+        nodes.push(DocumentSymbol {
+            name: region.name,
+            detail: None,
+            kind: SymbolKind::Namespace,
+            deprecated: None,
+            range: region.range,
+            selection_range: region.marker_range,
+            children: None,
+        });
+    }
+
+    for block in blocks::match_blocks(text, tokens) {
+        let end = match block.end_range {
+            Some(end_range) => end_range.end,
+            None => continue,
+        };
+        let subject = block.of_range.map_or_else(String::new, |of_range| {
+            lsp_utils::text_in_range(text, Range::new(block.match_range.end, of_range.start))
+        });
+        #[allow(deprecated)]
+        nodes.push(DocumentSymbol {
+            name: format!("match {}", subject.trim()),
+            detail: None,
+            kind: SymbolKind::Enum,
+            deprecated: None,
+            range: Range::new(block.match_range.start, end),
+            selection_range: block.match_range,
+            children: None,
+        });
+        for arm in block.arms {
+            #[allow(deprecated)]
+            nodes.push(DocumentSymbol {
+                name: if arm.is_default {
+                    "default".to_string()
+                } else {
+                    format!("case {}", arm.pattern)
+                },
+                detail: None,
+                kind: SymbolKind::EnumMember,
+                deprecated: None,
+                range: arm.range,
+                selection_range: arm.keyword_range,
+                children: None,
+            });
+        }
+    }
+
+    nest(nodes)
+}
+
+// arranges the symbols by their ranges, with each one under the innermost one containing it
+fn nest(mut nodes: Vec<DocumentSymbol>) -> Vec<DocumentSymbol> {
+    // outer ones first, where they start at the same position
+    nodes.sort_by(|a, b| {
+        a.range
+            .start
+            .cmp(&b.range.start)
+            .then_with(|| b.range.end.cmp(&a.range.end))
+    });
+
+    let mut roots = Vec::new();
+    // the current symbol along with the ones containing it
+    let mut stack: Vec<DocumentSymbol> = Vec::new();
+    let close = |stack: &mut Vec<DocumentSymbol>, roots: &mut Vec<DocumentSymbol>| {
+        if let Some(node) = stack.pop() {
+            match stack.last_mut() {
+                Some(parent) => parent.children.get_or_insert_with(Vec::new).push(node),
+                None => roots.push(node),
+            }
+        }
+    };
+    for node in nodes {
+        while stack.last().map_or(false, |top| {
+            node.range.start < top.range.start || top.range.end < node.range.end
+        }) {
+            close(&mut stack, &mut roots);
+        }
+        stack.push(node);
+    }
+    while !stack.is_empty() {
+        close(&mut stack, &mut roots);
+    }
+    roots
+}
+
+// the outline as a flat list, for clients that don't take it as a tree, with the name of the
+// symbol each one is in as its container
+pub fn flatten(uri: &Url, symbols: Vec<DocumentSymbol>) -> Vec<SymbolInformation> {
+    let mut flat = Vec::new();
+    let mut stack: Vec<(DocumentSymbol, Option<String>)> =
+        symbols.into_iter().rev().map(|s| (s, None)).collect();
+    while let Some((mut symbol, container_name)) = stack.pop() {
+        let children = symbol.children.take().unwrap_or_default();
+        stack.extend(
+            children
+                .into_iter()
+                .rev()
+                .map(|child| (child, Some(symbol.name.clone()))),
+        );
+        #[allow(deprecated)]
+        flat.push(SymbolInformation {
+            name: symbol.name,
+            kind: symbol.kind,
+            deprecated: None,
+            location: Location::new(uri.clone(), symbol.range),
+            container_name,
+        });
+    }
+    flat
+}
@@ -0,0 +1,174 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// opentelemetry traces of the LSP requests, with a span per request, sent to an OTLP
+// collector (over http, as json) when `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+//
+// https://opentelemetry.io/docs/specs/otlp/
+
+use crate::metrics::lock;
+use serde_json::{json, Value};
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// spans are sent in batches of (up to) this many, or once this long passed since the last one
+const BATCH_SIZE: usize = 64;
+const BATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+// span kind and status codes, as the protocol has them
+const KIND_SERVER: u64 = 2;
+const STATUS_OK: u64 = 1;
+const STATUS_ERROR: u64 = 2;
+
+// a finished request
+pub struct Span {
+    pub method: String,
+    pub document: Option<String>,
+    pub start: SystemTime,
+    pub duration: Duration,
+    pub failed: bool,
+}
+
+pub struct Exporter {
+    url: String,
+    service_name: String,
+    client: reqwest::Client,
+    spans: Mutex<Vec<Value>>,
+    last_export: Mutex<Instant>,
+    ids: RandomState,
+    next_id: AtomicU64,
+}
+
+impl Exporter {
+    // None unless an endpoint is configured
+    pub fn from_env() -> Option<Self> {
+        let endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .ok()
+            .filter(|endpoint| !endpoint.trim().is_empty())?;
+        Some(Self {
+            url: format!("{}/v1/traces", endpoint.trim().trim_end_matches('/')),
+            service_name: env::var("OTEL_SERVICE_NAME")
+                .unwrap_or_else(|_| env!("CARGO_PKG_NAME").to_string()),
+            client: reqwest::Client::new(),
+            spans: Mutex::new(Vec::new()),
+            last_export: Mutex::new(Instant::now()),
+            ids: RandomState::new(),
+            next_id: AtomicU64::new(0),
+        })
+    }
+
+    // hex id of the given number of random-ish 64 bit words
+    fn id(&self, words: usize) -> String {
+        (0..words)
+            .map(|_| {
+                let mut hasher = self.ids.build_hasher();
+                hasher.write_u64(self.next_id.fetch_add(1, Ordering::Relaxed));
+                format!("{:016x}", hasher.finish())
+            })
+            .collect()
+    }
+
+    // queues the span, sending the queued ones in the background once there are enough
+    pub fn record(&self, span: Span) {
+        let nanos = |time: SystemTime| {
+            time.duration_since(UNIX_EPOCH)
+                .map(|since| since.as_nanos())
+                .unwrap_or(0)
+        };
+        let mut attributes = vec![json!({
+            "key": "rpc.method",
+            "value": { "stringValue": span.method },
+        })];
+        if let Some(document) = &span.document {
+            attributes.push(json!({
+                "key": "document.uri",
+                "value": { "stringValue": document },
+            }));
+        }
+        let span = json!({
+            "traceId": self.id(2),
+            "spanId": self.id(1),
+            "name": span.method,
+            "kind": KIND_SERVER,
+            // numbers this large go as strings in the json encoding
+            "startTimeUnixNano": nanos(span.start).to_string(),
+            "endTimeUnixNano": nanos(span.start + span.duration).to_string(),
+            "attributes": attributes,
+            "status": { "code": if span.failed { STATUS_ERROR } else { STATUS_OK } },
+        });
+
+        let due = {
+            let mut spans = lock(&self.spans);
+            spans.push(span);
+            spans.len() >= BATCH_SIZE || lock(&self.last_export).elapsed() >= BATCH_INTERVAL
+        };
+        if due {
+            // outside of the runtime (eg: on exit), the spans wait for the final flush
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                if let Some(request) = self.take_batch() {
+                    let url = self.url.clone();
+                    runtime.spawn(async move {
+                        if let Err(e) = request.send().await {
+                            eprintln!("Failed to export traces to {}: {}", url, e);
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    // sends the queued spans, waiting for the collector to take them
+    pub async fn flush(&self) {
+        if let Some(request) = self.take_batch() {
+            if let Err(e) = request.send().await {
+                eprintln!("Failed to export traces to {}: {}", self.url, e);
+            }
+        }
+    }
+
+    fn take_batch(&self) -> Option<reqwest::RequestBuilder> {
+        let spans = std::mem::take(&mut *lock(&self.spans));
+        if spans.is_empty() {
+            return None;
+        }
+        *lock(&self.last_export) = Instant::now();
+        let body = json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [{
+                        "key": "service.name",
+                        "value": { "stringValue": self.service_name },
+                    }, {
+                        "key": "service.version",
+                        "value": { "stringValue": env!("CARGO_PKG_VERSION") },
+                    }],
+                },
+                "scopeSpans": [{
+                    "scope": { "name": env!("CARGO_PKG_NAME") },
+                    "spans": spans,
+                }],
+            }],
+        });
+        Some(
+            self.client
+                .post(&self.url)
+                .header("Content-Type", "application/json")
+                .body(body.to_string()),
+        )
+    }
+}
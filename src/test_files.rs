@@ -0,0 +1,420 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// meta files of the tremor test framework (`tremor test`), in the directory of the test
+// they describe: `assert.yaml` with the expected outcome, `before.yaml`/`after.yaml` with
+// processes to run around the test, and `tags.json` with the tags to select tests by.
+//
+// the yaml is looked at line by line (keys and their indentation), which is all these files
+// use, and works on partial documents too.
+
+use crate::fuzzy;
+use crate::lints::{Fix, Lint, INVALID_TEST_FILE};
+use crate::modules::MODULE_FILE_EXTENSION;
+use crate::paths;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Assert,
+    // before.yaml and after.yaml
+    Process,
+    Tags,
+}
+
+// keys along with what they are for
+type Keys = &'static [(&'static str, &'static str)];
+
+const ASSERT_KEYS: Keys = &[
+    ("status", "Expected exit status of the test run"),
+    ("name", "Name of the test"),
+    ("asserts", "Checks on the files that the test produces"),
+];
+
+const ASSERT_ITEM_KEYS: Keys = &[
+    ("source", "File to check, relative to the test directory"),
+    ("contains", "Lines that the file must contain"),
+    ("doesnt_contain", "Lines that the file must not contain"),
+    ("equals_file", "File that the source must be equal to"),
+];
+
+const PROCESS_KEYS: Keys = &[
+    ("cmd", "Command to run"),
+    ("args", "Arguments for the command"),
+    ("dir", "Working directory, relative to the test directory"),
+    ("env", "Environment variables for the command"),
+    (
+        "await",
+        "Conditions to wait for, before going on with the test",
+    ),
+    (
+        "max-await-secs",
+        "Seconds to wait for the conditions at most",
+    ),
+    ("min-await-secs", "Seconds to wait at least"),
+];
+
+const AWAIT_KEYS: Keys = &[
+    ("port-open", "Ports that must accept connections"),
+    ("http-ok", "Urls that must respond with a success status"),
+    ("file-exists", "Files that must exist"),
+    ("wait-for-ms", "Milliseconds to wait for"),
+];
+
+// extensions of the scripts that tests cover
+const SCRIPT_EXTENSIONS: &[&str] = &["trickle", MODULE_FILE_EXTENSION];
+
+pub fn kind(uri: &Url) -> Option<Kind> {
+    let name = uri.path_segments()?.last()?;
+    match name {
+        "assert.yaml" | "assert.yml" => Some(Kind::Assert),
+        "before.yaml" | "before.yml" | "after.yaml" | "after.yml" => Some(Kind::Process),
+        "tags.json" => Some(Kind::Tags),
+        _ => None,
+    }
+}
+
+// keys allowed under the parent key (None for the top level). None if anything goes there.
+fn keys(kind: Kind, parent: Option<&str>) -> Option<Keys> {
+    match (kind, parent) {
+        (Kind::Assert, None) => Some(ASSERT_KEYS),
+        (Kind::Assert, Some("asserts")) => Some(ASSERT_ITEM_KEYS),
+        (Kind::Process, None) => Some(PROCESS_KEYS),
+        (Kind::Process, Some("await")) => Some(AWAIT_KEYS),
+        _ => None,
+    }
+}
+
+// a line of yaml, eg: `  - source: out.log`
+struct Line<'a> {
+    number: u64,
+    // char column of the key (after any `- `), or of the value for lines without a key
+    column: usize,
+    // starts a list item
+    is_item: bool,
+    key: Option<&'a str>,
+    value: &'a str,
+}
+
+fn lines(text: &str) -> Vec<Line> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(number, line)| {
+            let content = line.trim_start();
+            if content.is_empty() || content.starts_with('#') {
+                return None;
+            }
+            let mut column = line.len() - content.len();
+            let is_item = content.starts_with("- ") || content == "-";
+            let content = if is_item {
+                let item = content[1..].trim_start();
+                column += content.len() - item.len();
+                item
+            } else {
+                content
+            };
+            let key_end = content.find(':').filter(|i| {
+                let key = &content[..*i];
+                !key.is_empty()
+                    && key
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            });
+            Some(Line {
+                number: number as u64,
+                column,
+                is_item,
+                key: key_end.map(|i| &content[..i]),
+                value: key_end.map_or(content, |i| content[i + 1..].trim()),
+            })
+        })
+        .collect()
+}
+
+// key that the line at the index is nested in, ie. the closest one above with less indentation
+fn parent<'a>(lines: &[Line<'a>], index: usize) -> Option<&'a str> {
+    let column = lines[index].column;
+    lines[..index]
+        .iter()
+        .rev()
+        .find(|line| line.key.is_some() && line.column < column)
+        .and_then(|line| line.key)
+}
+
+fn range(line: u64, start: usize, end: usize) -> Range {
+    Range::new(
+        Position::new(line, start as u64),
+        Position::new(line, end as u64),
+    )
+}
+
+fn lint(range: Range, severity: DiagnosticSeverity, message: String) -> Lint {
+    Lint {
+        code: INVALID_TEST_FILE,
+        range,
+        severity,
+        message,
+        fixes: vec![],
+    }
+}
+
+pub fn check(kind: Kind, text: &str) -> Vec<Lint> {
+    match kind {
+        Kind::Tags => check_tags(text),
+        Kind::Assert | Kind::Process => check_yaml(kind, text),
+    }
+}
+
+fn check_tags(text: &str) -> Vec<Lint> {
+    match serde_json::from_str::<Value>(text) {
+        Ok(Value::Array(tags)) if tags.iter().all(Value::is_string) => vec![],
+        Ok(_) => vec![lint(
+            range(0, 0, 0),
+            DiagnosticSeverity::Error,
+            "Tags should be a list of strings, eg: `[\"kafka\", \"slow\"]`".to_string(),
+        )],
+        Err(e) => {
+            let line = e.line().saturating_sub(1) as u64;
+            let column = e.column().saturating_sub(1);
+            vec![lint(
+                range(line, column, column + 1),
+                DiagnosticSeverity::Error,
+                format!("Invalid json: {}", e),
+            )]
+        }
+    }
+}
+
+fn check_yaml(kind: Kind, text: &str) -> Vec<Lint> {
+    let mut lints = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let indentation = line.len() - line.trim_start().len();
+        if line[..indentation].contains('\t') {
+            lints.push(lint(
+                range(number as u64, 0, indentation),
+                DiagnosticSeverity::Error,
+                "Tabs are not allowed for indentation in yaml".to_string(),
+            ));
+        }
+    }
+
+    let lines = lines(text);
+    for (i, line) in lines.iter().enumerate() {
+        let key = match line.key {
+            Some(key) => key,
+            None => continue,
+        };
+        let known = match keys(kind, parent(&lines, i)) {
+            Some(known) => known,
+            None => continue,
+        };
+        if known.iter().any(|(k, _)| *k == key) {
+            continue;
+        }
+
+        let key_range = range(line.number, line.column, line.column + key.len());
+        let suggestion = known
+            .iter()
+            .map(|(k, _)| (fuzzy::edit_distance(key, k), *k))
+            .filter(|(distance, _)| *distance <= 2)
+            .min()
+            .map(|(_, k)| k);
+        let mut lint = lint(
+            key_range,
+            DiagnosticSeverity::Warning,
+            format!(
+                "Unknown key `{}`, expected one of: {}",
+                key,
+                known.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(", ")
+            ),
+        );
+        if let Some(suggestion) = suggestion {
+            lint.fixes.push(Fix {
+                title: format!("Change to `{}`", suggestion),
+                edits: vec![TextEdit::new(key_range, suggestion.to_string())],
+            });
+        }
+        lints.push(lint);
+    }
+
+    if kind == Kind::Assert {
+        lints.extend(missing_assert_keys(&lines));
+    }
+    lints
+}
+
+// required keys of assert files: all of the top level ones, and a source to check along with
+// at least one check for each item of `asserts`
+fn missing_assert_keys(lines: &[Line]) -> Vec<Lint> {
+    let mut lints = Vec::new();
+
+    let top_level: Vec<&str> = lines
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| parent(lines, *i).is_none())
+        .filter_map(|(_, line)| line.key)
+        .collect();
+    let missing: Vec<&str> = ASSERT_KEYS
+        .iter()
+        .map(|(k, _)| *k)
+        .filter(|k| !top_level.contains(k))
+        .collect();
+    if !missing.is_empty() {
+        lints.push(lint(
+            range(lines.first().map_or(0, |line| line.number), 0, 0),
+            DiagnosticSeverity::Error,
+            format!("Missing `{}`", missing.join("`, `")),
+        ));
+    }
+
+    // items of `asserts`, as the index of their first line along with the keys they have
+    let mut items: Vec<(usize, Vec<&str>)> = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if parent(lines, i) != Some("asserts") {
+            continue;
+        }
+        match (line.is_item, items.last_mut()) {
+            (false, Some((_, keys))) => keys.extend(line.key),
+            _ => items.push((i, line.key.into_iter().collect())),
+        }
+    }
+    for (i, keys) in items {
+        let line = &lines[i];
+        let item_range = range(
+            line.number,
+            line.column,
+            line.column + line.key.unwrap_or(line.value).len(),
+        );
+        if !keys.contains(&"source") {
+            lints.push(lint(
+                item_range,
+                DiagnosticSeverity::Error,
+                "Missing `source`, the file to check".to_string(),
+            ));
+        }
+        if !keys
+            .iter()
+            .any(|k| matches!(*k, "contains" | "doesnt_contain" | "equals_file"))
+        {
+            lints.push(lint(
+                item_range,
+                DiagnosticSeverity::Error,
+                "Missing a check: `contains`, `doesnt_contain` or `equals_file`".to_string(),
+            ));
+        }
+    }
+
+    lints
+}
+
+// keys for the position, if it's where a key goes (start of a line, possibly partially typed)
+pub fn completions(kind: Kind, text: &str, position: Position) -> Vec<CompletionItem> {
+    let line = text.lines().nth(position.line as usize).unwrap_or_default();
+    let before: String = line.chars().take(position.character as usize).collect();
+    let typed = before.trim_start().trim_start_matches('-').trim_start();
+    if kind == Kind::Tags
+        || !typed
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return vec![];
+    }
+
+    // the parent is found the same way as for the existing lines, with the position as a line
+    let column = before.chars().count() - typed.chars().count();
+    let mut lines: Vec<Line> = lines(text)
+        .into_iter()
+        .filter(|line| line.number < position.line as u64)
+        .collect();
+    lines.push(Line {
+        number: position.line as u64,
+        column,
+        is_item: false,
+        key: None,
+        value: typed,
+    });
+    let known = match keys(kind, parent(&lines, lines.len() - 1)) {
+        Some(known) => known,
+        None => return vec![],
+    };
+
+    known
+        .iter()
+        .map(|(key, description)| CompletionItem {
+            label: key.to_string(),
+            kind: Some(CompletionItemKind::Property),
+            detail: Some(description.to_string()),
+            insert_text: Some(format!("{}: ", key)),
+            ..CompletionItem::default()
+        })
+        .collect()
+}
+
+// links for values naming files next to the test file, and from the test name to the
+// script of the test, if there is just one in its directory
+pub fn links(uri: &Url, text: &str) -> Vec<DocumentLink> {
+    let dir = match paths::to_path(uri) {
+        Some(path) => match path.parent() {
+            Some(dir) => dir.to_path_buf(),
+            None => return vec![],
+        },
+        None => return vec![],
+    };
+    let link = |line: &Line, target: &Path, tooltip: String| {
+        let line_text = text.lines().nth(line.number as usize).unwrap_or_default();
+        let start = line_text
+            .rfind(line.value)
+            .map_or(line.column, |offset| line_text[..offset].chars().count());
+        Some(DocumentLink {
+            range: range(line.number, start, start + line.value.chars().count()),
+            target: paths::to_uri(target),
+            tooltip: Some(tooltip),
+            data: None,
+        })
+    };
+
+    let scripts: Vec<PathBuf> = fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.extension()
+                        .and_then(|e| e.to_str())
+                        .map_or(false, |e| SCRIPT_EXTENSIONS.contains(&e))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    lines(text)
+        .iter()
+        .filter_map(|line| {
+            let value = line.value.trim_matches(|c| c == '"' || c == '\'');
+            if value.is_empty() {
+                return None;
+            }
+            match (line.key, scripts.as_slice()) {
+                (Some("name"), [script]) => link(line, script, "Script of the test".to_string()),
+                _ if dir.join(value).is_file() => {
+                    link(line, &dir.join(value), format!("Open {}", value))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
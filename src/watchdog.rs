@@ -0,0 +1,85 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// exits the server once the editor that started it is gone (the `processId` of the initialize
+// request), as editors that crash leave their servers running otherwise
+
+use std::thread;
+use std::time::Duration;
+
+const INTERVAL: Duration = Duration::from_secs(5);
+
+// checks on the process in the background, for as long as the server runs
+pub fn watch(pid: u64) {
+    if pid == u64::from(std::process::id()) {
+        return;
+    }
+    let spawned = thread::Builder::new()
+        .name("watchdog".to_string())
+        .spawn(move || loop {
+            thread::sleep(INTERVAL);
+            if is_running(pid) == Some(false) {
+                eprintln!("Exiting: the parent process ({}) is gone", pid);
+                // as for an exit without a shutdown request
+                std::process::exit(1)
+            }
+        });
+    if let Err(e) = spawned {
+        eprintln!("Warning: failed to watch the parent process: {}", e);
+    }
+}
+
+// None if we can't tell, in which case the server keeps running
+#[cfg(target_os = "linux")]
+fn is_running(pid: u64) -> Option<bool> {
+    Some(std::path::Path::new(&format!("/proc/{}", pid)).exists())
+}
+
+// signal 0 only checks that the process exists (and that we may signal it, which we may for
+// our parent)
+#[cfg(all(unix, not(target_os = "linux")))]
+fn is_running(pid: u64) -> Option<bool> {
+    use std::process::{Command, Stdio};
+    Command::new("kill")
+        .args(&["-0", &pid.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .ok()
+        .map(|status| status.success())
+}
+
+#[cfg(windows)]
+fn is_running(pid: u64) -> Option<bool> {
+    use std::process::{Command, Stdio};
+    let output = Command::new("tasklist")
+        .args(&["/FI", &format!("PID eq {}", pid), "/FO", "CSV", "/NH"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let pid = format!("\"{}\"", pid);
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .any(|line| line.split(',').nth(1) == Some(pid.as_str())),
+    )
+}
+
+#[cfg(not(any(unix, windows)))]
+fn is_running(_pid: u64) -> Option<bool> {
+    None
+}
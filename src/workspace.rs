@@ -0,0 +1,525 @@
+// Copyright 2018-2020, Wayfair GmbH
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::cache::{self, IndexCache};
+use crate::config::Config;
+use crate::fuzzy;
+use crate::language::Tokenizer;
+use crate::modules;
+use crate::paths;
+use crate::symbols::{self, Symbol};
+use halfbrown::HashMap;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use tower_lsp::lsp_types::{Location, SymbolInformation, SymbolKind, Url};
+
+// extensions of the files we care about, across all the tremor languages
+pub const FILE_EXTENSIONS: &[&str] = &["tremor", "trickle"];
+
+// directories not worth indexing, whatever the settings: build output, vendored dependencies
+// and version control data
+const EXCLUDED_DIRS: &[&str] = &["target", "node_modules", ".git"];
+
+// kinds that symbol queries can be narrowed down to, as in `fn:parse`
+const QUERY_KINDS: &[(&str, SymbolKind)] = &[
+    ("fn", SymbolKind::Function),
+    ("const", SymbolKind::Constant),
+    ("mod", SymbolKind::Module),
+    ("window", SymbolKind::Struct),
+    ("operator", SymbolKind::Operator),
+    ("stream", SymbolKind::Event),
+    ("script", SymbolKind::Class),
+];
+
+// what we know about a (not necessarily open) file in the workspace
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct FileIndex {
+    pub symbols: Vec<Symbol>,
+}
+
+// index of the tremor files across the workspace folders
+#[derive(Debug, Default)]
+pub struct Workspace {
+    pub roots: Vec<PathBuf>,
+    pub files: HashMap<Url, FileIndex>,
+    pub cache: IndexCache,
+    // number of files that were indexed from the cache
+    pub cache_hits: usize,
+    // and from their content
+    pub cache_misses: usize,
+}
+
+impl Workspace {
+    // reuses the cached index for the file if its content did not change since
+    pub fn index_file(&mut self, path: &Path) {
+        if let (Some(uri), Ok(text)) = (paths::to_uri(path), fs::read_to_string(path)) {
+            let content_hash = cache::content_hash(&text);
+            let index = match self.cache.get(path, content_hash) {
+                Some(index) => {
+                    self.cache_hits += 1;
+                    index.clone()
+                }
+                None => {
+                    self.cache_misses += 1;
+                    let index = index_text(&text);
+                    self.cache.insert(path, content_hash, index.clone());
+                    index
+                }
+            };
+            self.files.insert(uri, index);
+        }
+    }
+
+    pub fn load_cache(&mut self) {
+        self.cache = IndexCache::load(&self.roots);
+        self.cache_hits = 0;
+        self.cache_misses = 0;
+    }
+
+    // the indexed files of the language (by its file extension), in order. the files of the
+    // other language are left to its server.
+    pub fn files_of_language(
+        &self,
+        extension: &str,
+        config_for: impl Fn(&Url) -> Config,
+    ) -> Vec<Url> {
+        let mut uris: Vec<Url> = self
+            .files
+            .keys()
+            .filter(|uri| match paths::to_path(uri) {
+                Some(path) => {
+                    file_language(&path, &self.roots, &config_for(uri)) == Some(extension)
+                }
+                None => false,
+            })
+            .cloned()
+            .collect();
+        uris.sort();
+        uris
+    }
+
+    // symbols of the files (with their score) that match the query
+    pub fn matches(&self, query: &SymbolQuery, uris: &[Url]) -> Vec<(i64, SymbolInformation)> {
+        let mut matches = Vec::new();
+        for (uri, file) in uris
+            .iter()
+            .filter_map(|uri| self.files.get(uri).map(|file| (uri, file)))
+        {
+            // module path of the file, eg: `lib::strings`
+            let container_name = paths::to_path(uri)
+                .and_then(|path| modules::module_path(uri, &path))
+                .map(|path| path.join("::"));
+            for symbol in &file.symbols {
+                if query.kind.map_or(false, |kind| kind != symbol.kind) {
+                    continue;
+                }
+                let score = if query.pattern.contains('*') {
+                    Some(0).filter(|_| glob_matches(&query.pattern, &symbol.name))
+                } else {
+                    fuzzy::score(&query.pattern, &symbol.name)
+                };
+                if let Some(score) = score {
+                    #[allow(deprecated)]
+                    matches.push((
+                        score,
+                        SymbolInformation {
+                            name: symbol.name.clone(),
+                            kind: symbol.kind,
+                            deprecated: None,
+                            location: Location::new(uri.clone(), symbol.range),
+                            container_name: container_name.clone(),
+                        },
+                    ));
+                }
+            }
+        }
+        matches
+    }
+
+    pub fn save_cache(&mut self, files: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+        self.cache.retain(files);
+        self.cache.save(&self.roots)
+    }
+}
+
+// a workspace symbol query: a name pattern, optionally preceded by a kind (eg: `fn:parse`).
+// patterns with a `*` are globs (eg: `window:*15s*`), others match fuzzily.
+pub struct SymbolQuery {
+    kind: Option<SymbolKind>,
+    pattern: String,
+}
+
+impl SymbolQuery {
+    pub fn parse(query: &str) -> Self {
+        let (kind, pattern) = match query.splitn(2, ':').collect::<Vec<_>>().as_slice() {
+            [prefix, pattern] if !pattern.starts_with(':') => {
+                match QUERY_KINDS.iter().find(|(name, _)| name == prefix) {
+                    Some((_, kind)) => (Some(*kind), pattern.trim()),
+                    None => (None, query.trim()),
+                }
+            }
+            _ => (None, query.trim()),
+        };
+        Self {
+            kind,
+            pattern: pattern.to_string(),
+        }
+    }
+}
+
+// the matched symbols, best matches first
+pub fn rank(mut matches: Vec<(i64, SymbolInformation)>) -> Vec<SymbolInformation> {
+    matches.sort_by(|(a_score, a), (b_score, b)| {
+        b_score
+            .cmp(a_score)
+            .then_with(|| a.name.cmp(&b.name))
+            .then_with(|| a.location.uri.cmp(&b.location.uri))
+    });
+    matches.into_iter().map(|(_, symbol)| symbol).collect()
+}
+
+pub fn index_text(text: &str) -> FileIndex {
+    match Tokenizer::new(text).collect::<Result<Vec<_>, _>>() {
+        Ok(tokens) => FileIndex {
+            symbols: symbols::collect(text, &tokens),
+        },
+        Err(_e) => FileIndex::default(),
+    }
+}
+
+// case-insensitive match of the name against a pattern where `*` stands for any text
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    // matched[j]: whether the pattern so far matches the first j chars of the name
+    let mut matched = vec![false; name.len() + 1];
+    matched[0] = true;
+    for p in &pattern {
+        if *p == '*' {
+            for j in 1..=name.len() {
+                matched[j] = matched[j] || matched[j - 1];
+            }
+        } else {
+            for j in (1..=name.len()).rev() {
+                matched[j] = matched[j - 1] && name[j - 1] == *p;
+            }
+            matched[0] = false;
+        }
+    }
+    matched[name.len()]
+}
+
+// file extension of the language of the file: the one it's mapped to by the fileLanguages
+// setting, or else the one of its extension. None for other files.
+pub fn file_language(path: &Path, roots: &[PathBuf], config: &Config) -> Option<&'static str> {
+    let relative = roots
+        .iter()
+        .find_map(|root| path.strip_prefix(root).ok())
+        .unwrap_or(path);
+    let extension = path.extension().and_then(|e| e.to_str());
+    config.language_for(relative).or_else(|| {
+        FILE_EXTENSIONS
+            .iter()
+            .copied()
+            .find(|e| Some(*e) == extension)
+    })
+}
+
+pub fn is_tremor_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map_or(false, |e| FILE_EXTENSIONS.contains(&e))
+}
+
+// patterns of the paths to skip under the root: the default ones, the configured ones and the
+// ones of the .gitignore of the root. as in .gitignore, patterns without a `/` match names
+// anywhere (eg: `*.gen.tremor`) and others paths from the root (eg: `vendor/**/generated`),
+// with `*` matching within a directory and `**` across them.
+pub fn exclusions(root: &Path, configured: &[String]) -> Vec<String> {
+    let gitignore = fs::read_to_string(root.join(".gitignore")).unwrap_or_default();
+    let ignored = gitignore
+        .lines()
+        .map(str::trim)
+        // negations are not supported, so what they bring back stays excluded
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'))
+        .map(String::from);
+    EXCLUDED_DIRS
+        .iter()
+        .map(|dir| dir.to_string())
+        .chain(configured.iter().cloned())
+        .chain(ignored)
+        .collect()
+}
+
+// whether the path (relative to the root) or a directory it is in is excluded by the patterns
+pub fn is_excluded(relative: &Path, patterns: &[String]) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| matches_pattern(relative, pattern))
+}
+
+// whether the path (relative to the root) or a directory it is in matches the pattern
+pub fn matches_pattern(relative: &Path, pattern: &str) -> bool {
+    let names: Vec<String> = relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+    let pattern: Vec<char> = pattern.trim_end_matches('/').chars().collect();
+    if pattern.contains(&'/') {
+        let pattern = match pattern.as_slice() {
+            ['/', rest @ ..] => rest,
+            all => all,
+        };
+        (1..=names.len()).any(|n| {
+            let path: Vec<char> = names[..n].join("/").chars().collect();
+            path_matches(pattern, &path)
+        })
+    } else {
+        names.iter().any(|name| {
+            let name: Vec<char> = name.chars().collect();
+            path_matches(&pattern, &name)
+        })
+    }
+}
+
+fn path_matches(pattern: &[char], path: &[char]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        // `**/` matches whole directories (or none), a `**` at the end everything
+        ['*', '*', '/', rest @ ..] => (0..=path.len())
+            .filter(|i| *i == 0 || path[i - 1] == '/')
+            .any(|i| path_matches(rest, &path[i..])),
+        ['*', '*', rest @ ..] => (0..=path.len()).any(|i| path_matches(rest, &path[i..])),
+        ['*', rest @ ..] => (0..=path.len())
+            .take_while(|i| *i == 0 || path[i - 1] != '/')
+            .any(|i| path_matches(rest, &path[i..])),
+        ['?', rest @ ..] => {
+            path.first().map_or(false, |c| *c != '/') && path_matches(rest, &path[1..])
+        }
+        [c, rest @ ..] => path.first() == Some(c) && path_matches(rest, &path[1..]),
+    }
+}
+
+// all the files under the directory that pass the filter, recursively, but for the excluded
+// ones (see is_excluded)
+pub fn find_files_with(
+    dir: &Path,
+    exclude: &[String],
+    filter: impl Fn(&Path) -> bool,
+) -> Vec<PathBuf> {
+    let root = dir;
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            // unreadable directories are not fatal for indexing
+            Err(_e) => continue,
+        };
+        for entry in entries.filter_map(std::result::Result::ok) {
+            let path = entry.path();
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            if is_excluded(relative, exclude) {
+                continue;
+            }
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(_e) => continue,
+            };
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if file_type.is_symlink() && path.is_dir() {
+                // not followed, as links back up the tree would make the walk endless
+                continue;
+            } else if filter(&path) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uris(paths: &[&str]) -> Vec<Url> {
+        paths
+            .iter()
+            .filter_map(|path| paths::to_uri(Path::new(path)))
+            .collect()
+    }
+
+    #[test]
+    fn glob_stars_at_either_end() {
+        assert!(glob_matches("*15s", "window_15s"));
+        assert!(!glob_matches("*15s", "window_15s_b"));
+        assert!(glob_matches("parse*", "parse_json"));
+        assert!(!glob_matches("parse*", "json_parse"));
+        assert!(glob_matches("*15s*", "a15sb"));
+        assert!(glob_matches("a*c", "abbc"));
+        assert!(!glob_matches("a*c", "abcd"));
+    }
+
+    #[test]
+    fn glob_of_only_stars() {
+        assert!(glob_matches("*", "anything"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("**", "x"));
+    }
+
+    #[test]
+    fn glob_ignores_case() {
+        assert!(glob_matches("Parse*", "parseJSON"));
+        assert!(glob_matches("*json", "parseJSON"));
+    }
+
+    #[test]
+    fn query_kind_prefixes() {
+        let query = SymbolQuery::parse("fn:parse");
+        assert_eq!(query.kind, Some(SymbolKind::Function));
+        assert_eq!(query.pattern, "parse");
+
+        let query = SymbolQuery::parse("window: *15s* ");
+        assert_eq!(query.kind, Some(SymbolKind::Struct));
+        assert_eq!(query.pattern, "*15s*");
+    }
+
+    #[test]
+    fn query_unknown_prefix_stays_fuzzy() {
+        let query = SymbolQuery::parse("foo:bar");
+        assert_eq!(query.kind, None);
+        assert_eq!(query.pattern, "foo:bar");
+    }
+
+    #[test]
+    fn query_module_path_is_no_prefix() {
+        let query = SymbolQuery::parse("fn::x");
+        assert_eq!(query.kind, None);
+        assert_eq!(query.pattern, "fn::x");
+    }
+
+    fn excluded(relative: &str, pattern: &str) -> bool {
+        matches_pattern(Path::new(relative), pattern)
+    }
+
+    #[test]
+    fn pattern_double_star_in_the_middle() {
+        assert!(excluded("vendor/generated/a.tremor", "vendor/**/generated"));
+        assert!(excluded(
+            "vendor/x/y/generated/a.tremor",
+            "vendor/**/generated"
+        ));
+        // whole directories only
+        assert!(!excluded(
+            "vendor/xgenerated/a.tremor",
+            "vendor/**/generated"
+        ));
+        assert!(!excluded(
+            "lib/vendor/generated/a.tremor",
+            "vendor/**/generated"
+        ));
+    }
+
+    #[test]
+    fn pattern_double_star_at_the_end() {
+        assert!(excluded("vendor/a.tremor", "vendor/**"));
+        assert!(excluded("vendor/x/y/a.tremor", "vendor/**"));
+        assert!(!excluded("vendored/a.tremor", "vendor/**"));
+        assert!(excluded("x/y/a.gen.tremor", "**/*.gen.tremor"));
+    }
+
+    #[test]
+    fn pattern_anchored_at_the_root() {
+        assert!(excluded("build/a.tremor", "/build"));
+        assert!(!excluded("lib/build/a.tremor", "/build"));
+        // without a `/`, names match anywhere
+        assert!(excluded("lib/build/a.tremor", "build"));
+    }
+
+    #[test]
+    fn pattern_of_directories() {
+        assert!(excluded("out/a.tremor", "out/"));
+        assert!(excluded("lib/out/a.tremor", "out/"));
+        assert!(!excluded("output/a.tremor", "out/"));
+    }
+
+    #[test]
+    fn pattern_question_mark() {
+        assert!(excluded("tmp1/a.tremor", "tmp?"));
+        assert!(!excluded("tmp/a.tremor", "tmp?"));
+        assert!(!excluded("tmp12/a.tremor", "tmp?"));
+        assert!(!excluded("a/b.tremor", "a?b.tremor"));
+    }
+
+    #[test]
+    fn pattern_star_stays_within_directories() {
+        assert!(excluded("gen/a.tremor", "gen/*.tremor"));
+        assert!(!excluded("gen/x/a.tremor", "gen/*.tremor"));
+        assert!(excluded("lib/a.gen.tremor", "*.gen.tremor"));
+        assert!(!excluded("a/b", "a*b"));
+    }
+
+    #[test]
+    fn gitignore_negations_are_skipped() {
+        let root = std::env::temp_dir().join("tremor-language-server-test-exclusions");
+        let written = fs::create_dir_all(&root)
+            .and_then(|_| fs::write(root.join(".gitignore"), "gen\n!gen/keep\n# c\n\n"));
+        assert!(written.is_ok());
+        let patterns = exclusions(&root, &["vendor".to_string()]);
+        let _ = fs::remove_dir_all(&root);
+        assert!(patterns.contains(&"vendor".to_string()));
+        assert!(patterns.contains(&"gen".to_string()));
+        assert!(!patterns
+            .iter()
+            .any(|pattern| pattern.starts_with('!') || pattern.starts_with('#')));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn files_of_language_in_mixed_workspace() {
+        let mut workspace = Workspace {
+            roots: vec![PathBuf::from("/ws")],
+            ..Workspace::default()
+        };
+        for uri in uris(&[
+            "/ws/b.trickle",
+            "/ws/a.tremor",
+            "/ws/gen/q.tq",
+            "/ws/lib/c.tremor",
+        ]) {
+            workspace.files.insert(uri, index_text(""));
+        }
+        let config = Config {
+            file_languages: vec![("*.tq".to_string(), "trickle")],
+            ..Config::default()
+        };
+
+        assert_eq!(
+            workspace.files_of_language("tremor", |_| config.clone()),
+            uris(&["/ws/a.tremor", "/ws/lib/c.tremor"])
+        );
+        assert_eq!(
+            workspace.files_of_language("trickle", |_| config.clone()),
+            uris(&["/ws/b.trickle", "/ws/gen/q.tq"])
+        );
+    }
+}